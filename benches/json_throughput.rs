@@ -0,0 +1,39 @@
+/*!
+ * Throughput of JSON output for the nested schema in test_schema_with_record.txt,
+ * measured end to end by running the binary and reading its stdout.
+ *
+ * Run with `cargo bench --bench json_throughput`
+ */
+use std::io;
+use std::process::{Command, Stdio};
+use std::time::Instant;
+
+const RECORDS: u64 = 200_000;
+
+fn main() {
+    let schema = concat!(env!("CARGO_MANIFEST_DIR"), "/test_schema_with_record.txt");
+    for style in &["ndjson", "array", "pretty"] {
+        let records = RECORDS.to_string();
+        let started = Instant::now();
+        let mut child = Command::new(env!("CARGO_BIN_EXE_datablaster"))
+            .args(["-s", schema, "-f", "json", "--json-style", style])
+            .args(["-r", records.as_str(), "-"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("failed to run datablaster");
+        let bytes = io::copy(&mut child.stdout.take().unwrap(), &mut io::sink())
+            .expect("failed to read output");
+        let status = child.wait().expect("failed to wait for datablaster");
+        assert!(status.success(), "datablaster failed with {}", status);
+        let seconds = started.elapsed().as_secs_f64();
+        println!(
+            "json {:<6} {:>8.1} MB/s {:>10.0} records/s ({} bytes in {:.2}s)",
+            style,
+            bytes as f64 / 1_000_000.0 / seconds,
+            RECORDS as f64 / seconds,
+            bytes,
+            seconds
+        );
+    }
+}
@@ -1,11 +1,39 @@
 extern crate clap;
 use clap::{App, Arg, ArgMatches};
+use std::time::Duration;
 
 pub const RECORDS_TO_CREATE: &str = "COUNT";
+pub const SIZE: &str = "SIZE";
+pub const DURATION: &str = "DURATION";
+pub const MAX_RECORDS_PER_FILE: &str = "MAX_RECORDS_PER_FILE";
+pub const MAX_BYTES_PER_FILE: &str = "MAX_BYTES_PER_FILE";
+pub const PARTITION_BY: &str = "PARTITION_BY";
+pub const COMPRESS: &str = "COMPRESS";
+pub const COMPRESSION_LEVEL: &str = "COMPRESSION_LEVEL";
+pub const MAX_OPEN_PARTITIONS: &str = "MAX_OPEN_PARTITIONS";
 pub const OUTPUT_FILE: &str = "OUTPUT_FILE";
 pub const FORMAT: &str = "FILE_FORMAT";
 pub const SCHEMA: &str = "SCHEMA_FILE";
 pub const VERBOSE: &str = "VERBOSE";
+pub const BINARY_ENCODING: &str = "BINARY_ENCODING";
+pub const JSON_STYLE: &str = "JSON_STYLE";
+pub const SQL_DIALECT: &str = "SQL_DIALECT";
+pub const YAML_STYLE: &str = "YAML_STYLE";
+pub const FRAMING: &str = "FRAMING";
+pub const MESSAGE: &str = "MESSAGE";
+pub const XML_ROOT: &str = "XML_ROOT";
+pub const XML_ROW: &str = "XML_ROW";
+pub const XML_ITEM: &str = "XML_ITEM";
+pub const XML_ATTRIBUTES: &str = "XML_ATTRIBUTES";
+pub const BATCH_SIZE: &str = "BATCH_SIZE";
+pub const CREATE_TABLE: &str = "CREATE_TABLE";
+pub const MAX_LATENESS: &str = "MAX_LATENESS";
+pub const EVENT_TIME_FIELD: &str = "EVENT_TIME_FIELD";
+pub const MODE: &str = "MODE";
+pub const CDC_RATIOS: &str = "CDC_RATIOS";
+pub const KEY_FIELD: &str = "KEY_FIELD";
+pub const SCD_MUTABLE_FIELDS: &str = "SCD_MUTABLE_FIELDS";
+pub const SCD_MAX_VERSIONS: &str = "SCD_MAX_VERSIONS";
 
 pub fn parse_args<'a>() -> ArgMatches<'a> {
     let matches = App::new("Data Blaster")
@@ -14,7 +42,19 @@ pub fn parse_args<'a>() -> ArgMatches<'a> {
             Arg::with_name(RECORDS_TO_CREATE)
                 .short("r")
                 .long("records")
-                .help("Number of records to create")
+                .help("Number of records to create (entities in scd2 mode). Defaults to 10 when no --size or --duration is given")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(SIZE)
+                .long("size")
+                .help("Stop once this much output has been written (e.g. 500MB, 5GiB). Rows buffered for a batch or stripe count as written. Compressed output counts the bytes before compression")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(DURATION)
+                .long("duration")
+                .help("Stop once this much time has passed (e.g. 90s, 10m)")
                 .takes_value(true),
         )
         .arg(
@@ -22,7 +62,7 @@ pub fn parse_args<'a>() -> ArgMatches<'a> {
                 .short("f")
                 .long("format")
                 .help("The output file format")
-                .possible_values(&["csv", "json"])
+                .possible_values(&["csv", "json", "sql", "pgcopy", "pgcopy-binary", "sqlite", "arrow", "arrow-stream", "xml", "yaml", "toml", "msgpack", "cbor", "protobuf", "orc", "fixed-width"])
                 .takes_value(true)
                 .required(true),
         )
@@ -30,10 +70,175 @@ pub fn parse_args<'a>() -> ArgMatches<'a> {
             Arg::with_name(SCHEMA)
                 .short("s")
                 .long("schema")
-                .help("Schema File")
+                .help("Schema File, or a .proto file to generate one of its messages")
                 .takes_value(true)
                 .required(true),
         )
+        .arg(
+            Arg::with_name(MESSAGE)
+                .long("message")
+                .help("Message to generate from a .proto schema file. Needed when it defines more than one top level message")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(MAX_RECORDS_PER_FILE)
+                .long("max-records-per-file")
+                .help("Start a new output file after this many records")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(MAX_BYTES_PER_FILE)
+                .long("max-bytes-per-file")
+                .help("Start a new output file once a file reaches this size (e.g. 128MiB). Rows buffered for a batch or stripe count as written. Compressed files count the bytes before compression")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(COMPRESS)
+                .long("compress")
+                .help("Compress output files. Defaults to the OUTPUT_FILE extension (.gz, .zst or .bz2). orc compresses the streams inside the file instead, with zlib for gzip")
+                .possible_values(&["none", "gzip", "zstd", "bz2"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(COMPRESSION_LEVEL)
+                .long("compression-level")
+                .help("gzip 0-9, zstd 1-22 or bz2 1-9")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(PARTITION_BY)
+                .long("partition-by")
+                .help("Comma separated fields to split output into field=value directories by. OUTPUT_FILE is then the base directory")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(MAX_OPEN_PARTITIONS)
+                .long("max-open-partitions")
+                .help("Most partition files kept open at once")
+                .default_value("64")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(MODE)
+                .long("mode")
+                .help("insert writes new rows, cdc writes a stream of change events and scd2 writes the version history of each row")
+                .possible_values(&["insert", "cdc", "scd2"])
+                .default_value("insert")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(CDC_RATIOS)
+                .long("cdc-ratios")
+                .help("Relative amount of inserts, updates and deletes in cdc mode")
+                .default_value("60:30:10")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(KEY_FIELD)
+                .long("key")
+                .help("Field that identifies a row in cdc and scd2 mode. Defaults to the first field")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(SCD_MUTABLE_FIELDS)
+                .long("scd-mutable")
+                .help("Comma separated fields that change between versions in scd2 mode. Defaults to all but the key")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(SCD_MAX_VERSIONS)
+                .long("scd-max-versions")
+                .help("Most versions generated per entity in scd2 mode")
+                .default_value("5")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(BINARY_ENCODING)
+                .long("binary-encoding")
+                .help("How byte fields are rendered in text formats")
+                .possible_values(&["hex", "base64"])
+                .default_value("hex")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(JSON_STYLE)
+                .long("json-style")
+                .help("One object per line, or a single array of objects, compact or indented")
+                .possible_values(&["ndjson", "array", "pretty"])
+                .default_value("ndjson")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(YAML_STYLE)
+                .long("yaml-style")
+                .help("A YAML document per record, or a single document with a sequence of records")
+                .possible_values(&["documents", "sequence"])
+                .default_value("documents")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(FRAMING)
+                .long("framing")
+                .help("How msgpack, cbor and protobuf records are separated: back to back, or each prefixed with its length as a 4 byte big-endian integer or a varint. Defaults to sequence, or varint-delimited for protobuf")
+                .possible_values(&["sequence", "length-delimited", "varint-delimited"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(XML_ROOT)
+                .long("xml-root")
+                .help("Root element of xml output. Defaults to the table name")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(XML_ROW)
+                .long("xml-row")
+                .help("Element each record is written as in xml output. Defaults to the table name")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(XML_ITEM)
+                .long("xml-item")
+                .help("Element each list item is written as in xml output")
+                .default_value("item")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(XML_ATTRIBUTES)
+                .long("xml-attributes")
+                .help("Write scalar fields as attributes instead of child elements in xml output"),
+        )
+        .arg(
+            Arg::with_name(SQL_DIALECT)
+                .long("sql-dialect")
+                .help("Quoting, literals and column types used by the sql format")
+                .possible_values(&["postgres", "mysql", "sqlite"])
+                .default_value("postgres")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(BATCH_SIZE)
+                .long("batch-size")
+                .help("Rows per INSERT statement (sql), transaction (sqlite), record batch (arrow) or stripe (orc). Defaults to 100, 100, 65536 and 64MiB stripes")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(CREATE_TABLE)
+                .long("create-table")
+                .help("Start sql output with a CREATE TABLE statement for the schema"),
+        )
+        .arg(
+            Arg::with_name(MAX_LATENESS)
+                .long("max-lateness")
+                .help("Emit records out of order, up to this long after their event time (e.g. 30s)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(EVENT_TIME_FIELD)
+                .long("event-time-field")
+                .help("TIMESTAMP field used with --max-lateness. Defaults to the first TIMESTAMP field")
+                .takes_value(true)
+                .requires(MAX_LATENESS),
+        )
         .arg(
             Arg::with_name(VERBOSE)
                 .short("v")
@@ -42,9 +247,104 @@ pub fn parse_args<'a>() -> ArgMatches<'a> {
         )
         .arg(
             Arg::with_name(OUTPUT_FILE)
-                .help("Output file path")
+                .help("Output file path, or - for stdout. When splitting output, a {part} or {part:05} placeholder sets where the file number goes")
                 .required(true),
         )
         .get_matches();
     matches
 }
+
+/// Parses counts such as `--records`, which have to be at least 1. `what` names the
+/// count in errors
+pub fn parse_count(count: &str, what: &str) -> Result<u64, String> {
+    match count.trim().parse::<u64>() {
+        Ok(0) => Err(format!("The {} must be at least 1", what)),
+        Ok(count) => Ok(count),
+        Err(_) => Err(format!("Invalid {}: {}", what, count)),
+    }
+}
+
+/// Parses durations like `30s` or `1h 30m`, which have to be longer than zero
+pub fn parse_duration(duration: &str) -> Result<Duration, String> {
+    match humantime::parse_duration(duration) {
+        Ok(d) if d.is_zero() => Err(format!("Duration {} must be longer than zero", duration)),
+        Ok(d) => Ok(d),
+        Err(e) => Err(format!("Invalid duration {} - {}", duration, e)),
+    }
+}
+
+/// Parses sizes like `1024`, `500MB` or `5GiB`. KB, MB, ... are powers of 1000 and KiB, MiB, ... powers of 1024
+pub fn parse_byte_size(size: &str) -> Result<u64, String> {
+    let size = size.trim();
+    let unit_start = size
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(unit_start);
+    let number = number
+        .parse::<f64>()
+        .map_err(|_| format!("Invalid size: {}", size))?;
+    let multiplier: u64 = match unit.trim().to_lowercase().as_str() {
+        "" | "b" => 1,
+        "kb" => 1_000,
+        "mb" => 1_000_000,
+        "gb" => 1_000_000_000,
+        "tb" => 1_000_000_000_000,
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        "tib" => 1 << 40,
+        _ => return Err(format!("Invalid size unit: {}", unit)),
+    };
+    Ok((number * multiplier as f64) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn byte_sizes() {
+        assert_eq!(parse_byte_size("1024"), Ok(1024));
+        assert_eq!(parse_byte_size("10b"), Ok(10));
+        assert_eq!(parse_byte_size("500KB"), Ok(500_000));
+        assert_eq!(parse_byte_size("500KiB"), Ok(500 * 1024));
+        assert_eq!(parse_byte_size("2mb"), Ok(2_000_000));
+        assert_eq!(parse_byte_size("2 MiB"), Ok(2 << 20));
+        assert_eq!(parse_byte_size("1.5GB"), Ok(1_500_000_000));
+        assert_eq!(parse_byte_size("1.5GiB"), Ok(3 << 29));
+        assert_eq!(parse_byte_size(" 1TiB "), Ok(1 << 40));
+        assert_eq!(
+            parse_byte_size("5XB"),
+            Err("Invalid size unit: XB".to_string())
+        );
+        assert_eq!(parse_byte_size(""), Err("Invalid size: ".to_string()));
+        assert_eq!(parse_byte_size("MB"), Err("Invalid size: MB".to_string()));
+        assert!(parse_byte_size("-5MB").is_err());
+        assert!(parse_byte_size("1.2.3").is_err());
+    }
+
+    #[test]
+    fn counts() {
+        assert_eq!(parse_count("25", "number of records"), Ok(25));
+        assert_eq!(
+            parse_count("0", "number of records"),
+            Err("The number of records must be at least 1".to_string())
+        );
+        assert_eq!(
+            parse_count("-1", "number of records"),
+            Err("Invalid number of records: -1".to_string())
+        );
+        assert!(parse_count("ten", "number of records").is_err());
+        assert!(parse_count("", "number of records").is_err());
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("1h 30m"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+        assert!(parse_duration("0s").is_err());
+        assert!(parse_duration("30").is_err());
+        assert!(parse_duration("").is_err());
+    }
+}
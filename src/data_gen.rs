@@ -1,33 +1,228 @@
+pub mod cdc;
+pub mod out_of_order;
+pub mod scd2;
+
+use super::definition::gen::GeneratorContext;
 use super::definition::schema::{FieldType, RecordSchema};
 use crate::data_repr::{ColumnData, Tuple};
+use std::num::NonZeroUsize;
+
+/**
+ * Generator
+ *
+ * An endless stream of tuples for a schema. The record index generators see
+ * counts up from zero, or from the index given to `with_start`.
+ */
+#[derive(Debug, Clone)]
+pub struct Generator<'a> {
+    schema: &'a RecordSchema,
+    record_index: u64,
+}
+
+impl<'a> Generator<'a> {
+    pub fn new(schema: &'a RecordSchema) -> Self {
+        Generator {
+            schema,
+            record_index: 0,
+        }
+    }
+
+    pub fn with_start(mut self, record_index: u64) -> Self {
+        self.record_index = record_index;
+        self
+    }
+
+    /// Groups the tuples into vectors of `batch_size`
+    pub fn batches(self, batch_size: NonZeroUsize) -> Batches<Self> {
+        Batches::new(self, batch_size)
+    }
+}
+
+impl Iterator for Generator<'_> {
+    type Item = Tuple;
 
-pub fn create_data_from_schema(schema: &RecordSchema) -> Tuple {
+    fn next(&mut self) -> Option<Tuple> {
+        let tuple = create_data_from_schema(self.schema, self.record_index);
+        self.record_index += 1;
+        Some(tuple)
+    }
+}
+
+/**
+ * Batches
+ *
+ * Collects a stream of tuples into vectors of up to `batch_size`. Only the
+ * last batch of a finite stream is shorter.
+ */
+#[derive(Debug, Clone)]
+pub struct Batches<I: Iterator<Item = Tuple>> {
+    tuples: I,
+    batch_size: NonZeroUsize,
+}
+
+impl<I: Iterator<Item = Tuple>> Batches<I> {
+    pub fn new(tuples: I, batch_size: NonZeroUsize) -> Self {
+        Batches { tuples, batch_size }
+    }
+}
+
+impl<I: Iterator<Item = Tuple>> Iterator for Batches<I> {
+    type Item = Vec<Tuple>;
+
+    fn next(&mut self) -> Option<Vec<Tuple>> {
+        let batch: Vec<Tuple> = self.tuples.by_ref().take(self.batch_size.get()).collect();
+        if batch.is_empty() {
+            None
+        } else {
+            Some(batch)
+        }
+    }
+}
+
+pub fn create_data_from_schema(schema: &RecordSchema, record_index: u64) -> Tuple {
     let tuple = Tuple::new();
-    create_data_from_schema_recurse(schema, tuple)
+    create_data_from_schema_recurse(schema, record_index, tuple)
+}
+
+/// A new version of `row` where the fields `regenerate` picks get new values. Fields are
+/// generated in schema order against the new version, so templates read the values it ends
+/// up with.
+pub fn regenerate_fields<F: Fn(&str) -> bool>(
+    schema: &RecordSchema,
+    record_index: u64,
+    row: &Tuple,
+    regenerate: F,
+) -> Tuple {
+    let mut tuple = Tuple::new();
+    for cs in schema.iter() {
+        let data = match row.get(cs.get_name()) {
+            Some(data) if !regenerate(cs.get_name()) => data.clone(),
+            _ => {
+                let ctx = GeneratorContext::new(record_index, &tuple);
+                create_data_from_column_type(cs.get_type(), &ctx)
+            }
+        };
+        tuple.add_field_data(cs.get_name(), data)
+    }
+    tuple
 }
 
-fn create_data_from_schema_recurse(schema: &RecordSchema, mut tuple: Tuple) -> Tuple {
+fn create_data_from_schema_recurse(
+    schema: &RecordSchema,
+    record_index: u64,
+    mut tuple: Tuple,
+) -> Tuple {
     for cs in schema.iter() {
-        tuple.add_field_data(cs.get_name(), create_data_from_column_type(cs.get_type()))
+        let ctx = GeneratorContext::new(record_index, &tuple);
+        let data = create_data_from_column_type(cs.get_type(), &ctx);
+        tuple.add_field_data(cs.get_name(), data)
     }
     tuple
 }
 
-fn create_data_from_column_type(col_type: &FieldType) -> ColumnData {
+fn create_data_from_column_type(col_type: &FieldType, ctx: &GeneratorContext) -> ColumnData {
     match &col_type {
-        FieldType::Float(def) => ColumnData::Float(def.generate()),
-        FieldType::Integer(def) => ColumnData::Integer(def.generate()),
-        FieldType::String(def) => ColumnData::String(def.generate()),
+        FieldType::Float(def) => ColumnData::Float(def.generate(ctx)),
+        FieldType::Integer(def) => ColumnData::Integer(def.generate(ctx)),
+        FieldType::Boolean(def) => ColumnData::Boolean(def.generate(ctx)),
+        FieldType::String(def) => ColumnData::String(def.generate(ctx)),
+        FieldType::Uuid(def) => ColumnData::Uuid(def.generate(ctx)),
+        FieldType::Bytes(def) => ColumnData::Bytes(def.generate(ctx)),
+        FieldType::Timestamp(def) => ColumnData::Timestamp(def.generate(ctx)),
         FieldType::List(v) => {
             let mut list = Vec::new();
             for _ in 0..4 {
-                list.push(create_data_from_column_type(v))
+                list.push(create_data_from_column_type(v, ctx))
             }
             ColumnData::List(list)
         }
         FieldType::Record(v) => {
-            let sub_tuple = create_data_from_schema_recurse(v, Tuple::new());
+            let sub_tuple = create_data_from_schema_recurse(v, ctx.record_index(), Tuple::new());
             ColumnData::Record(sub_tuple)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use std::path::Path;
+
+    fn schema() -> RecordSchema {
+        parse(
+            "table T (i INTEGER trend(0, 1), s STRING template('row {i}'), l LIST(INTEGER),);",
+            Path::new(""),
+        )
+        .unwrap()
+        .into_record()
+    }
+
+    fn batch_size(size: usize) -> NonZeroUsize {
+        NonZeroUsize::new(size).unwrap()
+    }
+
+    fn indexes(tuples: &[Tuple]) -> Vec<i64> {
+        tuples
+            .iter()
+            .map(|t| match t.get("i") {
+                Some(ColumnData::Integer(i)) => *i,
+                data => panic!("i is {:?}", data),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn generators_count_up_from_their_start() {
+        let schema = schema();
+        let tuples: Vec<Tuple> = Generator::new(&schema).take(1000).collect();
+        assert_eq!(indexes(&tuples), (0..1000).collect::<Vec<_>>());
+        let tuple = &tuples[7];
+        let fields: Vec<&str> = tuple.into_iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(fields, ["i", "s", "l"]);
+        assert_eq!(
+            tuple.get("s"),
+            Some(&ColumnData::String("row 7".to_string()))
+        );
+        assert!(matches!(tuple.get("l"), Some(ColumnData::List(l)) if l.len() == 4));
+
+        let tuples: Vec<Tuple> = Generator::new(&schema).with_start(10).take(3).collect();
+        assert_eq!(indexes(&tuples), [10, 11, 12]);
+    }
+
+    #[test]
+    fn generators_are_repeatable() {
+        let schema = schema();
+        let generator = Generator::new(&schema).with_start(5);
+        let first: Vec<Tuple> = generator.clone().take(20).collect();
+        let second: Vec<Tuple> = generator.take(20).collect();
+        assert_eq!(indexes(&first), indexes(&second));
+        let strings = |tuples: &[Tuple]| -> Vec<ColumnData> {
+            tuples.iter().map(|t| t.get("s").unwrap().clone()).collect()
+        };
+        assert_eq!(strings(&first), strings(&second));
+    }
+
+    #[test]
+    fn batches() {
+        let schema = schema();
+        let sizes = |count: usize, size: usize| -> Vec<usize> {
+            Batches::new(Generator::new(&schema).take(count), batch_size(size))
+                .map(|batch| batch.len())
+                .collect()
+        };
+        assert_eq!(sizes(250, 100), [100, 100, 50]);
+        assert_eq!(sizes(200, 100), [100, 100]);
+        assert_eq!(sizes(3, 1), [1, 1, 1]);
+        assert_eq!(sizes(5, 10), [5]);
+        assert!(sizes(0, 10).is_empty());
+
+        // Batches keep the order of the tuples
+        let batches: Vec<Vec<Tuple>> = Generator::new(&schema)
+            .batches(batch_size(4))
+            .take(3)
+            .collect();
+        let all: Vec<i64> = batches.iter().flat_map(|batch| indexes(batch)).collect();
+        assert_eq!(all, (0..12).collect::<Vec<_>>());
+    }
+}
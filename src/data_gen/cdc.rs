@@ -0,0 +1,244 @@
+use super::{create_data_from_schema, regenerate_fields};
+use crate::data_repr::{ColumnData, Tuple};
+use crate::definition::schema::{FieldSchema, FieldType, RecordSchema};
+use rand::distributions::WeightedIndex;
+use rand::prelude::*;
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/**
+ * CdcOperation
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CdcOperation {
+    Insert,
+    Update,
+    Delete,
+}
+
+impl CdcOperation {
+    // Debezium style operation codes
+    fn code(&self) -> &'static str {
+        match self {
+            CdcOperation::Insert => "c",
+            CdcOperation::Update => "u",
+            CdcOperation::Delete => "d",
+        }
+    }
+}
+
+/**
+ * CdcRatios
+ *
+ * Relative weights of inserts, updates and deletes, written as `insert:update:delete`
+ */
+#[derive(Debug, Clone)]
+pub struct CdcRatios {
+    insert: f64,
+    update: f64,
+    delete: f64,
+}
+
+impl std::str::FromStr for CdcRatios {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let ratios = s
+            .split(':')
+            .map(|r| r.trim().parse::<f64>())
+            .collect::<Result<Vec<f64>, _>>()
+            .map_err(|_| format!("Invalid CDC ratios: {}", s))?;
+        match ratios.as_slice() {
+            [insert, update, delete] if ratios.iter().all(|r| *r >= 0.0) && *insert > 0.0 => {
+                Ok(CdcRatios {
+                    insert: *insert,
+                    update: *update,
+                    delete: *delete,
+                })
+            }
+            _ => Err(format!(
+                "Invalid CDC ratios: {} (expected insert:update:delete with a non-zero insert)",
+                s
+            )),
+        }
+    }
+}
+
+/// The envelope written for every change event
+pub fn output_schema(schema: &RecordSchema) -> RecordSchema {
+    RecordSchema::new()
+        .with_field(FieldSchema::new(
+            "before",
+            FieldType::Record(schema.clone()),
+        ))
+        .with_field(FieldSchema::new("after", FieldType::Record(schema.clone())))
+        .with_field(FieldSchema::new(
+            "op",
+            FieldType::String(Default::default()),
+        ))
+        .with_field(FieldSchema::new(
+            "ts_ms",
+            FieldType::Integer(Default::default()),
+        ))
+}
+
+/**
+ * CdcStream
+ *
+ * An endless stream of change events against a generated table. The rows that
+ * are currently live are kept so updates and deletes always refer to an existing
+ * key and carry the row's previous image.
+ */
+pub struct CdcStream<'a> {
+    schema: &'a RecordSchema,
+    key_field: String,
+    operations: WeightedIndex<f64>,
+    live_rows: HashMap<String, Tuple>,
+    live_keys: Vec<String>,
+    record_index: u64,
+    last_ts_ms: i64,
+}
+
+// How many times an insert looks for an unused key before it becomes an update
+const INSERT_KEY_ATTEMPTS: usize = 10;
+
+impl<'a> CdcStream<'a> {
+    pub fn new<S: Into<String>>(
+        schema: &'a RecordSchema,
+        key_field: S,
+        ratios: &CdcRatios,
+    ) -> Result<Self, String> {
+        let key_field = key_field.into();
+        match schema.iter().find(|f| f.get_name() == key_field) {
+            Some(f) => match f.get_type() {
+                FieldType::List(_) | FieldType::Record(_) => {
+                    return Err(format!("Key field {} must be a scalar", key_field))
+                }
+                _ => (),
+            },
+            None => return Err(format!("Key field {} is not in the schema", key_field)),
+        }
+        let operations = WeightedIndex::new(vec![ratios.insert, ratios.update, ratios.delete])
+            .map_err(|e| format!("Invalid CDC ratios: {}", e))?;
+        Ok(CdcStream {
+            schema,
+            key_field,
+            operations,
+            live_rows: HashMap::new(),
+            live_keys: Vec::new(),
+            record_index: 0,
+            last_ts_ms: 0,
+        })
+    }
+
+    fn key_of(&self, tuple: &Tuple) -> String {
+        tuple
+            .get(&self.key_field)
+            .map(|k| k.to_string())
+            .unwrap_or_default()
+    }
+
+    fn next_operation(&self) -> CdcOperation {
+        if self.live_keys.is_empty() {
+            return CdcOperation::Insert;
+        }
+        match self.operations.sample(&mut rand::thread_rng()) {
+            0 => CdcOperation::Insert,
+            1 => CdcOperation::Update,
+            _ => CdcOperation::Delete,
+        }
+    }
+
+    fn insert(&mut self) -> (Option<Tuple>, Option<Tuple>, CdcOperation) {
+        for _ in 0..INSERT_KEY_ATTEMPTS {
+            let row = create_data_from_schema(self.schema, self.record_index);
+            let key = self.key_of(&row);
+            if !self.live_rows.contains_key(&key) {
+                self.live_keys.push(key.clone());
+                self.live_rows.insert(key, row.clone());
+                return (None, Some(row), CdcOperation::Insert);
+            }
+        }
+        // The key space is crowded, so change an existing row instead
+        self.update()
+    }
+
+    fn update(&mut self) -> (Option<Tuple>, Option<Tuple>, CdcOperation) {
+        let key = self.live_keys[rand::thread_rng().gen_range(0..self.live_keys.len())].clone();
+        // Everything but the key gets new values, and fields derived from the key are
+        // generated from the one it keeps
+        let after = regenerate_fields(
+            self.schema,
+            self.record_index,
+            &self.live_rows[&key],
+            |name| name != self.key_field,
+        );
+        let before = self.live_rows.insert(key, after.clone());
+        (before, Some(after), CdcOperation::Update)
+    }
+
+    fn delete(&mut self) -> (Option<Tuple>, Option<Tuple>, CdcOperation) {
+        let index = rand::thread_rng().gen_range(0..self.live_keys.len());
+        let key = self.live_keys.swap_remove(index);
+        let before = self.live_rows.remove(&key);
+        (before, None, CdcOperation::Delete)
+    }
+
+    fn ts_ms(&mut self) -> i64 {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or_default();
+        // Never go backwards, even if the clock does
+        self.last_ts_ms = self.last_ts_ms.max(now);
+        self.last_ts_ms
+    }
+}
+
+impl<'a> Iterator for CdcStream<'a> {
+    type Item = Tuple;
+
+    fn next(&mut self) -> Option<Tuple> {
+        let (before, after, op) = match self.next_operation() {
+            CdcOperation::Insert => self.insert(),
+            CdcOperation::Update => self.update(),
+            CdcOperation::Delete => self.delete(),
+        };
+        self.record_index += 1;
+
+        let image = |row: Option<Tuple>| row.map(ColumnData::Record).unwrap_or(ColumnData::Null);
+        let mut event = Tuple::new();
+        event.add_field_data("before", image(before));
+        event.add_field_data("after", image(after));
+        event.add_field_data("op", ColumnData::String(op.code().to_string()));
+        event.add_field_data("ts_ms", ColumnData::Integer(self.ts_ms()));
+        Some(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use std::path::Path;
+
+    #[test]
+    fn updates_keep_fields_derived_from_the_key() {
+        let schema = parse(
+            "table T (id INTEGER, name STRING, email STRING template('{id}-{name}@x'),);",
+            Path::new(""),
+        )
+        .unwrap()
+        .into_record();
+        let ratios = "1:9:0".parse::<CdcRatios>().unwrap();
+        for event in CdcStream::new(&schema, "id", &ratios).unwrap().take(50) {
+            if let Some(ColumnData::Record(after)) = event.get("after") {
+                let expected = format!(
+                    "{}-{}@x",
+                    after.get("id").unwrap(),
+                    after.get("name").unwrap()
+                );
+                assert_eq!(after.get("email").unwrap().to_string(), expected);
+            }
+        }
+    }
+}
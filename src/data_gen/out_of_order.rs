@@ -0,0 +1,130 @@
+use crate::data_repr::{ColumnData, Tuple};
+use chrono::{DateTime, Duration, Utc};
+use rand::prelude::*;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+/**
+ * OutOfOrder
+ *
+ * Delays each tuple by a random amount, up to `max_lateness`, past its event
+ * time. A tuple is released once a tuple with a later event time has been
+ * generated, so no tuple arrives more than `max_lateness` after its event time.
+ */
+pub struct OutOfOrder<I: Iterator<Item = Tuple>> {
+    tuples: I,
+    event_time_field: String,
+    max_lateness: Duration,
+    pending: BinaryHeap<Reverse<Pending>>,
+    sequence: u64,
+    // The latest event time generated so far
+    watermark: Option<DateTime<Utc>>,
+}
+
+struct Pending {
+    release_at: DateTime<Utc>,
+    sequence: u64,
+    tuple: Tuple,
+}
+
+impl PartialEq for Pending {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Pending {}
+
+impl PartialOrd for Pending {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Pending {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.release_at, self.sequence).cmp(&(other.release_at, other.sequence))
+    }
+}
+
+impl<I: Iterator<Item = Tuple>> OutOfOrder<I> {
+    pub fn new<S: Into<String>>(tuples: I, event_time_field: S, max_lateness: Duration) -> Self {
+        OutOfOrder {
+            tuples,
+            event_time_field: event_time_field.into(),
+            max_lateness,
+            pending: BinaryHeap::new(),
+            sequence: 0,
+            watermark: None,
+        }
+    }
+
+    fn event_time(&self, tuple: &Tuple) -> Option<DateTime<Utc>> {
+        match tuple.get(&self.event_time_field) {
+            Some(ColumnData::Timestamp(ts)) => Some(*ts),
+            _ => None,
+        }
+    }
+}
+
+impl<I: Iterator<Item = Tuple>> Iterator for OutOfOrder<I> {
+    type Item = Tuple;
+
+    fn next(&mut self) -> Option<Tuple> {
+        loop {
+            if let Some(Reverse(next)) = self.pending.peek() {
+                if Some(next.release_at) <= self.watermark {
+                    return self.pending.pop().map(|Reverse(p)| p.tuple);
+                }
+            }
+            let tuple = match self.tuples.next() {
+                Some(tuple) => tuple,
+                // Nothing left to generate, so everything can be released
+                None => return self.pending.pop().map(|Reverse(p)| p.tuple),
+            };
+            let event_time = match self.event_time(&tuple) {
+                Some(event_time) => event_time,
+                // A tuple without an event time, such as a NULL valid_to, can't be late
+                None => return Some(tuple),
+            };
+            let max_lateness_ms = self.max_lateness.num_milliseconds();
+            let delay = if max_lateness_ms > 0 {
+                Duration::milliseconds(rand::thread_rng().gen_range(0..=max_lateness_ms))
+            } else {
+                Duration::zero()
+            };
+            self.pending.push(Reverse(Pending {
+                release_at: event_time + delay,
+                sequence: self.sequence,
+                tuple,
+            }));
+            self.sequence += 1;
+            self.watermark = self.watermark.max(Some(event_time));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tuple(event_time: ColumnData) -> Tuple {
+        let mut tuple = Tuple::new();
+        tuple.add_field_data("ts", event_time);
+        tuple
+    }
+
+    #[test]
+    fn tuples_without_an_event_time_pass_through() {
+        let ts = Utc::now();
+        let tuples = vec![
+            tuple(ColumnData::Timestamp(ts)),
+            tuple(ColumnData::Null),
+            tuple(ColumnData::Timestamp(ts + Duration::hours(1))),
+        ];
+        let out: Vec<Tuple> =
+            OutOfOrder::new(tuples.into_iter(), "ts", Duration::minutes(5)).collect();
+        assert_eq!(out.len(), 3);
+        assert!(out.contains(&tuple(ColumnData::Null)));
+    }
+}
@@ -0,0 +1,259 @@
+use super::{create_data_from_schema, regenerate_fields};
+use crate::data_repr::{ColumnData, Tuple};
+use crate::definition::schema::{FieldSchema, FieldType, RecordSchema};
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use log::warn;
+use rand::prelude::*;
+use std::collections::HashSet;
+
+pub const VALID_FROM: &str = "valid_from";
+pub const VALID_TO: &str = "valid_to";
+pub const IS_CURRENT: &str = "is_current";
+
+// How many times a new entity looks for an unused business key
+const ENTITY_KEY_ATTEMPTS: usize = 10;
+// How many times a new version looks for values that differ from the previous version
+const VERSION_CHANGE_ATTEMPTS: usize = 10;
+// Versions of an entity are between one and this many days apart
+const MAX_DAYS_BETWEEN_VERSIONS: i64 = 90;
+
+/// The dimension's fields followed by the validity columns
+pub fn output_schema(schema: &RecordSchema) -> RecordSchema {
+    schema
+        .clone()
+        .with_field(FieldSchema::new(
+            VALID_FROM,
+            FieldType::Timestamp(Default::default()),
+        ))
+        .with_field(FieldSchema::new(
+            VALID_TO,
+            FieldType::Timestamp(Default::default()),
+        ))
+        .with_field(FieldSchema::new(
+            IS_CURRENT,
+            FieldType::Boolean(Default::default()),
+        ))
+}
+
+/**
+ * Scd2History
+ *
+ * Generates every version of `entities` business keys as a slowly changing
+ * dimension (type 2). Each new version changes some of the mutable fields and
+ * starts exactly when the previous version stops being valid. The latest
+ * version of an entity has no `valid_to` and is the only one marked current.
+ */
+pub struct Scd2History<'a> {
+    schema: &'a RecordSchema,
+    key_field: String,
+    mutable_fields: Vec<String>,
+    max_versions: usize,
+    start: DateTime<Utc>,
+    entities: u64,
+    entity_index: u64,
+    // Every generated row gets its own record index, whichever entity it belongs to
+    record_index: u64,
+    used_keys: HashSet<String>,
+    pending: Vec<Tuple>,
+}
+
+impl<'a> Scd2History<'a> {
+    pub fn new<S: Into<String>>(
+        schema: &'a RecordSchema,
+        key_field: S,
+        mutable_fields: Option<Vec<String>>,
+        max_versions: usize,
+        entities: u64,
+    ) -> Result<Self, String> {
+        let key_field = key_field.into();
+        let field_names: Vec<&str> = schema.iter().map(|f| f.get_name()).collect();
+        if !field_names.contains(&key_field.as_str()) {
+            return Err(format!("Key field {} is not in the schema", key_field));
+        }
+        for name in &[VALID_FROM, VALID_TO, IS_CURRENT] {
+            if field_names.contains(name) {
+                return Err(format!("{} is reserved for SCD2 history", name));
+            }
+        }
+        let mutable_fields = match mutable_fields {
+            Some(fields) => {
+                for f in &fields {
+                    if !field_names.contains(&f.as_str()) {
+                        return Err(format!("Mutable field {} is not in the schema", f));
+                    }
+                    if *f == key_field {
+                        return Err(format!("Key field {} can't be mutable", f));
+                    }
+                }
+                fields
+            }
+            None => field_names
+                .iter()
+                .filter(|f| **f != key_field)
+                .map(|f| f.to_string())
+                .collect(),
+        };
+        if max_versions > 1 && mutable_fields.is_empty() {
+            return Err("SCD2 history needs at least one mutable field".to_string());
+        }
+        if max_versions == 0 {
+            return Err("SCD2 history needs at least one version per entity".to_string());
+        }
+        Ok(Scd2History {
+            schema,
+            key_field,
+            mutable_fields,
+            max_versions,
+            // Versions start at midnight, early enough that the last ones start before today
+            start: Utc::now()
+                .duration_trunc(Duration::days(1))
+                .map_err(|e| e.to_string())?
+                - Duration::days(MAX_DAYS_BETWEEN_VERSIONS * max_versions as i64),
+            entities,
+            entity_index: 0,
+            record_index: 0,
+            used_keys: HashSet::new(),
+            pending: Vec::new(),
+        })
+    }
+
+    fn new_entity(&mut self) -> Option<Tuple> {
+        for _ in 0..ENTITY_KEY_ATTEMPTS {
+            let row = create_data_from_schema(self.schema, self.next_record_index());
+            let key = row
+                .get(&self.key_field)
+                .map(|k| k.to_string())
+                .unwrap_or_default();
+            if self.used_keys.insert(key) {
+                return Some(row);
+            }
+        }
+        None
+    }
+
+    fn next_record_index(&mut self) -> u64 {
+        self.record_index += 1;
+        self.record_index - 1
+    }
+
+    /// Copies the row, giving a random non-empty subset of the mutable fields new values.
+    /// None when the new values keep coming out the same as the old ones.
+    fn next_version(&mut self, row: &Tuple) -> Option<Tuple> {
+        let mut rng = rand::thread_rng();
+        for _ in 0..VERSION_CHANGE_ATTEMPTS {
+            let record_index = self.next_record_index();
+            let changed_count = rng.gen_range(1..=self.mutable_fields.len());
+            let changed: Vec<&String> = self
+                .mutable_fields
+                .choose_multiple(&mut rng, changed_count)
+                .collect();
+            let next = regenerate_fields(self.schema, record_index, row, |name| {
+                changed.iter().any(|c| *c == name)
+            });
+            if changed.iter().any(|name| next.get(name) != row.get(name)) {
+                return Some(next);
+            }
+        }
+        None
+    }
+
+    fn entity_history(&mut self, first: Tuple) -> Vec<Tuple> {
+        let mut rng = rand::thread_rng();
+        let version_count = rng.gen_range(1..=self.max_versions);
+        let mut valid_from =
+            self.start + Duration::days(rng.gen_range(0..MAX_DAYS_BETWEEN_VERSIONS));
+
+        let mut history = Vec::with_capacity(version_count);
+        let mut row = first;
+        for version in 1..=version_count {
+            // The last version stays current, as does one none of whose fields would change
+            let next = if version < version_count {
+                self.next_version(&row)
+            } else {
+                None
+            };
+            let valid_to = next
+                .as_ref()
+                .map(|_| valid_from + Duration::days(rng.gen_range(1..=MAX_DAYS_BETWEEN_VERSIONS)));
+
+            let mut output = row.clone();
+            output.add_field_data(VALID_FROM, ColumnData::Timestamp(valid_from));
+            output.add_field_data(
+                VALID_TO,
+                valid_to
+                    .map(ColumnData::Timestamp)
+                    .unwrap_or(ColumnData::Null),
+            );
+            output.add_field_data(IS_CURRENT, ColumnData::Boolean(next.is_none()));
+            history.push(output);
+
+            match (next, valid_to) {
+                (Some(next), Some(valid_to)) => {
+                    row = next;
+                    valid_from = valid_to;
+                }
+                _ => break,
+            }
+        }
+        history
+    }
+}
+
+impl<'a> Iterator for Scd2History<'a> {
+    type Item = Tuple;
+
+    fn next(&mut self) -> Option<Tuple> {
+        if self.pending.is_empty() {
+            if self.entity_index >= self.entities {
+                return None;
+            }
+            let first = match self.new_entity() {
+                Some(first) => first,
+                None => {
+                    warn!(
+                        "Ran out of unique values for {} after {} entities",
+                        self.key_field, self.entity_index
+                    );
+                    self.entities = self.entity_index;
+                    return None;
+                }
+            };
+            self.pending = self.entity_history(first);
+            self.pending.reverse();
+            self.entity_index += 1;
+        }
+        self.pending.pop()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use std::io::Write;
+
+    #[test]
+    fn consecutive_versions_differ() {
+        let dir = std::env::temp_dir().join(format!("datablaster-scd2-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut countries = std::fs::File::create(dir.join("countries.txt")).unwrap();
+        writeln!(countries, "DE\nFR").unwrap();
+        let schema = parse(
+            "table T (id INTEGER, country STRING from_file('countries.txt'),);",
+            &dir,
+        )
+        .unwrap()
+        .into_record();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let history: Vec<Tuple> = Scd2History::new(&schema, "id", None, 8, 20)
+            .unwrap()
+            .collect();
+        for (previous, next) in history.iter().zip(history.iter().skip(1)) {
+            if previous.get("id") == next.get("id") {
+                assert_ne!(previous.get("country"), next.get("country"));
+                assert_eq!(previous.get(VALID_TO), next.get(VALID_FROM));
+            }
+        }
+    }
+}
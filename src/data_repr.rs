@@ -1,14 +1,23 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::fmt;
+use uuid::Uuid;
+
 // Data Repr
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ColumnData {
     Integer(i64),
     Float(f64),
+    Boolean(bool),
     String(String),
+    Uuid(Uuid),
+    Bytes(Vec<u8>),
+    Timestamp(DateTime<Utc>),
+    Null,
     Record(Tuple),
     List(Vec<ColumnData>),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct Tuple {
     fields: Vec<(String, ColumnData)>,
 }
@@ -24,6 +33,76 @@ impl Tuple {
     pub fn add_field_data<S: Into<String>>(&mut self, name: S, data: ColumnData) {
         self.fields.push((name.into(), data));
     }
+
+    /// Rough size of the values once encoded in a binary format
+    pub fn estimated_size(&self) -> u64 {
+        self.fields
+            .iter()
+            .map(|(_, data)| data.estimated_size())
+            .sum()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ColumnData> {
+        self.fields
+            .iter()
+            .find(|(field_name, _)| field_name == name)
+            .map(|(_, data)| data)
+    }
+}
+
+impl ColumnData {
+    /// Rough size of the value once encoded in a binary format
+    pub fn estimated_size(&self) -> u64 {
+        match self {
+            ColumnData::Integer(_) | ColumnData::Float(_) | ColumnData::Timestamp(_) => 8,
+            ColumnData::Boolean(_) | ColumnData::Null => 1,
+            ColumnData::String(v) => v.len() as u64 + 4,
+            ColumnData::Uuid(_) => 16,
+            ColumnData::Bytes(v) => v.len() as u64 + 4,
+            ColumnData::Record(t) => t.estimated_size(),
+            ColumnData::List(v) => v.iter().map(|d| d.estimated_size()).sum::<u64>() + 4,
+        }
+    }
+}
+
+/// RFC 3339 in UTC, with as much sub-second precision as the value needs
+pub fn format_timestamp(ts: &DateTime<Utc>) -> String {
+    ts.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+impl fmt::Display for ColumnData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ColumnData::Integer(v) => write!(f, "{}", v),
+            ColumnData::Float(v) => write!(f, "{}", v),
+            ColumnData::Boolean(v) => write!(f, "{}", v),
+            ColumnData::String(v) => write!(f, "{}", v),
+            ColumnData::Uuid(v) => write!(f, "{}", v),
+            ColumnData::Bytes(v) => write!(f, "{}", hex::encode(v)),
+            ColumnData::Timestamp(v) => write!(f, "{}", format_timestamp(v)),
+            ColumnData::Null => Ok(()),
+            ColumnData::Record(t) => {
+                write!(f, "{{")?;
+                for (i, (name, data)) in t.into_iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", name, data)?;
+                }
+                write!(f, "}}")
+            }
+            ColumnData::List(v) => {
+                write!(f, "[")?;
+                for (i, data) in v.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", data)?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
 }
 
 impl IntoIterator for Tuple {
@@ -1,11 +1,51 @@
+pub mod series;
+
+use crate::data_repr::{ColumnData, Tuple};
+use chrono::{DateTime, Datelike, Duration, Utc};
+use rand::distributions::{Alphanumeric, WeightedIndex};
 use rand::prelude::*;
 use std::fmt::Debug;
+use uuid::Uuid;
+
+/**
+ * GeneratorContext
+ *
+ * What a generator can see of the record currently being generated
+ */
+pub struct GeneratorContext<'a> {
+    record_index: u64,
+    record: &'a Tuple,
+}
+
+impl<'a> GeneratorContext<'a> {
+    pub fn new(record_index: u64, record: &'a Tuple) -> Self {
+        GeneratorContext {
+            record_index,
+            record,
+        }
+    }
+
+    /// Position of the top level record being generated
+    pub fn record_index(&self) -> u64 {
+        self.record_index
+    }
+
+    /// Data for a field generated earlier in the current record
+    pub fn get_field(&self, name: &str) -> Option<&ColumnData> {
+        self.record.get(name)
+    }
+}
 
 /**
  * DataGenerator
  */
 pub trait DataGenerator<T>: Debug + DataGeneratorClone<T> {
-    fn generate_data(&self) -> T;
+    fn generate_data(&self, ctx: &GeneratorContext) -> T;
+
+    /// Names of the fields in the same record that this generator reads from
+    fn referenced_fields(&self) -> Vec<&str> {
+        Vec::new()
+    }
 }
 
 pub trait DataGeneratorClone<T> {
@@ -39,15 +79,204 @@ impl<T: Clone> DataFunctionGenerator<T> {
 }
 
 impl<T: 'static + Debug + Clone> DataGenerator<T> for DataFunctionGenerator<T> {
-    fn generate_data(&self) -> T {
+    fn generate_data(&self, _ctx: &GeneratorContext) -> T {
         (self.gen_fn)()
     }
 }
 
 /**
- * Default Generators
+ * RandomBytesGenerator
+ */
+#[derive(Debug, Clone)]
+pub struct RandomBytesGenerator {
+    len: usize,
+}
+
+impl RandomBytesGenerator {
+    pub fn new(len: usize) -> Self {
+        RandomBytesGenerator { len }
+    }
+}
+
+impl DataGenerator<Vec<u8>> for RandomBytesGenerator {
+    fn generate_data(&self, _ctx: &GeneratorContext) -> Vec<u8> {
+        let mut bytes = vec![0; self.len];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        bytes
+    }
+}
+
+/**
+ * SampleGenerator
+ *
+ * Picks from a fixed set of values, either uniformly or by weight
  */
+#[derive(Debug, Clone)]
+pub struct SampleGenerator<T: Clone> {
+    values: Vec<T>,
+    weights: Option<WeightedIndex<f64>>,
+}
 
+impl<T: Clone> SampleGenerator<T> {
+    pub fn new(values: Vec<T>) -> Self {
+        assert!(!values.is_empty(), "SampleGenerator requires values");
+        SampleGenerator {
+            values,
+            weights: None,
+        }
+    }
+
+    pub fn with_weights(values: Vec<T>, weights: Vec<f64>) -> Result<Self, String> {
+        if values.len() != weights.len() {
+            return Err(format!(
+                "{} values given but {} weights",
+                values.len(),
+                weights.len()
+            ));
+        }
+        let weights = WeightedIndex::new(weights).map_err(|e| e.to_string())?;
+        Ok(SampleGenerator {
+            values,
+            weights: Some(weights),
+        })
+    }
+}
+
+impl<T: 'static + Debug + Clone> DataGenerator<T> for SampleGenerator<T> {
+    fn generate_data(&self, _ctx: &GeneratorContext) -> T {
+        let mut rng = rand::thread_rng();
+        let index = match &self.weights {
+            Some(weights) => weights.sample(&mut rng),
+            None => rng.gen_range(0..self.values.len()),
+        };
+        self.values[index].clone()
+    }
+}
+
+/**
+ * TemplateGenerator
+ *
+ * Builds a string out of literal text, other fields and small built in generators
+ */
+#[derive(Debug, Clone)]
+pub enum TemplatePart {
+    Literal(String),
+    Field(String),
+    Integer {
+        min: i64,
+        max: i64,
+        width: usize,
+        zero_pad: bool,
+    },
+    Float {
+        min: f64,
+        max: f64,
+        precision: usize,
+    },
+    Digits(usize),
+    Letters(usize),
+    Alphanumeric(usize),
+    Hex(usize),
+    Uuid,
+    Choice(Vec<String>),
+    Year,
+    Month,
+    Day,
+}
+
+#[derive(Debug, Clone)]
+pub struct TemplateGenerator {
+    parts: Vec<TemplatePart>,
+}
+
+impl TemplateGenerator {
+    pub fn new(parts: Vec<TemplatePart>) -> Self {
+        TemplateGenerator { parts }
+    }
+}
+
+impl DataGenerator<String> for TemplateGenerator {
+    fn generate_data(&self, ctx: &GeneratorContext) -> String {
+        let mut rng = rand::thread_rng();
+        let mut output = String::new();
+        for part in &self.parts {
+            match part {
+                TemplatePart::Literal(s) => output.push_str(s),
+                TemplatePart::Field(name) => {
+                    if let Some(data) = ctx.get_field(name) {
+                        output.push_str(&data.to_string())
+                    }
+                }
+                TemplatePart::Integer {
+                    min,
+                    max,
+                    width,
+                    zero_pad,
+                } => {
+                    let v = rng.gen_range(*min..=*max);
+                    if *zero_pad {
+                        output.push_str(&format!("{:0width$}", v, width = width))
+                    } else {
+                        output.push_str(&format!("{:width$}", v, width = width))
+                    }
+                }
+                TemplatePart::Float {
+                    min,
+                    max,
+                    precision,
+                } => output.push_str(&format!(
+                    "{:.precision$}",
+                    rng.gen_range(*min..*max),
+                    precision = precision
+                )),
+                TemplatePart::Digits(n) => {
+                    (0..*n).for_each(|_| output.push(rng.gen_range(b'0'..=b'9') as char))
+                }
+                TemplatePart::Letters(n) => {
+                    (0..*n).for_each(|_| output.push(rng.gen_range(b'a'..=b'z') as char))
+                }
+                TemplatePart::Alphanumeric(n) => {
+                    (0..*n).for_each(|_| output.push(rng.sample(Alphanumeric) as char))
+                }
+                TemplatePart::Hex(n) => (0..*n).for_each(|_| {
+                    output.push(std::char::from_digit(rng.gen_range(0..16), 16).unwrap())
+                }),
+                TemplatePart::Uuid => output.push_str(&Uuid::new_v4().to_string()),
+                TemplatePart::Choice(choices) => {
+                    output.push_str(&choices[rng.gen_range(0..choices.len())])
+                }
+                // Fields generated earlier with the same name take precedence
+                TemplatePart::Year => match ctx.get_field("year") {
+                    Some(data) => output.push_str(&data.to_string()),
+                    None => output.push_str(&Utc::now().year().to_string()),
+                },
+                TemplatePart::Month => match ctx.get_field("month") {
+                    Some(data) => output.push_str(&data.to_string()),
+                    None => output.push_str(&format!("{:02}", Utc::now().month())),
+                },
+                TemplatePart::Day => match ctx.get_field("day") {
+                    Some(data) => output.push_str(&data.to_string()),
+                    None => output.push_str(&format!("{:02}", Utc::now().day())),
+                },
+            }
+        }
+        output
+    }
+
+    fn referenced_fields(&self) -> Vec<&str> {
+        self.parts
+            .iter()
+            .filter_map(|p| match p {
+                TemplatePart::Field(name) => Some(name.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/**
+ * Default Generators
+ */
 pub trait DefaultGenerator {
     fn default_gen() -> Box<dyn DataGenerator<Self>>;
 }
@@ -68,8 +297,40 @@ impl DefaultGenerator for f64 {
     }
 }
 
+impl DefaultGenerator for bool {
+    fn default_gen() -> Box<dyn DataGenerator<Self>> {
+        Box::new(DataFunctionGenerator::new(|| rand::thread_rng().gen()))
+    }
+}
+
 impl DefaultGenerator for std::string::String {
     fn default_gen() -> Box<dyn DataGenerator<Self>> {
         Box::new(DataFunctionGenerator::new(|| "placeholder".to_string()))
     }
 }
+
+impl DefaultGenerator for Uuid {
+    fn default_gen() -> Box<dyn DataGenerator<Self>> {
+        Box::new(DataFunctionGenerator::new(Uuid::new_v4))
+    }
+}
+
+impl DefaultGenerator for Vec<u8> {
+    fn default_gen() -> Box<dyn DataGenerator<Self>> {
+        Box::new(DataFunctionGenerator::new(|| {
+            let mut rng = rand::thread_rng();
+            let mut bytes = vec![0; rng.gen_range(0..=32)];
+            rng.fill_bytes(&mut bytes);
+            bytes
+        }))
+    }
+}
+
+impl DefaultGenerator for DateTime<Utc> {
+    fn default_gen() -> Box<dyn DataGenerator<Self>> {
+        // Somewhere in the last year
+        Box::new(DataFunctionGenerator::new(|| {
+            Utc::now() - Duration::seconds(rand::thread_rng().gen_range(0..365 * 24 * 60 * 60))
+        }))
+    }
+}
@@ -0,0 +1,317 @@
+/*!
+ * Time series generators
+ *
+ * These are keyed off of the index of the record being generated so that
+ * values progress from one record to the next. Generators that remember
+ * their previous value keep it along with the index it was generated for,
+ * so asking for the same record twice (e.g. inside of a list) gives the same value,
+ * and start over when the index goes backwards (e.g. a new run).
+ */
+
+use super::{DataGenerator, GeneratorContext};
+use chrono::{DateTime, Duration, Utc};
+use rand::prelude::*;
+use std::cell::Cell;
+use std::f64::consts::PI;
+
+fn noise(amount: f64) -> f64 {
+    if amount > 0.0 {
+        rand::thread_rng().gen_range(-amount..=amount)
+    } else {
+        0.0
+    }
+}
+
+/**
+ * TimestampSeriesGenerator
+ */
+#[derive(Debug, Clone)]
+pub struct TimestampSeriesGenerator {
+    start: DateTime<Utc>,
+    interval: Duration,
+    jitter: Duration,
+    last: Cell<Option<(u64, DateTime<Utc>)>>,
+}
+
+impl TimestampSeriesGenerator {
+    pub fn new(start: DateTime<Utc>, interval: Duration, jitter: Duration) -> Result<Self, String> {
+        if interval <= Duration::zero() {
+            return Err("Series interval must be positive".to_string());
+        }
+        // Keeps neighbouring timestamps in order
+        if jitter * 2 >= interval {
+            return Err("Series jitter must be smaller than half the interval".to_string());
+        }
+        Ok(TimestampSeriesGenerator {
+            start,
+            interval,
+            jitter,
+            last: Cell::new(None),
+        })
+    }
+}
+
+impl DataGenerator<DateTime<Utc>> for TimestampSeriesGenerator {
+    fn generate_data(&self, ctx: &GeneratorContext) -> DateTime<Utc> {
+        let index = ctx.record_index();
+        if let Some((last_index, last_value)) = self.last.get() {
+            if last_index == index {
+                return last_value;
+            }
+        }
+        // The jitter is applied to each point on the grid, so it doesn't add up
+        let jitter_ms = noise(self.jitter.num_milliseconds() as f64) as i64;
+        let value = self.start
+            + Duration::milliseconds(self.interval.num_milliseconds() * index as i64 + jitter_ms);
+        self.last.set(Some((index, value)));
+        value
+    }
+}
+
+/**
+ * RandomWalkGenerator
+ */
+#[derive(Debug, Clone)]
+pub struct RandomWalkGenerator {
+    start: f64,
+    step: f64,
+    last: Cell<Option<(u64, f64)>>,
+}
+
+impl RandomWalkGenerator {
+    pub fn new(start: f64, step: f64) -> Self {
+        RandomWalkGenerator {
+            start,
+            step,
+            last: Cell::new(None),
+        }
+    }
+}
+
+impl DataGenerator<f64> for RandomWalkGenerator {
+    fn generate_data(&self, ctx: &GeneratorContext) -> f64 {
+        let index = ctx.record_index();
+        let value = match self.last.get() {
+            Some((last_index, last_value)) if last_index == index => return last_value,
+            Some((last_index, last_value)) if last_index < index => last_value + noise(self.step),
+            // First value, or the index went backwards
+            _ => self.start,
+        };
+        self.last.set(Some((index, value)));
+        value
+    }
+}
+
+/**
+ * TrendGenerator
+ */
+#[derive(Debug, Clone)]
+pub struct TrendGenerator {
+    start: f64,
+    slope: f64,
+    noise: f64,
+}
+
+impl TrendGenerator {
+    pub fn new(start: f64, slope: f64, noise: f64) -> Self {
+        TrendGenerator {
+            start,
+            slope,
+            noise,
+        }
+    }
+}
+
+impl DataGenerator<f64> for TrendGenerator {
+    fn generate_data(&self, ctx: &GeneratorContext) -> f64 {
+        self.start + self.slope * ctx.record_index() as f64 + noise(self.noise)
+    }
+}
+
+/**
+ * SeasonalGenerator
+ */
+#[derive(Debug, Clone)]
+pub struct SeasonalGenerator {
+    base: f64,
+    amplitude: f64,
+    period: f64,
+    noise: f64,
+}
+
+impl SeasonalGenerator {
+    pub fn new(base: f64, amplitude: f64, period: f64, noise: f64) -> Result<Self, String> {
+        if period <= 0.0 {
+            return Err("Seasonal period must be positive".to_string());
+        }
+        Ok(SeasonalGenerator {
+            base,
+            amplitude,
+            period,
+            noise,
+        })
+    }
+}
+
+impl DataGenerator<f64> for SeasonalGenerator {
+    fn generate_data(&self, ctx: &GeneratorContext) -> f64 {
+        let phase = 2.0 * PI * ctx.record_index() as f64 / self.period;
+        self.base + self.amplitude * phase.sin() + noise(self.noise)
+    }
+}
+
+/**
+ * SpikeGenerator
+ */
+#[derive(Debug, Clone)]
+pub struct SpikeGenerator {
+    base: f64,
+    probability: f64,
+    magnitude: f64,
+    noise: f64,
+}
+
+impl SpikeGenerator {
+    pub fn new(base: f64, probability: f64, magnitude: f64, noise: f64) -> Result<Self, String> {
+        if !(0.0..=1.0).contains(&probability) {
+            return Err("Spike probability must be between 0 and 1".to_string());
+        }
+        Ok(SpikeGenerator {
+            base,
+            probability,
+            magnitude,
+            noise,
+        })
+    }
+}
+
+impl DataGenerator<f64> for SpikeGenerator {
+    fn generate_data(&self, _ctx: &GeneratorContext) -> f64 {
+        let spike = if rand::thread_rng().gen_bool(self.probability) {
+            self.magnitude
+        } else {
+            0.0
+        };
+        self.base + spike + noise(self.noise)
+    }
+}
+
+/**
+ * RoundedGenerator
+ *
+ * Lets the floating point series be used for INTEGER fields
+ */
+#[derive(Debug, Clone)]
+pub struct RoundedGenerator {
+    generator: Box<dyn DataGenerator<f64>>,
+}
+
+impl RoundedGenerator {
+    pub fn new(generator: Box<dyn DataGenerator<f64>>) -> Self {
+        RoundedGenerator { generator }
+    }
+}
+
+impl DataGenerator<i64> for RoundedGenerator {
+    fn generate_data(&self, ctx: &GeneratorContext) -> i64 {
+        self.generator.generate_data(ctx).round() as i64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_repr::Tuple;
+    use chrono::TimeZone;
+
+    fn generate<T>(generator: &dyn DataGenerator<T>, index: u64) -> T {
+        generator.generate_data(&GeneratorContext::new(index, &Tuple::new()))
+    }
+
+    #[test]
+    fn timestamp_series_stay_on_their_grid() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let series =
+            TimestampSeriesGenerator::new(start, Duration::seconds(60), Duration::seconds(10))
+                .unwrap();
+        let mut previous = None;
+        for index in 0..10_000 {
+            let value = generate(&series, index);
+            let offset = value - (start + Duration::seconds(60 * index as i64));
+            assert!(
+                offset.num_seconds().abs() <= 10,
+                "{} is off by {}",
+                index,
+                offset
+            );
+            assert_eq!(generate(&series, index), value);
+            assert!(previous < Some(value));
+            previous = Some(value);
+        }
+
+        let exact =
+            TimestampSeriesGenerator::new(start, Duration::seconds(60), Duration::zero()).unwrap();
+        assert_eq!(generate(&exact, 3), start + Duration::seconds(180));
+        assert_eq!(generate(&exact, 1), start + Duration::seconds(60));
+    }
+
+    #[test]
+    fn series_arguments_are_checked() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        assert!(TimestampSeriesGenerator::new(start, Duration::zero(), Duration::zero()).is_err());
+        assert!(
+            TimestampSeriesGenerator::new(start, Duration::seconds(-1), Duration::zero()).is_err()
+        );
+        assert!(
+            TimestampSeriesGenerator::new(start, Duration::seconds(10), Duration::seconds(5))
+                .is_err()
+        );
+        assert!(SeasonalGenerator::new(0.0, 1.0, 0.0, 0.0).is_err());
+        assert!(SpikeGenerator::new(0.0, -0.1, 1.0, 0.0).is_err());
+        assert!(SpikeGenerator::new(0.0, 1.1, 1.0, 0.0).is_err());
+    }
+
+    #[test]
+    fn random_walks_start_over_when_the_index_goes_back() {
+        let walk = RandomWalkGenerator::new(100.0, 1.0);
+        assert_eq!(generate(&walk, 0), 100.0);
+        let mut previous = 100.0;
+        for index in 1..100 {
+            let value = generate(&walk, index);
+            assert!((value - previous).abs() <= 1.0);
+            assert_eq!(generate(&walk, index), value);
+            previous = value;
+        }
+        assert_eq!(generate(&walk, 50), 100.0);
+        assert_eq!(generate(&walk, 0), 100.0);
+    }
+
+    #[test]
+    fn trends_seasons_and_spikes() {
+        let trend = TrendGenerator::new(10.0, 0.5, 0.0);
+        assert_eq!(generate(&trend, 0), 10.0);
+        assert_eq!(generate(&trend, 4), 12.0);
+
+        let seasonal = SeasonalGenerator::new(10.0, 2.0, 4.0, 0.0).unwrap();
+        for (index, expected) in [(0, 10.0), (1, 12.0), (2, 10.0), (3, 8.0), (4, 10.0)] {
+            assert!((generate(&seasonal, index) - expected).abs() < 1e-9);
+        }
+
+        let never = SpikeGenerator::new(1.0, 0.0, 50.0, 0.0).unwrap();
+        let always = SpikeGenerator::new(1.0, 1.0, 50.0, 0.0).unwrap();
+        for index in 0..100 {
+            assert_eq!(generate(&never, index), 1.0);
+            assert_eq!(generate(&always, index), 51.0);
+        }
+
+        let noisy = TrendGenerator::new(0.0, 0.0, 0.5);
+        assert!((0..100).all(|index| generate(&noisy, index).abs() <= 0.5));
+    }
+
+    #[test]
+    fn rounded_series() {
+        let rounded = RoundedGenerator::new(Box::new(TrendGenerator::new(0.0, 0.4, 0.0)));
+        let values: Vec<i64> = (0..5).map(|index| generate(&rounded, index)).collect();
+        assert_eq!(values, vec![0, 0, 1, 1, 2]);
+    }
+}
@@ -0,0 +1,249 @@
+use super::gen::SampleGenerator;
+use super::schema::{FieldDefinition, FieldSchema, FieldType, RecordSchema};
+
+/**
+ * ProtoType
+ *
+ * Protocol Buffers type of a field, which decides how its values are encoded
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProtoType {
+    Double,
+    Float,
+    Int32,
+    Int64,
+    Uint32,
+    Uint64,
+    Sint32,
+    Sint64,
+    Fixed32,
+    Fixed64,
+    Sfixed32,
+    Sfixed64,
+    Bool,
+    String,
+    Bytes,
+    /// Value names and numbers
+    Enum(Vec<(String, i32)>),
+    Message(ProtoMessage),
+    /// google.protobuf.Timestamp
+    Timestamp,
+}
+
+impl ProtoType {
+    /// Scalar type for a .proto type name
+    pub fn scalar(name: &str) -> Option<ProtoType> {
+        Some(match name {
+            "double" => ProtoType::Double,
+            "float" => ProtoType::Float,
+            "int32" => ProtoType::Int32,
+            "int64" => ProtoType::Int64,
+            "uint32" => ProtoType::Uint32,
+            "uint64" => ProtoType::Uint64,
+            "sint32" => ProtoType::Sint32,
+            "sint64" => ProtoType::Sint64,
+            "fixed32" => ProtoType::Fixed32,
+            "fixed64" => ProtoType::Fixed64,
+            "sfixed32" => ProtoType::Sfixed32,
+            "sfixed64" => ProtoType::Sfixed64,
+            "bool" => ProtoType::Bool,
+            "string" => ProtoType::String,
+            "bytes" => ProtoType::Bytes,
+            _ => return None,
+        })
+    }
+
+    /// Name of the type as it appears in a .proto file
+    pub fn name(&self) -> &str {
+        match self {
+            ProtoType::Double => "double",
+            ProtoType::Float => "float",
+            ProtoType::Int32 => "int32",
+            ProtoType::Int64 => "int64",
+            ProtoType::Uint32 => "uint32",
+            ProtoType::Uint64 => "uint64",
+            ProtoType::Sint32 => "sint32",
+            ProtoType::Sint64 => "sint64",
+            ProtoType::Fixed32 => "fixed32",
+            ProtoType::Fixed64 => "fixed64",
+            ProtoType::Sfixed32 => "sfixed32",
+            ProtoType::Sfixed64 => "sfixed64",
+            ProtoType::Bool => "bool",
+            ProtoType::String => "string",
+            ProtoType::Bytes => "bytes",
+            ProtoType::Enum(_) => "enum",
+            ProtoType::Message(m) => m.get_name(),
+            ProtoType::Timestamp => "google.protobuf.Timestamp",
+        }
+    }
+
+    /// Whether repeated values are packed into a single length-delimited field
+    pub fn is_packable(&self) -> bool {
+        !matches!(
+            self,
+            ProtoType::String | ProtoType::Bytes | ProtoType::Message(_) | ProtoType::Timestamp
+        )
+    }
+
+    /// Generated field type. Enums generate their value names
+    fn field_type(&self) -> FieldType {
+        match self {
+            ProtoType::Double | ProtoType::Float => FieldType::Float(Default::default()),
+            ProtoType::Bool => FieldType::Boolean(Default::default()),
+            ProtoType::String => FieldType::String(Default::default()),
+            ProtoType::Bytes => FieldType::Bytes(Default::default()),
+            ProtoType::Enum(values) => {
+                let names = values.iter().map(|(name, _)| name.clone()).collect();
+                FieldType::String(FieldDefinition::new(Box::new(SampleGenerator::new(names))))
+            }
+            ProtoType::Message(m) => FieldType::Record(m.record_schema()),
+            ProtoType::Timestamp => FieldType::Timestamp(Default::default()),
+            _ => FieldType::Integer(Default::default()),
+        }
+    }
+}
+
+/**
+ * ProtoField
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProtoField {
+    name: String,
+    number: u32,
+    proto_type: ProtoType,
+    repeated: bool,
+    oneof: Option<String>,
+}
+
+impl ProtoField {
+    pub fn new<S: Into<String>>(
+        name: S,
+        number: u32,
+        proto_type: ProtoType,
+        repeated: bool,
+    ) -> Self {
+        ProtoField {
+            name: name.into(),
+            number,
+            proto_type,
+            repeated,
+            oneof: None,
+        }
+    }
+
+    /// Makes the field a branch of the oneof `name`
+    pub fn with_oneof<S: Into<String>>(mut self, name: S) -> Self {
+        self.oneof = Some(name.into());
+        self
+    }
+
+    pub fn get_name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    pub fn get_number(&self) -> u32 {
+        self.number
+    }
+
+    pub fn get_type(&self) -> &ProtoType {
+        &self.proto_type
+    }
+
+    pub fn is_repeated(&self) -> bool {
+        self.repeated
+    }
+
+    pub fn get_oneof(&self) -> Option<&str> {
+        self.oneof.as_deref()
+    }
+}
+
+/**
+ * ProtoMessage
+ *
+ * A resolved message type, with every field type it references inlined
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProtoMessage {
+    name: String,
+    fields: Vec<ProtoField>,
+}
+
+impl ProtoMessage {
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        ProtoMessage {
+            name: name.into(),
+            fields: Vec::new(),
+        }
+    }
+
+    /// Field numbers follow the order of the schema. Lists of lists have no
+    /// protobuf equivalent.
+    pub fn from_schema<S: Into<String>>(name: S, schema: &RecordSchema) -> Result<Self, String> {
+        fn proto_type(field: &FieldSchema, field_type: &FieldType) -> Result<ProtoType, String> {
+            Ok(match field_type {
+                FieldType::Integer(_) => ProtoType::Int64,
+                FieldType::Float(_) => ProtoType::Double,
+                FieldType::Boolean(_) => ProtoType::Bool,
+                FieldType::String(_) | FieldType::Uuid(_) => ProtoType::String,
+                FieldType::Bytes(_) => ProtoType::Bytes,
+                FieldType::Timestamp(_) => ProtoType::Timestamp,
+                FieldType::Record(r) => {
+                    ProtoType::Message(ProtoMessage::from_schema(field.get_name(), r)?)
+                }
+                FieldType::List(_) => {
+                    return Err(format!(
+                        "{} is a list of lists, which protobuf can't represent",
+                        field.get_name()
+                    ))
+                }
+            })
+        }
+        let mut message = ProtoMessage::new(name);
+        for (number, field) in (1..).zip(schema.iter()) {
+            let (item_type, repeated) = match field.get_type() {
+                FieldType::List(t) => (&**t, true),
+                t => (t, false),
+            };
+            message.add_field(ProtoField::new(
+                field.get_name(),
+                number,
+                proto_type(field, item_type)?,
+                repeated,
+            ));
+        }
+        Ok(message)
+    }
+
+    pub fn get_name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    pub fn add_field(&mut self, field: ProtoField) {
+        self.fields.push(field);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ProtoField> {
+        self.fields.iter()
+    }
+
+    pub fn field(&self, name: &str) -> Option<&ProtoField> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+
+    /// Schema that generates values for every field of the message, including
+    /// every branch of a oneof
+    pub fn record_schema(&self) -> RecordSchema {
+        let mut record = RecordSchema::new();
+        for field in &self.fields {
+            let field_type = field.proto_type.field_type();
+            let field_type = if field.repeated {
+                FieldType::List(Box::new(field_type))
+            } else {
+                field_type
+            };
+            record.add_field(FieldSchema::new(field.name.as_str(), field_type));
+        }
+        record
+    }
+}
@@ -1,4 +1,6 @@
-use super::gen::{DataGenerator, DefaultGenerator};
+use super::gen::{DataGenerator, DefaultGenerator, GeneratorContext};
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
 
 /**
  * FieldSchema
@@ -7,6 +9,7 @@ use super::gen::{DataGenerator, DefaultGenerator};
 pub struct FieldSchema {
     name: String,
     field_type: FieldType,
+    format: FieldFormat,
 }
 
 impl FieldSchema {
@@ -14,9 +17,15 @@ impl FieldSchema {
         FieldSchema {
             name: name.into(),
             field_type,
+            format: Default::default(),
         }
     }
 
+    pub fn with_format(mut self, format: FieldFormat) -> Self {
+        self.format = format;
+        self
+    }
+
     pub fn get_name(&self) -> &str {
         self.name.as_str()
     }
@@ -24,6 +33,123 @@ impl FieldSchema {
     pub fn get_type(&self) -> &FieldType {
         &self.field_type
     }
+
+    pub fn get_format(&self) -> &FieldFormat {
+        &self.format
+    }
+}
+
+/**
+ * Alignment
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Alignment {
+    Left,
+    Right,
+}
+
+impl std::str::FromStr for Alignment {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "left" => Ok(Alignment::Left),
+            "right" => Ok(Alignment::Right),
+            _ => Err(format!("Unknown alignment: {}", s)),
+        }
+    }
+}
+
+/**
+ * Overflow
+ *
+ * What happens to values that don't fit their width
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Overflow {
+    #[default]
+    Error,
+    /// Cuts text off at the width. Numbers are filled with `*` instead, as
+    /// dropping digits would change their value
+    Truncate,
+}
+
+impl std::str::FromStr for Overflow {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(Overflow::Error),
+            "truncate" => Ok(Overflow::Truncate),
+            _ => Err(format!("Unknown overflow: {}", s)),
+        }
+    }
+}
+
+/**
+ * FieldFormat
+ *
+ * Layout of a field in fixed-width output, set by annotations such as
+ * `name STRING width(20) align(left)`
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldFormat {
+    width: Option<usize>,
+    align: Option<Alignment>,
+    padding: char,
+    overflow: Overflow,
+}
+
+impl Default for FieldFormat {
+    fn default() -> Self {
+        FieldFormat {
+            width: None,
+            align: None,
+            padding: ' ',
+            overflow: Default::default(),
+        }
+    }
+}
+
+impl FieldFormat {
+    pub fn with_width(mut self, width: usize) -> Self {
+        self.width = Some(width);
+        self
+    }
+
+    pub fn with_align(mut self, align: Alignment) -> Self {
+        self.align = Some(align);
+        self
+    }
+
+    pub fn with_padding(mut self, padding: char) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    pub fn with_overflow(mut self, overflow: Overflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    pub fn get_width(&self) -> Option<usize> {
+        self.width
+    }
+
+    /// Numbers are right aligned unless the schema says otherwise
+    pub fn get_align(&self, numeric: bool) -> Alignment {
+        match self.align {
+            Some(align) => align,
+            None if numeric => Alignment::Right,
+            None => Alignment::Left,
+        }
+    }
+
+    pub fn get_padding(&self) -> char {
+        self.padding
+    }
+
+    pub fn get_overflow(&self) -> Overflow {
+        self.overflow
+    }
 }
 
 /**
@@ -39,8 +165,12 @@ impl<T> FieldDefinition<T> {
         FieldDefinition { generator }
     }
 
-    pub fn generate(&self) -> T {
-        self.generator.generate_data()
+    pub fn generate(&self, ctx: &GeneratorContext) -> T {
+        self.generator.generate_data(ctx)
+    }
+
+    pub fn referenced_fields(&self) -> Vec<&str> {
+        self.generator.referenced_fields()
     }
 }
 
@@ -57,15 +187,62 @@ impl<T: DefaultGenerator> Default for FieldDefinition<T> {
 pub enum FieldType {
     Integer(FieldDefinition<i64>),
     Float(FieldDefinition<f64>),
+    Boolean(FieldDefinition<bool>),
     String(FieldDefinition<std::string::String>),
+    Uuid(FieldDefinition<Uuid>),
+    Bytes(FieldDefinition<Vec<u8>>),
+    Timestamp(FieldDefinition<DateTime<Utc>>),
     List(Box<FieldType>),
     Record(RecordSchema),
 }
 
+impl FieldType {
+    /// Names of sibling fields that must be generated before this one
+    pub fn referenced_fields(&self) -> Vec<&str> {
+        match self {
+            FieldType::Integer(def) => def.referenced_fields(),
+            FieldType::Float(def) => def.referenced_fields(),
+            FieldType::Boolean(def) => def.referenced_fields(),
+            FieldType::String(def) => def.referenced_fields(),
+            FieldType::Uuid(def) => def.referenced_fields(),
+            FieldType::Bytes(def) => def.referenced_fields(),
+            FieldType::Timestamp(def) => def.referenced_fields(),
+            FieldType::List(t) => t.referenced_fields(),
+            FieldType::Record(_) => Vec::new(),
+        }
+    }
+}
+
 /**
- * RecordSchema
+ * TableSchema
  */
 #[derive(Debug, Clone)]
+pub struct TableSchema {
+    name: String,
+    record: RecordSchema,
+}
+
+impl TableSchema {
+    pub fn new<S: Into<String>>(name: S, record: RecordSchema) -> Self {
+        TableSchema {
+            name: name.into(),
+            record,
+        }
+    }
+
+    pub fn get_name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    pub fn into_record(self) -> RecordSchema {
+        self.record
+    }
+}
+
+/**
+ * RecordSchema
+ */
+#[derive(Debug, Clone, Default)]
 pub struct RecordSchema {
     field_list: Vec<FieldSchema>,
     contains_record: bool,
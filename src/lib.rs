@@ -0,0 +1,44 @@
+/*!
+ * Generates test data from a schema and writes it in one of many formats.
+ *
+ * A schema is either parsed from the text format the `datablaster` binary
+ * reads, or built in code:
+ *
+ * ```
+ * use datablaster::{Batches, ColumnData, FieldSchema, FieldType, Generator, RecordSchema};
+ * use std::num::NonZeroUsize;
+ *
+ * let schema = RecordSchema::new()
+ *     .with_field(FieldSchema::new("id", FieldType::Integer(Default::default())))
+ *     .with_field(FieldSchema::new(
+ *         "tags",
+ *         FieldType::List(Box::new(FieldType::String(Default::default()))),
+ *     ));
+ * let tuples: Vec<_> = Generator::new(&schema).take(10).collect();
+ * assert!(matches!(tuples[0].get("id"), Some(ColumnData::Integer(_))));
+ *
+ * let batch_size = NonZeroUsize::new(100).unwrap();
+ * for batch in Generator::new(&schema).batches(batch_size).take(3) {
+ *     assert_eq!(batch.len(), 100);
+ * }
+ * let sizes: Vec<_> = Batches::new(Generator::new(&schema).take(250), batch_size)
+ *     .map(|batch| batch.len())
+ *     .collect();
+ * assert_eq!(sizes, [100, 100, 50]);
+ * ```
+ *
+ * Tuples are written with any [`TupleWriter`], usually opened through
+ * [`output::OutputOptions`].
+ */
+pub mod data_gen;
+pub mod data_repr;
+pub mod definition;
+pub mod output;
+pub mod parser;
+pub mod writer;
+
+pub use data_gen::{Batches, Generator};
+pub use data_repr::{ColumnData, Tuple};
+pub use definition::schema::{FieldDefinition, FieldSchema, FieldType, RecordSchema, TableSchema};
+pub use parser::{parse, parse_proto};
+pub use writer::TupleWriter;
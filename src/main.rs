@@ -1,24 +1,29 @@
 mod args;
-mod data_gen;
-mod data_repr;
-mod definition;
-mod parser;
-mod writer;
 
-use data_gen::*;
+use datablaster::data_gen::out_of_order::OutOfOrder;
+use datablaster::data_gen::*;
+use datablaster::output::compression::Compression;
+use datablaster::output::{self, OutputOptions};
+use datablaster::parser::*;
+use datablaster::writer::counting::ByteCounter;
+use datablaster::writer::json::JsonStyle;
+use datablaster::writer::partitioned::PartitionedTupleWriter;
+use datablaster::writer::rotating::{FilePattern, RotatingTupleWriter};
+use datablaster::writer::sql::SqlDialect;
+use datablaster::writer::yaml::YamlStyle;
+use datablaster::writer::*;
+use datablaster::FieldType;
 use env_logger::fmt::Formatter;
+use env_logger::Target;
 use log::LevelFilter;
 use log::Record;
 #[allow(unused_imports)]
 use log::{debug, error, info, trace, warn};
-use parser::*;
 use std::error::Error;
 use std::fs;
-use std::fs::File;
-use std::io::Write;
-use writer::csv::TupleToCSVSerializer;
-use writer::json::TupleToJsonSerializer;
-use writer::*;
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+use std::time::Instant;
 
 fn run() -> Result<(), Box<dyn Error>> {
     let matches = args::parse_args();
@@ -27,10 +32,23 @@ fn run() -> Result<(), Box<dyn Error>> {
     let output_file_format = matches.value_of(args::FORMAT).unwrap(); //required
     let output_file = matches.value_of(args::OUTPUT_FILE).unwrap(); //required
     let schema_file = matches.value_of(args::SCHEMA).unwrap(); //required
-    let number_of_records = if let Some(nr) = matches.value_of(args::RECORDS_TO_CREATE) {
-        nr.parse::<i64>().unwrap_or(10)
-    } else {
-        10
+    let binary_encoding = matches
+        .value_of(args::BINARY_ENCODING)
+        .unwrap() // has a default
+        .parse::<BinaryEncoding>()?;
+    let max_bytes = matches
+        .value_of(args::SIZE)
+        .map(args::parse_byte_size)
+        .transpose()?;
+    let max_duration = matches
+        .value_of(args::DURATION)
+        .map(args::parse_duration)
+        .transpose()?;
+    let number_of_records = match matches.value_of(args::RECORDS_TO_CREATE) {
+        Some(nr) => Some(args::parse_count(nr, "number of records")?),
+        // Without any other limit fall back to a small sample
+        None if max_bytes.is_none() && max_duration.is_none() => Some(10),
+        None => None,
     };
 
     // Init logger
@@ -52,76 +70,318 @@ fn run() -> Result<(), Box<dyn Error>> {
             writeln!(buf, "] {}", record.args())
         })
         .filter(None, log_level)
+        // stdout may be carrying the output itself
+        .target(Target::Stderr)
         .init();
 
     let schema_file_string =
         fs::read_to_string(schema_file).map_err(|e| format!("{} - {}", schema_file, e))?;
-    let schema = parse(&schema_file_string)?;
-
-    //let schema = parse_result.map_err(|e| format!("\nParse Error: {:?}\non input: ```{}```", e.code, e.input))?;
-
-    /*
-    let schema = RecordSchema::new()
-        .with_field(FieldSchema::new("total", FieldType::Float(Default::default())))
-        .with_field(FieldSchema::new("transaction_id", FieldType::Integer(Default::default())))
-        .with_field(FieldSchema::new("line_items", FieldType::List(Box::new(FieldType::Record(
-            RecordSchema::new()
-                .with_field(FieldSchema::new("item", FieldType::String(Default::default())))
-                .with_field(FieldSchema::new("sub_items", FieldType::Record(
-                    RecordSchema::new()
-                        .with_field(FieldSchema::new("name", FieldType::String(Default::default())))
-                        .with_field(FieldSchema::new("amount", FieldType::Integer(Default::default())))
-                        .with_field(FieldSchema::new("cost", FieldType::Float(Default::default())))
-                )))
-                .with_field(FieldSchema::new("amount", FieldType::Integer(Default::default())))
-                .with_field(FieldSchema::new("cost", FieldType::Float(Default::default())))
-        )))))
-        .with_field(FieldSchema::new("sales_agents", FieldType::List(Box::new(FieldType::String(Default::default())))))
-        .with_field(FieldSchema::new("team", FieldType::Record(
-            RecordSchema::new()
-                .with_field(FieldSchema::new("project_manager", FieldType::String(Default::default())))
-                .with_field(FieldSchema::new("team_members", FieldType::List(Box::new(FieldType::String(Default::default())))))
-                .with_field(FieldSchema::new("budget", FieldType::Float(Default::default())))
-            )
-        ))
-    ;
+    let schema_dir = Path::new(schema_file)
+        .parent()
+        .unwrap_or_else(|| Path::new(""));
+    let (table, proto_message) = if schema_file.ends_with(".proto") {
+        let (table, message) = parse_proto(
+            &schema_file_string,
+            schema_dir,
+            matches.value_of(args::MESSAGE),
+        )?;
+        (table, Some(message))
+    } else {
+        (parse(&schema_file_string, schema_dir)?, None)
+    };
+    let table_name = table.get_name().to_string();
+    let schema = table.into_record();
 
-    let schema = RecordSchema::new()
-        .with_field(FieldSchema::new("id", FieldType::Integer(Default::default())))
-        .with_field(FieldSchema::new("fname", FieldType::String(FieldDefinition::new(Box::new(DataFunctionGenerator::new(|| "fname".to_string()))))))
-        .with_field(FieldSchema::new("lname", FieldType::String(FieldDefinition::new(Box::new(DataFunctionGenerator::new(|| "lname".to_string()))))))
-        .with_field(FieldSchema::new("salary", FieldType::Integer(Default::default())))
-    ;
-    */
+    // Change data capture wraps each row in an envelope, so check the writer against that
+    let mode = matches.value_of(args::MODE).unwrap(); // has a default
+    let output_schema = match mode {
+        "cdc" => cdc::output_schema(&schema),
+        "scd2" => scd2::output_schema(&schema),
+        _ => schema.clone(),
+    };
+    // The message doesn't describe the envelopes, so those are numbered in schema order
+    let proto_message = match proto_message {
+        Some(_) if mode != "insert" && output_file_format == "protobuf" => {
+            warn!(
+                "Encoding {} records with field numbers in schema order",
+                mode
+            );
+            None
+        }
+        proto_message => proto_message,
+    };
 
-    let file = File::create(output_file).map_err(|e| format!("{} - {}", output_file, e))?;
-    let mut tuple_serializer: Box<dyn TupleWriter> = match output_file_format {
-        "csv" => Box::new(TupleToCSVSerializer::new(file)),
-        "json" => Box::new(TupleToJsonSerializer::new(file, false)),
-        _ => return Err(format!("Unknown output format: {}", output_file_format).into()),
+    let compression = match matches.value_of(args::COMPRESS) {
+        Some(compression) => compression.parse::<Compression>()?,
+        None => Compression::from_path(output_file),
     };
-    if schema.contains_record() && !tuple_serializer.supports_record() {
+    let compression_level = matches
+        .value_of(args::COMPRESSION_LEVEL)
+        .map(|l| {
+            l.parse::<u32>()
+                .map_err(|_| format!("Invalid compression level: {}", l))
+        })
+        .transpose()?;
+    let json_style = matches
+        .value_of(args::JSON_STYLE)
+        .unwrap() // has a default
+        .parse::<JsonStyle>()?;
+    let yaml_style = matches
+        .value_of(args::YAML_STYLE)
+        .unwrap() // has a default
+        .parse::<YamlStyle>()?;
+    let framing = matches
+        .value_of(args::FRAMING)
+        .map(|f| f.parse::<Framing>())
+        .transpose()?;
+    let sql_dialect = matches
+        .value_of(args::SQL_DIALECT)
+        .unwrap() // has a default
+        .parse::<SqlDialect>()?;
+    let batch_size = matches
+        .value_of(args::BATCH_SIZE)
+        .map(|n| {
+            n.parse::<usize>()
+                .ok()
+                .filter(|n| *n > 0)
+                .ok_or("Batch size must be a positive number")
+        })
+        .transpose()?;
+    let output_options = OutputOptions::new(output_file_format)
+        .with_binary_encoding(binary_encoding)
+        .with_compression(compression, compression_level)?
+        .with_table_name(table_name)
+        .with_json_style(json_style)
+        .with_yaml_style(yaml_style)
+        .with_framing(framing)?
+        .with_xml_elements(
+            matches.value_of(args::XML_ROOT).map(String::from),
+            matches.value_of(args::XML_ROW).map(String::from),
+            matches.value_of(args::XML_ITEM).unwrap().to_string(), // has a default
+        )?
+        .with_xml_attributes(matches.is_present(args::XML_ATTRIBUTES))
+        .with_sql_dialect(sql_dialect)
+        .with_batch_size(batch_size)
+        .with_schema(output_schema.clone())
+        .with_create_table(matches.is_present(args::CREATE_TABLE))
+        .with_proto_message(proto_message);
+    let bytes_written = ByteCounter::new();
+    let max_records_per_file = matches
+        .value_of(args::MAX_RECORDS_PER_FILE)
+        .map(|n| args::parse_count(n, "max records per file"))
+        .transpose()?;
+    let max_bytes_per_file = match matches.value_of(args::MAX_BYTES_PER_FILE) {
+        Some(size) => match args::parse_byte_size(size)? {
+            0 => return Err("The max bytes per file must be at least 1".into()),
+            size => Some(size),
+        },
+        None => None,
+    };
+    if output_schema.contains_record() && !output_options.supports_record() {
         return Err("Records not supported".into());
     }
-    if schema.contains_list() && !tuple_serializer.supports_list() {
+    if output_schema.contains_list() && !output_options.supports_list() {
         return Err("Lists not supported".into());
     }
 
-    info!("Writing {} records to {}", number_of_records, output_file);
+    let rotate = max_records_per_file.is_some() || max_bytes_per_file.is_some();
+    if output_file == output::STDOUT && (rotate || matches.is_present(args::PARTITION_BY)) {
+        return Err("Output can't be split into several files when writing to stdout".into());
+    }
+    let mut tuple_serializer: Box<dyn TupleWriter> = if let Some(partition_by) =
+        matches.value_of(args::PARTITION_BY)
+    {
+        let partition_by: Vec<String> = partition_by
+            .split(',')
+            .map(|f| f.trim().to_string())
+            .collect();
+        for field in &partition_by {
+            match output_schema.iter().find(|f| f.get_name() == field) {
+                Some(f) if matches!(f.get_type(), FieldType::List(_) | FieldType::Record(_)) => {
+                    return Err(format!("Can't partition by {}, it isn't a scalar", field).into())
+                }
+                Some(_) => (),
+                None => {
+                    return Err(format!("Partition field {} is not in the schema", field).into())
+                }
+            }
+        }
+        let max_open = args::parse_count(
+            matches.value_of(args::MAX_OPEN_PARTITIONS).unwrap(), // has a default
+            "max open partitions",
+        )? as usize;
+        let total = bytes_written.clone();
+        let options = output_options.clone();
+        let file_name = format!("part-{{part:05}}.{}", options.extension());
+        Box::new(PartitionedTupleWriter::new(
+            Box::new(move |dir, parts| {
+                let pattern = FilePattern::new(&dir.join(&file_name).to_string_lossy())
+                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+                let total = total.clone();
+                let options = options.clone();
+                let open_file = move |path: &Path, file_counter: &ByteCounter| {
+                    options.open(path, &[&total, file_counter])
+                };
+                if rotate {
+                    Ok(Box::new(RotatingTupleWriter::new(
+                        Box::new(open_file),
+                        pattern,
+                        parts.clone(),
+                        max_records_per_file,
+                        max_bytes_per_file,
+                    )?))
+                } else {
+                    let path = pattern.path(parts.get());
+                    parts.set(parts.get() + 1);
+                    open_file(&path, &ByteCounter::new())
+                }
+            }),
+            output_file,
+            partition_by,
+            max_open,
+        ))
+    } else if rotate {
+        let pattern = FilePattern::from_path(output_file)?;
+        let total = bytes_written.clone();
+        let options = output_options.clone();
+        Box::new(RotatingTupleWriter::new(
+            Box::new(move |path, file_counter| options.open(path, &[&total, file_counter])),
+            pattern,
+            Default::default(),
+            max_records_per_file,
+            max_bytes_per_file,
+        )?)
+    } else {
+        output_options.open(Path::new(output_file), &[&bytes_written])?
+    };
+
+    let key_field = match matches.value_of(args::KEY_FIELD) {
+        Some(key_field) => key_field,
+        None => schema
+            .iter()
+            .next()
+            .ok_or("Schema has no fields")?
+            .get_name(),
+    };
+    let mut tuples: Box<dyn Iterator<Item = _>> = match mode {
+        "cdc" => {
+            let ratios = matches
+                .value_of(args::CDC_RATIOS)
+                .unwrap() // has a default
+                .parse::<cdc::CdcRatios>()?;
+            let events = cdc::CdcStream::new(&schema, key_field, &ratios)?;
+            match number_of_records {
+                Some(n) => Box::new(events.take(n as usize)),
+                None => Box::new(events),
+            }
+        }
+        "scd2" => {
+            let mutable_fields = matches
+                .value_of(args::SCD_MUTABLE_FIELDS)
+                .map(|f| f.split(',').map(|f| f.trim().to_string()).collect());
+            let max_versions = matches
+                .value_of(args::SCD_MAX_VERSIONS)
+                .unwrap() // has a default
+                .parse::<usize>()
+                .map_err(|e| format!("Invalid max versions - {}", e))?;
+            Box::new(scd2::Scd2History::new(
+                &schema,
+                key_field,
+                mutable_fields,
+                max_versions,
+                number_of_records.unwrap_or(u64::MAX),
+            )?)
+        }
+        _ => {
+            let rows = Generator::new(&schema);
+            match number_of_records {
+                Some(n) => Box::new(rows.take(n as usize)),
+                None => Box::new(rows),
+            }
+        }
+    };
+    if let Some(max_lateness) = matches.value_of(args::MAX_LATENESS) {
+        let max_lateness = humantime::parse_duration(max_lateness)
+            .map_err(|e| format!("Invalid max lateness {} - {}", max_lateness, e))?;
+        let event_time_field = match matches.value_of(args::EVENT_TIME_FIELD) {
+            Some(name) => output_schema
+                .iter()
+                .find(|f| f.get_name() == name)
+                .filter(|f| matches!(f.get_type(), FieldType::Timestamp(_)))
+                .ok_or(format!("{} is not a TIMESTAMP field", name))?,
+            None => output_schema
+                .iter()
+                .find(|f| matches!(f.get_type(), FieldType::Timestamp(_)))
+                .ok_or("--max-lateness requires a TIMESTAMP field")?,
+        };
+        tuples = Box::new(OutOfOrder::new(
+            tuples,
+            event_time_field.get_name(),
+            chrono::Duration::from_std(max_lateness)?,
+        ));
+    }
+
+    let mut limits = Vec::new();
+    match (mode, number_of_records) {
+        ("scd2", Some(n)) => limits.push(format!("the history of {} entities", n)),
+        (_, Some(n)) => limits.push(format!("{} records", n)),
+        _ => (),
+    }
+    if let Some(size) = matches.value_of(args::SIZE) {
+        limits.push(size.to_string());
+    }
+    if let Some(duration) = matches.value_of(args::DURATION) {
+        limits.push(duration.to_string());
+    }
+    let destination = match output_file {
+        output::STDOUT => "stdout",
+        path => path,
+    };
+    info!("Writing {} to {}", limits.join(" or "), destination);
+
+    let started = Instant::now();
     let mut next_print = 1;
-    for i in 0..number_of_records {
-        let output_data = create_data_from_schema(&schema);
-        if let Err(e) = tuple_serializer.write_tuple(&output_data) {
-            return Err(format!("Error writing tuple: {}", e).into());
+    let mut records_written = 0;
+    for output_data in tuples {
+        match tuple_serializer.write_tuple(&output_data) {
+            Ok(()) => (),
+            // Whatever we were piped into has stopped reading, e.g. `| head`
+            Err(e) if e.kind() == ErrorKind::BrokenPipe => {
+                info!("Output closed by reader");
+                break;
+            }
+            Err(e) => return Err(format!("Error writing tuple: {}", e).into()),
         };
-        if next_print <= i + 1 {
-            info!("Wrote {} records", i + 1);
+        records_written += 1;
+        if next_print <= records_written {
+            info!("Wrote {} records", records_written);
             next_print *= 10;
         }
+        if max_bytes
+            .is_some_and(|max| bytes_written.get() + tuple_serializer.buffered_bytes() >= max)
+            || max_duration.is_some_and(|max| started.elapsed() >= max)
+        {
+            break;
+        }
+    }
+    match tuple_serializer.finish() {
+        Err(e) if e.kind() != ErrorKind::BrokenPipe => return Err(e.into()),
+        _ => (),
     }
-    tuple_serializer.flush()?;
 
-    info!("{} records written to {}", number_of_records, output_file);
+    // Compressed streams are counted as they go into the compressor, orc compresses itself
+    let counted = if compression != Compression::None && output_file_format != "orc" {
+        "bytes before compression"
+    } else {
+        "bytes"
+    };
+    info!(
+        "{} records ({} {}) written to {}",
+        records_written,
+        bytes_written.get(),
+        counted,
+        destination
+    );
     Ok(())
 }
 
@@ -0,0 +1,450 @@
+pub mod compression;
+
+use crate::definition::proto::ProtoMessage;
+use crate::definition::schema::RecordSchema;
+use crate::writer::arrow::TupleToArrowSerializer;
+use crate::writer::cbor::TupleToCborSerializer;
+use crate::writer::counting::{ByteCounter, CountingWriter};
+use crate::writer::csv::TupleToCSVSerializer;
+use crate::writer::fixed_width::TupleToFixedWidthSerializer;
+use crate::writer::json::{JsonStyle, TupleToJsonSerializer};
+use crate::writer::msgpack::TupleToMessagePackSerializer;
+use crate::writer::orc::{OrcCompression, TupleToOrcWriter};
+use crate::writer::pgcopy::{TupleToPgCopyBinarySerializer, TupleToPgCopySerializer};
+use crate::writer::protobuf::TupleToProtobufSerializer;
+use crate::writer::sql::{SqlDialect, TupleToSqlSerializer};
+use crate::writer::sqlite::TupleToSqliteWriter;
+use crate::writer::toml::TupleToTomlSerializer;
+use crate::writer::xml::{is_valid_name, TupleToXmlSerializer};
+use crate::writer::yaml::{TupleToYamlSerializer, YamlStyle};
+use crate::writer::*;
+use compression::{CompressedTupleWriter, Compression};
+use std::fs::File;
+use std::io::{BufWriter, Error, ErrorKind, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+/// OUTPUT_FILE that streams to stdout instead of a file
+pub const STDOUT: &str = "-";
+
+/**
+ * OutputOptions
+ *
+ * Everything needed to open a TupleWriter for an output file
+ */
+#[derive(Debug, Clone)]
+pub struct OutputOptions {
+    format: String,
+    binary_encoding: BinaryEncoding,
+    compression: Compression,
+    compression_level: Option<u32>,
+    table_name: String,
+    json_style: JsonStyle,
+    yaml_style: YamlStyle,
+    framing: Option<Framing>,
+    xml_root: Option<String>,
+    xml_row: Option<String>,
+    xml_item: String,
+    xml_attributes: bool,
+    sql_dialect: SqlDialect,
+    batch_size: Option<usize>,
+    schema: Rc<RecordSchema>,
+    create_table: bool,
+    proto_message: Option<Rc<ProtoMessage>>,
+}
+
+impl OutputOptions {
+    pub fn new<S: Into<String>>(format: S) -> Self {
+        OutputOptions {
+            format: format.into(),
+            binary_encoding: Default::default(),
+            compression: Default::default(),
+            compression_level: None,
+            table_name: String::new(),
+            json_style: Default::default(),
+            yaml_style: Default::default(),
+            framing: None,
+            xml_root: None,
+            xml_row: None,
+            xml_item: "item".to_string(),
+            xml_attributes: false,
+            sql_dialect: Default::default(),
+            batch_size: None,
+            schema: Rc::new(RecordSchema::new()),
+            create_table: false,
+            proto_message: None,
+        }
+    }
+
+    pub fn with_binary_encoding(mut self, binary_encoding: BinaryEncoding) -> Self {
+        self.binary_encoding = binary_encoding;
+        self
+    }
+
+    pub fn with_compression(
+        mut self,
+        compression: Compression,
+        level: Option<u32>,
+    ) -> Result<Self, String> {
+        if let Some(level) = level {
+            compression.check_level(level)?;
+        }
+        if self.format == "sqlite" && compression != Compression::None {
+            return Err("sqlite output can't be compressed".to_string());
+        }
+        if self.format == "orc" && compression == Compression::Bzip2 {
+            return Err("orc output can only be compressed with gzip (zlib) or zstd".to_string());
+        }
+        self.compression = compression;
+        self.compression_level = level;
+        Ok(self)
+    }
+
+    pub fn with_table_name<S: Into<String>>(mut self, table_name: S) -> Self {
+        self.table_name = table_name.into();
+        self
+    }
+
+    pub fn with_json_style(mut self, json_style: JsonStyle) -> Self {
+        self.json_style = json_style;
+        self
+    }
+
+    pub fn with_yaml_style(mut self, yaml_style: YamlStyle) -> Self {
+        self.yaml_style = yaml_style;
+        self
+    }
+
+    /// Overrides the format's own framing
+    pub fn with_framing(mut self, framing: Option<Framing>) -> Result<Self, String> {
+        if self.format == "protobuf" && framing == Some(Framing::Sequence) {
+            return Err("protobuf messages have to be length or varint delimited".to_string());
+        }
+        self.framing = framing;
+        Ok(self)
+    }
+
+    /// Element names of xml output. The root and row elements default to the
+    /// table name, so other formats don't check them
+    pub fn with_xml_elements(
+        mut self,
+        root: Option<String>,
+        row: Option<String>,
+        item: String,
+    ) -> Result<Self, String> {
+        if self.format == "xml" {
+            let root_name = root.as_deref().unwrap_or(self.table_name.as_str());
+            let row_name = row.as_deref().unwrap_or(self.table_name.as_str());
+            for name in [root_name, row_name, &item] {
+                if !is_valid_name(name) {
+                    return Err(format!("{} is not a valid XML element name", name));
+                }
+            }
+        }
+        self.xml_root = root;
+        self.xml_row = row;
+        self.xml_item = item;
+        Ok(self)
+    }
+
+    pub fn with_xml_attributes(mut self, xml_attributes: bool) -> Self {
+        self.xml_attributes = xml_attributes;
+        self
+    }
+
+    pub fn with_sql_dialect(mut self, sql_dialect: SqlDialect) -> Self {
+        self.sql_dialect = sql_dialect;
+        self
+    }
+
+    /// Overrides the format's own batch size
+    pub fn with_batch_size(mut self, batch_size: Option<usize>) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Schema of the tuples being written, for formats that declare column types
+    pub fn with_schema(mut self, schema: RecordSchema) -> Self {
+        self.schema = Rc::new(schema);
+        self
+    }
+
+    pub fn with_create_table(mut self, create_table: bool) -> Self {
+        self.create_table = create_table;
+        self
+    }
+
+    /// Message protobuf output is encoded as. Derived from the schema when not given
+    pub fn with_proto_message(mut self, proto_message: Option<ProtoMessage>) -> Self {
+        self.proto_message = proto_message.map(Rc::new);
+        self
+    }
+
+    /// File extension for the output format, including any compression
+    pub fn extension(&self) -> String {
+        let extension = match self.format.as_str() {
+            "pgcopy" => "copy",
+            "pgcopy-binary" => "bin",
+            "arrow-stream" => "arrows",
+            "protobuf" => "pb",
+            "fixed-width" => "txt",
+            format => format,
+        };
+        match self.compression.extension() {
+            // Compressed inside the file
+            Some(_) if self.format == "orc" => extension.to_string(),
+            Some(compression) => format!("{}.{}", extension, compression),
+            None => extension.to_string(),
+        }
+    }
+
+    pub fn supports_list(&self) -> bool {
+        self.probe().is_ok_and(|w| w.supports_list())
+    }
+
+    pub fn supports_record(&self) -> bool {
+        self.probe().is_ok_and(|w| w.supports_record())
+    }
+
+    /// A writer that goes nowhere, to ask what the format supports
+    fn probe(&self) -> std::io::Result<Box<dyn TupleWriter>> {
+        match self.format.as_str() {
+            "sqlite" => self.sqlite_writer(Path::new(":memory:"), &[]),
+            _ => self.tuple_writer(Box::new(std::io::sink())),
+        }
+    }
+
+    /// Creates the file at `path`, or writes to stdout for `-`, counting every byte written
+    /// on each of `counters`. When compressing, the bytes going into the compressor are what
+    /// gets counted, since it holds on to an unknown amount of them.
+    /// Output is only ever appended to, so pipes and FIFOs work as well as regular files.
+    pub fn open(
+        &self,
+        path: &Path,
+        counters: &[&ByteCounter],
+    ) -> std::io::Result<Box<dyn TupleWriter>> {
+        if self.format == "sqlite" {
+            return self.sqlite_writer(path, counters);
+        }
+        let file: Box<dyn Write> = if path == Path::new(STDOUT) {
+            Box::new(BufWriter::new(std::io::stdout()))
+        } else {
+            let file = File::create(path)
+                .map_err(|e| Error::new(e.kind(), format!("{} - {}", path.display(), e)))?;
+            Box::new(BufWriter::new(file))
+        };
+        // orc compresses inside the file
+        let (mut output, stream): (Box<dyn Write>, _) =
+            if self.compression == Compression::None || self.format == "orc" {
+                (file, None)
+            } else {
+                let stream = self.compression.wrap(file, self.compression_level)?;
+                (Box::new(stream.clone()), Some(stream))
+            };
+        for counter in counters {
+            output = Box::new(CountingWriter::new(output, (*counter).clone()));
+        }
+        let writer = self.tuple_writer(output)?;
+        Ok(match stream {
+            Some(stream) => Box::new(CompressedTupleWriter::new(writer, stream)),
+            None => writer,
+        })
+    }
+
+    /// SQLite writes the database file itself, so it can't be streamed
+    fn sqlite_writer(
+        &self,
+        path: &Path,
+        counters: &[&ByteCounter],
+    ) -> std::io::Result<Box<dyn TupleWriter>> {
+        if path == Path::new(STDOUT) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "sqlite output can't be written to stdout",
+            ));
+        }
+        Ok(Box::new(
+            TupleToSqliteWriter::open(path, self.table_name.as_str(), self.schema.clone())?
+                .with_binary_encoding(self.binary_encoding)
+                .with_batch_size(self.batch_size.unwrap_or(100))
+                .with_counters(counters),
+        ))
+    }
+
+    fn tuple_writer(&self, output: Box<dyn Write>) -> std::io::Result<Box<dyn TupleWriter>> {
+        match self.format.as_str() {
+            "csv" => Ok(Box::new(
+                TupleToCSVSerializer::new(output).with_binary_encoding(self.binary_encoding),
+            )),
+            "fixed-width" => Ok(Box::new(
+                TupleToFixedWidthSerializer::new(output, &self.schema)
+                    .with_binary_encoding(self.binary_encoding),
+            )),
+            "json" => Ok(Box::new(
+                TupleToJsonSerializer::new(output, self.json_style)
+                    .with_binary_encoding(self.binary_encoding),
+            )),
+            "sql" => {
+                let mut writer =
+                    TupleToSqlSerializer::new(output, self.table_name.as_str(), self.sql_dialect)
+                        .with_binary_encoding(self.binary_encoding)
+                        .with_batch_size(self.batch_size.unwrap_or(100));
+                if self.create_table {
+                    writer = writer.with_create_table(self.schema.clone());
+                }
+                Ok(Box::new(writer))
+            }
+            "pgcopy" => Ok(Box::new(TupleToPgCopySerializer::new(output))),
+            "pgcopy-binary" => Ok(Box::new(TupleToPgCopyBinarySerializer::new(
+                output,
+                self.schema.clone(),
+            ))),
+            "arrow" | "arrow-stream" => Ok(Box::new(
+                TupleToArrowSerializer::new(
+                    output,
+                    self.schema.clone(),
+                    self.format == "arrow-stream",
+                )
+                .with_batch_size(self.batch_size.unwrap_or(65536)),
+            )),
+            "xml" => Ok(Box::new(
+                TupleToXmlSerializer::new(
+                    output,
+                    self.xml_root.as_deref().unwrap_or(self.table_name.as_str()),
+                    self.xml_row.as_deref().unwrap_or(self.table_name.as_str()),
+                )
+                .with_item_name(self.xml_item.as_str())
+                .with_attributes(self.xml_attributes)
+                .with_binary_encoding(self.binary_encoding),
+            )),
+            "yaml" => Ok(Box::new(
+                TupleToYamlSerializer::new(output, self.yaml_style)
+                    .with_binary_encoding(self.binary_encoding),
+            )),
+            "toml" => Ok(Box::new(
+                TupleToTomlSerializer::new(output, self.table_name.as_str())
+                    .with_binary_encoding(self.binary_encoding),
+            )),
+            "msgpack" => Ok(Box::new(TupleToMessagePackSerializer::new(
+                output,
+                self.framing.unwrap_or_default(),
+            ))),
+            "cbor" => Ok(Box::new(TupleToCborSerializer::new(
+                output,
+                self.framing.unwrap_or_default(),
+            ))),
+            "protobuf" => {
+                let message = match &self.proto_message {
+                    Some(message) => message.clone(),
+                    None => Rc::new(
+                        ProtoMessage::from_schema(self.table_name.as_str(), &self.schema)
+                            .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?,
+                    ),
+                };
+                Ok(Box::new(TupleToProtobufSerializer::new(
+                    output,
+                    message,
+                    self.framing.unwrap_or(Framing::VarintDelimited),
+                )))
+            }
+            "orc" => {
+                let compression = match self.compression {
+                    Compression::Gzip => OrcCompression::Zlib,
+                    Compression::Zstd => OrcCompression::Zstd,
+                    _ => OrcCompression::None,
+                };
+                Ok(Box::new(
+                    TupleToOrcWriter::new(output, self.schema.clone())
+                        .with_compression(compression, self.compression_level)
+                        .with_stripe_rows(self.batch_size),
+                ))
+            }
+            f => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Unknown output format: {}", f),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_repr::{ColumnData, Tuple};
+    use crate::writer::rotating::{FilePattern, RotatingTupleWriter};
+    use std::io::Read;
+
+    #[test]
+    fn xml_element_names_are_only_checked_for_xml() {
+        let elements = |format: &str| {
+            OutputOptions::new(format)
+                .with_table_name("2024_events")
+                .with_xml_elements(None, None, "item".to_string())
+        };
+        assert!(elements("csv").is_ok());
+        assert!(elements("xml").is_err());
+        assert!(OutputOptions::new("xml")
+            .with_table_name("2024_events")
+            .with_xml_elements(Some("events".to_string()), None, "item".to_string())
+            .is_err());
+        assert!(OutputOptions::new("xml")
+            .with_table_name("2024_events")
+            .with_xml_elements(
+                Some("events".to_string()),
+                Some("event".to_string()),
+                "item".to_string()
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn compressed_files_rotate_by_their_uncompressed_size() {
+        let dir = std::env::temp_dir().join(format!("datablaster-rotate-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let options = OutputOptions::new("csv")
+            .with_compression(Compression::Gzip, None)
+            .unwrap();
+        let pattern = FilePattern::new(&dir.join("out-{part}.csv.gz").to_string_lossy()).unwrap();
+        let mut writer = RotatingTupleWriter::new(
+            Box::new(move |path, counter| options.open(path, &[counter])),
+            pattern.clone(),
+            Default::default(),
+            None,
+            Some(1000),
+        )
+        .unwrap();
+        let mut tuple = Tuple::new();
+        tuple.add_field_data("id", ColumnData::Integer(1));
+        tuple.add_field_data("text", ColumnData::String("x".repeat(50)));
+        for _ in 0..100 {
+            writer.write_tuple(&tuple).unwrap();
+        }
+        writer.finish().unwrap();
+        drop(writer);
+
+        let mut rows = 0;
+        for part in 0.. {
+            let path = pattern.path(part);
+            if !path.exists() {
+                assert!(part > 1, "Only {} files", part);
+                break;
+            }
+            let mut csv = String::new();
+            flate2::read::GzDecoder::new(File::open(&path).unwrap())
+                .read_to_string(&mut csv)
+                .unwrap();
+            rows += csv.lines().count() - 1;
+            // Files end after the row that takes them to the limit, the last one earlier
+            assert!(
+                csv.len() < 1000 + 60,
+                "{} bytes in {}",
+                csv.len(),
+                path.display()
+            );
+            assert!(csv.len() >= 1000 || !pattern.path(part + 1).exists());
+            assert!(std::fs::metadata(&path).unwrap().len() < 1000);
+        }
+        assert_eq!(rows, 100);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
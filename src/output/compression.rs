@@ -0,0 +1,309 @@
+use crate::data_repr::Tuple;
+use crate::writer::TupleWriter;
+use bzip2::write::BzEncoder;
+use flate2::write::GzEncoder;
+use log::error;
+use std::cell::RefCell;
+use std::io::Write;
+use std::rc::Rc;
+
+/**
+ * Compression
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+    Bzip2,
+}
+
+impl Compression {
+    /// Picks the compression from a file name such as `out.csv.gz`
+    pub fn from_path(path: &str) -> Self {
+        match path.rsplit('.').next() {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            Some("bz2") => Compression::Bzip2,
+            _ => Compression::None,
+        }
+    }
+
+    pub fn extension(&self) -> Option<&'static str> {
+        match self {
+            Compression::None => None,
+            Compression::Gzip => Some("gz"),
+            Compression::Zstd => Some("zst"),
+            Compression::Bzip2 => Some("bz2"),
+        }
+    }
+
+    pub fn check_level(&self, level: u32) -> Result<(), String> {
+        let levels = match self {
+            Compression::None => return Ok(()),
+            Compression::Gzip => 0..=9,
+            Compression::Zstd => 1..=22,
+            Compression::Bzip2 => 1..=9,
+        };
+        if levels.contains(&level) {
+            Ok(())
+        } else {
+            Err(format!(
+                "{:?} compression level must be between {} and {}",
+                self,
+                levels.start(),
+                levels.end()
+            ))
+        }
+    }
+
+    /// Wraps `output` in an encoder. There is none for `Compression::None`
+    pub fn wrap(
+        &self,
+        output: Box<dyn Write>,
+        level: Option<u32>,
+    ) -> std::io::Result<CompressedStream> {
+        let encoder = match self {
+            Compression::None => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "No compression to wrap the output in",
+                ))
+            }
+            Compression::Gzip => Encoder::Gzip(GzEncoder::new(
+                output,
+                level.map_or(flate2::Compression::default(), flate2::Compression::new),
+            )),
+            Compression::Zstd => Encoder::Zstd(zstd::Encoder::new(
+                output,
+                level.map_or(zstd::DEFAULT_COMPRESSION_LEVEL, |l| l as i32),
+            )?),
+            Compression::Bzip2 => Encoder::Bzip2(BzEncoder::new(
+                output,
+                level.map_or(bzip2::Compression::default(), bzip2::Compression::new),
+            )),
+        };
+        Ok(CompressedStream(Rc::new(RefCell::new(
+            CompressedWriter::new(encoder),
+        ))))
+    }
+}
+
+impl std::str::FromStr for Compression {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Compression::None),
+            "gzip" | "gz" => Ok(Compression::Gzip),
+            "zstd" | "zst" => Ok(Compression::Zstd),
+            "bz2" | "bzip2" => Ok(Compression::Bzip2),
+            _ => Err(format!("Unknown compression: {}", s)),
+        }
+    }
+}
+
+/**
+ * CompressedStream
+ *
+ * A compressed stream that clones share. One clone is written to by a
+ * TupleWriter, another finishes the stream once that TupleWriter is done.
+ */
+#[derive(Clone)]
+pub struct CompressedStream(Rc<RefCell<CompressedWriter>>);
+
+impl CompressedStream {
+    /// Writes the stream's trailer and flushes it
+    pub fn finish(&self) -> std::io::Result<()> {
+        self.0.borrow_mut().finish()
+    }
+}
+
+impl Write for CompressedStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.borrow_mut().flush()
+    }
+}
+
+/**
+ * CompressedTupleWriter
+ *
+ * Finishes the compressed stream a TupleWriter writes to when the TupleWriter
+ * is finished, so errors writing the trailer are reported.
+ */
+pub struct CompressedTupleWriter {
+    writer: Box<dyn TupleWriter>,
+    stream: CompressedStream,
+}
+
+impl CompressedTupleWriter {
+    pub fn new(writer: Box<dyn TupleWriter>, stream: CompressedStream) -> Self {
+        CompressedTupleWriter { writer, stream }
+    }
+}
+
+impl TupleWriter for CompressedTupleWriter {
+    fn supports_list(&self) -> bool {
+        self.writer.supports_list()
+    }
+    fn supports_record(&self) -> bool {
+        self.writer.supports_record()
+    }
+
+    fn write_tuple(&mut self, tuple: &Tuple) -> std::io::Result<()> {
+        self.writer.write_tuple(tuple)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+
+    fn buffered_bytes(&self) -> u64 {
+        self.writer.buffered_bytes()
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        self.writer.finish()?;
+        self.stream.finish()
+    }
+}
+
+enum Encoder {
+    Gzip(GzEncoder<Box<dyn Write>>),
+    Zstd(zstd::Encoder<'static, Box<dyn Write>>),
+    Bzip2(BzEncoder<Box<dyn Write>>),
+}
+
+/**
+ * CompressedWriter
+ *
+ * Writes the compressed stream's trailer when finished. Dropping it without
+ * finishing, as on errors, still tries to complete the stream.
+ */
+struct CompressedWriter {
+    encoder: Encoder,
+    finished: bool,
+}
+
+impl CompressedWriter {
+    fn new(encoder: Encoder) -> Self {
+        CompressedWriter {
+            encoder,
+            finished: false,
+        }
+    }
+
+    /// Only the first call writes the trailer, even if it fails
+    fn finish(&mut self) -> std::io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+        match &mut self.encoder {
+            Encoder::Gzip(e) => e.try_finish().and_then(|_| e.get_mut().flush()),
+            Encoder::Zstd(e) => e.do_finish().and_then(|_| e.get_mut().flush()),
+            Encoder::Bzip2(e) => e.try_finish().and_then(|_| e.get_mut().flush()),
+        }
+    }
+}
+
+impl Write for CompressedWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.finished {
+            return Err(std::io::Error::other(
+                "Compressed output is already finished",
+            ));
+        }
+        match &mut self.encoder {
+            Encoder::Gzip(e) => e.write(buf),
+            Encoder::Zstd(e) => e.write(buf),
+            Encoder::Bzip2(e) => e.write(buf),
+        }
+    }
+
+    /// Writers flushing on drop may do so after the stream is finished
+    fn flush(&mut self) -> std::io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        match &mut self.encoder {
+            Encoder::Gzip(e) => e.flush(),
+            Encoder::Zstd(e) => e.flush(),
+            Encoder::Bzip2(e) => e.flush(),
+        }
+    }
+}
+
+impl Drop for CompressedWriter {
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            error!("Error finishing compressed output: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::io::Read;
+
+    /// Collects what it's given until told to fail
+    #[derive(Clone, Default)]
+    struct Sink {
+        data: Rc<RefCell<Vec<u8>>>,
+        fail: Rc<Cell<bool>>,
+    }
+
+    impl Write for Sink {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if self.fail.get() {
+                return Err(std::io::Error::other("disk full"));
+            }
+            self.data.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn stream(compression: Compression) -> (CompressedStream, Sink) {
+        let sink = Sink::default();
+        let stream = compression.wrap(Box::new(sink.clone()), None).unwrap();
+        (stream, sink)
+    }
+
+    #[test]
+    fn finish_completes_the_stream() {
+        let (mut stream, sink) = stream(Compression::Gzip);
+        stream.write_all(b"hello").unwrap();
+        stream.finish().unwrap();
+        drop(stream);
+        let mut text = String::new();
+        flate2::read::GzDecoder::new(sink.data.borrow().as_slice())
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!(text, "hello");
+    }
+
+    #[test]
+    fn trailer_errors_are_returned() {
+        for compression in &[Compression::Gzip, Compression::Zstd, Compression::Bzip2] {
+            let (mut stream, sink) = stream(*compression);
+            stream.write_all(b"hello").unwrap();
+            sink.fail.set(true);
+            assert!(stream.finish().is_err(), "{:?}", compression);
+            sink.fail.set(false);
+            // Finished, even though it failed
+            assert!(stream.finish().is_ok());
+            assert!(stream.flush().is_ok());
+            assert!(stream.write_all(b"more").is_err());
+        }
+    }
+}
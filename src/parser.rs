@@ -1,19 +1,31 @@
-use crate::definition::schema::RecordSchema;
-use crate::definition::schema::{FieldSchema, FieldType};
+use crate::definition::gen::{DataFunctionGenerator, RandomBytesGenerator};
+use crate::definition::schema::{FieldDefinition, FieldFormat, FieldSchema, FieldType};
+use crate::definition::schema::{RecordSchema, TableSchema};
 #[allow(unused_imports)]
 use log::{debug, error, info, trace};
 use nom::{
     bytes::complete::{tag, tag_no_case, take_while1},
-    character::complete::{multispace0, multispace1},
-    combinator::{eof, iterator},
+    character::complete::{digit1, multispace0, multispace1},
+    combinator::{eof, iterator, map_res, opt, verify},
     error::{Error, ErrorKind, ParseError},
+    multi::many0,
     sequence::{delimited, preceded, terminated},
     Err, Finish, IResult,
 };
+use std::path::Path;
 use std::str;
+use uuid::Uuid;
 
 #[macro_use]
 mod helper;
+mod annotation;
+mod generator;
+mod proto;
+mod template;
+
+use annotation::{apply_annotation, is_annotation};
+use generator::{apply_generator, generator_call};
+pub use proto::parse_proto;
 
 fn obj_declaration(input: &str) -> IResult<&str, &str> {
     tag_no_case("table")(input)
@@ -24,29 +36,110 @@ fn token_named(input: &str) -> IResult<&str, &str> {
     take_while1(|c: char| c.is_alphanumeric() || c == '_')(input)
 }
 
-fn field_def(input: &str) -> IResult<&str, FieldSchema> {
+fn field_def<'a>(input: &'a str, base_dir: &Path) -> IResult<&'a str, FieldSchema> {
     // Get the field_name
     let (i, field_name) = preceded(multispace0, token_named)(input)?;
     // Get the field_type
-    let (i, field_type) = peek_parsed!(preceded(multispace1, field_type)(i))?;
-    Ok((i, FieldSchema::new(field_name, field_type)))
+    let (i, field_type) =
+        peek_parsed!(preceded(multispace1, |i| generated_field_type(i, base_dir))(i))?;
+    // Get the optional annotations
+    let (i, annotations) = many0(preceded(multispace1, generator_call))(i)?;
+    let mut format = FieldFormat::default();
+    for (name, args) in annotations {
+        format = match apply_annotation(format, name, &args) {
+            Ok(format) => format,
+            Err(e) => {
+                error!("{}: {}", field_name, e);
+                return Err(Err::Failure(Error::from_error_kind(
+                    input,
+                    ErrorKind::Verify,
+                )));
+            }
+        };
+    }
+    Ok((
+        i,
+        FieldSchema::new(field_name, field_type).with_format(format),
+    ))
+}
+
+fn generated_field_type<'a>(input: &'a str, base_dir: &Path) -> IResult<&'a str, FieldType> {
+    let (i, field_type) = field_type(input, base_dir)?;
+    // Get the optional generator
+    let (i, generator) = opt(preceded(
+        multispace1,
+        verify(generator_call, |(name, _): &(&str, Vec<String>)| {
+            !is_annotation(name)
+        }),
+    ))(i)?;
+    match generator {
+        Some((name, args)) => match apply_generator(field_type, name, &args, base_dir) {
+            Ok(field_type) => Ok((i, field_type)),
+            Err(e) => {
+                error!("{}: {}", name, e);
+                Err(Err::Failure(Error::from_error_kind(
+                    input,
+                    ErrorKind::Verify,
+                )))
+            }
+        },
+        None => Ok((i, field_type)),
+    }
 }
 
-fn field_type(input: &str) -> IResult<&str, FieldType> {
+fn field_type<'a>(input: &'a str, base_dir: &Path) -> IResult<&'a str, FieldType> {
     // Get type name and turn it into a FieldType
     let (i, type_name) = token_named(input)?;
     match type_name {
         f if f.to_lowercase() == "string" => Ok((i, FieldType::String(Default::default()))),
         f if f.to_lowercase() == "integer" => Ok((i, FieldType::Integer(Default::default()))),
         f if f.to_lowercase() == "float" => Ok((i, FieldType::Float(Default::default()))),
+        f if f.to_lowercase() == "boolean" => Ok((i, FieldType::Boolean(Default::default()))),
+        f if f.to_lowercase() == "timestamp" => Ok((i, FieldType::Timestamp(Default::default()))),
+        f if f.to_lowercase() == "uuid" => {
+            let (i, version) = opt(delimited(tag("("), token_named, tag(")")))(i)?;
+            match version.map(|v| v.to_lowercase()).as_deref() {
+                None | Some("v4") => Ok((i, FieldType::Uuid(Default::default()))),
+                Some("v7") => Ok((
+                    i,
+                    FieldType::Uuid(FieldDefinition::new(Box::new(DataFunctionGenerator::new(
+                        Uuid::now_v7,
+                    )))),
+                )),
+                Some(v) => {
+                    error!("Unknown UUID version: {}", v);
+                    Err(Err::Error(Error::from_error_kind(
+                        input,
+                        ErrorKind::TakeUntil,
+                    )))
+                }
+            }
+        }
+        f if f.to_lowercase() == "bytes" || f.to_lowercase() == "binary" => {
+            let (i, len) = opt(delimited(
+                tag("("),
+                map_res(digit1, |d: &str| d.parse::<usize>()),
+                tag(")"),
+            ))(i)?;
+            match len {
+                Some(len) => Ok((
+                    i,
+                    FieldType::Bytes(FieldDefinition::new(Box::new(RandomBytesGenerator::new(
+                        len,
+                    )))),
+                )),
+                None => Ok((i, FieldType::Bytes(Default::default()))),
+            }
+        }
         f if f.to_lowercase() == "list" => {
-            let (i, field) = delimited(tag("("), field_type, tag(")"))(i)?;
+            let (i, field) =
+                delimited(tag("("), |i| generated_field_type(i, base_dir), tag(")"))(i)?;
             Ok((i, FieldType::List(Box::new(field))))
         }
         f if f.to_lowercase() == "record" => {
             let (i, fields) =
                 delimited(tag("("), take_till_delimiter_closed('(', ')'), tag(")"))(i)?;
-            let (_, record) = table_record(fields)?;
+            let (_, record) = table_record(fields, base_dir)?;
             Ok((i, FieldType::Record(record)))
         }
         f => {
@@ -66,8 +159,16 @@ fn take_till_delimiter_closed(
     move |input| {
         let mut delim_count = 0;
         let mut ending_index = 0;
-        for (i, c) in input.chars().enumerate() {
+        let mut quote = None;
+        for (i, c) in input.char_indices() {
             ending_index = i;
+            // Delimiters inside of quoted generator arguments don't count
+            match quote {
+                Some(q) if c == q => quote = None,
+                Some(_) => continue,
+                None if c == '\'' || c == '"' => quote = Some(c),
+                None => (),
+            }
             match c {
                 c if c == closing_delim => {
                     if delim_count == 0 {
@@ -91,12 +192,26 @@ fn take_till_delimiter_closed(
     }
 }
 
-fn table_record(input: &str) -> IResult<&str, RecordSchema> {
+fn table_record<'a>(input: &'a str, base_dir: &Path) -> IResult<&'a str, RecordSchema> {
     debug!("Creating RecordSchema");
     let mut record = RecordSchema::new();
-    let mut it = iterator(input, terminated(field_def, tag(",")));
+    let mut it = iterator(input, terminated(|i| field_def(i, base_dir), tag(",")));
     for f in &mut it {
         debug!("FieldSchema: {:?}", f);
+        // Generators can only read fields that were generated before them
+        for referenced in f.get_type().referenced_fields() {
+            if !record.iter().any(|c| c.get_name() == referenced) {
+                error!(
+                    "{}: references {} which isn't defined before it",
+                    f.get_name(),
+                    referenced
+                );
+                return Err(Err::Failure(Error::from_error_kind(
+                    input,
+                    ErrorKind::Verify,
+                )));
+            }
+        }
         record.add_field(f);
     }
     let (field_input, _) = it.finish()?;
@@ -104,7 +219,7 @@ fn table_record(input: &str) -> IResult<&str, RecordSchema> {
     Ok(("", record))
 }
 
-pub fn parser(input: &str) -> IResult<&str, RecordSchema> {
+pub fn parser<'a>(input: &'a str, base_dir: &Path) -> IResult<&'a str, TableSchema> {
     let (input, declaration_type) = preceded(multispace0, obj_declaration)(input)?;
     debug!("DECLARATION_TYPE: {}", declaration_type);
     let (input, table_name) = preceded(multispace1, token_named)(input)?;
@@ -114,13 +229,74 @@ pub fn parser(input: &str) -> IResult<&str, RecordSchema> {
         take_till_delimiter_closed('(', ')'),
         preceded(multispace0, tag(")"))
     )(input))?;
-    let (_, record) = table_record(fields)?;
+    let (_, record) = table_record(fields, base_dir)?;
     let (input, _) = preceded(multispace0, tag(";"))(input)?;
     preceded(multispace0, eof)(input)?;
-    Ok(("", record))
+    Ok(("", TableSchema::new(table_name, record)))
+}
+
+/**
+ * Parses a schema definition. Files referenced by generators are resolved relative to `base_dir`
+ */
+pub fn parse(input: &str, base_dir: &Path) -> Result<TableSchema, String> {
+    let (_, table) = parser(input, base_dir)
+        .finish()
+        .map_err(|e| e.to_string())?;
+    Ok(table)
 }
 
-pub fn parse(input: &str) -> Result<RecordSchema, String> {
-    let (_, record) = parser(input).finish().map_err(|e| e.to_string())?;
-    Ok(record)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_gen::create_data_from_schema;
+    use crate::data_repr::ColumnData;
+
+    fn generate(fields: &str) -> Vec<(String, ColumnData)> {
+        let table = parse(&format!("table t ({});", fields), Path::new(".")).unwrap();
+        create_data_from_schema(&table.into_record(), 0)
+            .into_iter()
+            .collect()
+    }
+
+    #[test]
+    fn uuid_versions() {
+        let versions: Vec<_> = generate("a UUID, b UUID(v4), c uuid(V7),")
+            .into_iter()
+            .map(|(name, data)| match data {
+                ColumnData::Uuid(v) => (name, v.get_version_num()),
+                data => panic!("{} is {:?}", name, data),
+            })
+            .collect();
+        assert_eq!(
+            versions,
+            [
+                ("a".to_string(), 4),
+                ("b".to_string(), 4),
+                ("c".to_string(), 7)
+            ]
+        );
+        assert!(parse("table t (a UUID(v9),);", Path::new(".")).is_err());
+        assert!(parse("table t (a UUID(),);", Path::new(".")).is_err());
+    }
+
+    #[test]
+    fn binary_lengths() {
+        let lengths: Vec<_> = generate("a BINARY(4), b bytes(0), c BYTES(32),")
+            .into_iter()
+            .map(|(name, data)| match data {
+                ColumnData::Bytes(v) => (name, v.len()),
+                data => panic!("{} is {:?}", name, data),
+            })
+            .collect();
+        assert_eq!(
+            lengths,
+            [
+                ("a".to_string(), 4),
+                ("b".to_string(), 0),
+                ("c".to_string(), 32)
+            ]
+        );
+        assert!(parse("table t (a BINARY(-1),);", Path::new(".")).is_err());
+        assert!(parse("table t (a BINARY(x),);", Path::new(".")).is_err());
+    }
 }
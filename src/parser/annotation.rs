@@ -0,0 +1,47 @@
+use crate::definition::schema::{Alignment, FieldFormat, Overflow};
+
+/// Annotations look like generator calls, so generators can't use these names
+pub fn is_annotation(name: &str) -> bool {
+    matches!(
+        name.to_lowercase().as_str(),
+        "width" | "align" | "pad" | "overflow"
+    )
+}
+
+/**
+ * Annotations follow a field's type and generator, e.g. `code STRING width(8) pad('0') align(right)`
+ */
+pub fn apply_annotation(
+    format: FieldFormat,
+    name: &str,
+    args: &[String],
+) -> Result<FieldFormat, String> {
+    match (name.to_lowercase().as_str(), args) {
+        ("width", [width]) => match width.parse::<usize>() {
+            Ok(width) if width > 0 => Ok(format.with_width(width)),
+            _ => Err(format!("Expected a positive width but got '{}'", width)),
+        },
+        ("align", [align]) => Ok(format.with_align(align.to_lowercase().parse::<Alignment>()?)),
+        ("pad", [padding]) => {
+            let mut chars = padding.chars();
+            match (chars.next(), chars.next()) {
+                (Some(padding), None) => Ok(format.with_padding(padding)),
+                _ => Err(format!(
+                    "Expected a single padding character but got '{}'",
+                    padding
+                )),
+            }
+        }
+        ("overflow", [overflow]) => {
+            Ok(format.with_overflow(overflow.to_lowercase().parse::<Overflow>()?))
+        }
+        (n, args) => Err(format!(
+            "Unknown annotation {}({})",
+            n,
+            args.iter()
+                .map(|a| format!("'{}'", a))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
@@ -0,0 +1,350 @@
+use super::template::parse_template;
+use crate::definition::gen::series::{
+    RandomWalkGenerator, RoundedGenerator, SeasonalGenerator, SpikeGenerator,
+    TimestampSeriesGenerator, TrendGenerator,
+};
+use crate::definition::gen::{DataGenerator, SampleGenerator, TemplateGenerator};
+use crate::definition::schema::{FieldDefinition, FieldType};
+use chrono::{DateTime, Duration, Utc};
+use nom::{
+    branch::alt,
+    bytes::complete::{take_while, take_while1},
+    character::complete::{char, multispace0},
+    combinator::map,
+    multi::separated_list0,
+    sequence::{delimited, pair, tuple},
+    IResult,
+};
+use std::fmt::Debug;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/**
+ * Generator calls follow a field type, e.g. `sku STRING from_file('skus.txt')`
+ */
+pub fn generator_call(input: &str) -> IResult<&str, (&str, Vec<String>)> {
+    tuple((
+        take_while1(|c: char| c.is_alphanumeric() || c == '_'),
+        delimited(
+            pair(char('('), multispace0),
+            separated_list0(
+                delimited(multispace0, char(','), multispace0),
+                generator_arg,
+            ),
+            pair(multispace0, char(')')),
+        ),
+    ))(input)
+}
+
+/// Arguments are quoted with ' or ", and a quote is doubled to include it, e.g. 'O''Brien'
+fn generator_arg(input: &str) -> IResult<&str, String> {
+    alt((
+        quoted_arg('\''),
+        quoted_arg('"'),
+        map(
+            take_while1(|c: char| c.is_alphanumeric() || "_.-+".contains(c)),
+            |arg: &str| arg.to_string(),
+        ),
+    ))(input)
+}
+
+fn quoted_arg(quote: char) -> impl Fn(&str) -> IResult<&str, String> {
+    move |input| {
+        let (mut i, _) = char(quote)(input)?;
+        let mut arg = String::new();
+        loop {
+            let (rest, part) = take_while(|c| c != quote)(i)?;
+            arg.push_str(part);
+            let (rest, _) = char(quote)(rest)?;
+            match rest.strip_prefix(quote) {
+                Some(rest) => {
+                    arg.push(quote);
+                    i = rest;
+                }
+                None => return Ok((rest, arg)),
+            }
+        }
+    }
+}
+
+pub fn apply_generator(
+    field_type: FieldType,
+    name: &str,
+    args: &[String],
+    base_dir: &Path,
+) -> Result<FieldType, String> {
+    match (name.to_lowercase().as_str(), args) {
+        ("from_file", [path]) => {
+            let path = base_dir.join(path);
+            let contents =
+                fs::read_to_string(&path).map_err(|e| format!("{} - {}", path.display(), e))?;
+            let values = contents
+                .lines()
+                .map(|l| l.trim())
+                .filter(|l| !l.is_empty())
+                .map(|l| l.to_string())
+                .collect();
+            sample_field_type(field_type, values, None)
+                .map_err(|e| format!("{} - {}", path.display(), e))
+        }
+        ("from_csv", [path, column]) => {
+            let path = base_dir.join(path);
+            let (values, _) = read_csv_columns(&path, column, None)
+                .map_err(|e| format!("{} - {}", path.display(), e))?;
+            sample_field_type(field_type, values, None)
+                .map_err(|e| format!("{} - {}", path.display(), e))
+        }
+        ("from_csv", [path, column, weight_column]) => {
+            let path = base_dir.join(path);
+            let (values, weights) = read_csv_columns(&path, column, Some(weight_column))
+                .map_err(|e| format!("{} - {}", path.display(), e))?;
+            sample_field_type(field_type, values, weights)
+                .map_err(|e| format!("{} - {}", path.display(), e))
+        }
+        ("format", [template]) | ("template", [template]) => match field_type {
+            FieldType::String(_) => Ok(FieldType::String(FieldDefinition::new(Box::new(
+                TemplateGenerator::new(parse_template(template)?),
+            )))),
+            _ => Err("Templates are only supported for STRING fields".to_string()),
+        },
+        ("series", [start, interval]) => timestamp_series(field_type, start, interval, "0s"),
+        ("series", [start, interval, jitter]) => {
+            timestamp_series(field_type, start, interval, jitter)
+        }
+        ("random_walk", [start, step]) => numeric_series(
+            field_type,
+            Box::new(RandomWalkGenerator::new(number(start)?, number(step)?)),
+        ),
+        ("trend", [start, slope]) => numeric_series(
+            field_type,
+            Box::new(TrendGenerator::new(number(start)?, number(slope)?, 0.0)),
+        ),
+        ("trend", [start, slope, noise]) => numeric_series(
+            field_type,
+            Box::new(TrendGenerator::new(
+                number(start)?,
+                number(slope)?,
+                number(noise)?,
+            )),
+        ),
+        ("seasonal", [base, amplitude, period]) => numeric_series(
+            field_type,
+            Box::new(SeasonalGenerator::new(
+                number(base)?,
+                number(amplitude)?,
+                number(period)?,
+                0.0,
+            )?),
+        ),
+        ("seasonal", [base, amplitude, period, noise]) => numeric_series(
+            field_type,
+            Box::new(SeasonalGenerator::new(
+                number(base)?,
+                number(amplitude)?,
+                number(period)?,
+                number(noise)?,
+            )?),
+        ),
+        ("spikes", [base, probability, magnitude]) => numeric_series(
+            field_type,
+            Box::new(SpikeGenerator::new(
+                number(base)?,
+                number(probability)?,
+                number(magnitude)?,
+                0.0,
+            )?),
+        ),
+        ("spikes", [base, probability, magnitude, noise]) => numeric_series(
+            field_type,
+            Box::new(SpikeGenerator::new(
+                number(base)?,
+                number(probability)?,
+                number(magnitude)?,
+                number(noise)?,
+            )?),
+        ),
+        (n, args) => Err(format!(
+            "Unknown generator {}({})",
+            n,
+            args.iter()
+                .map(|a| format!("'{}'", a))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+fn number(arg: &str) -> Result<f64, String> {
+    arg.parse::<f64>()
+        .map_err(|_| format!("Expected a number but got '{}'", arg))
+}
+
+fn duration(arg: &str) -> Result<Duration, String> {
+    let duration = humantime::parse_duration(arg).map_err(|e| format!("'{}' - {}", arg, e))?;
+    Duration::from_std(duration).map_err(|e| format!("'{}' - {}", arg, e))
+}
+
+fn timestamp(arg: &str) -> Result<DateTime<Utc>, String> {
+    if arg.to_lowercase() == "now" {
+        return Ok(Utc::now());
+    }
+    DateTime::parse_from_rfc3339(arg)
+        .map(|ts| ts.with_timezone(&Utc))
+        .map_err(|e| format!("'{}' - {}", arg, e))
+}
+
+fn timestamp_series(
+    field_type: FieldType,
+    start: &str,
+    interval: &str,
+    jitter: &str,
+) -> Result<FieldType, String> {
+    match field_type {
+        FieldType::Timestamp(_) => Ok(FieldType::Timestamp(FieldDefinition::new(Box::new(
+            TimestampSeriesGenerator::new(
+                timestamp(start)?,
+                duration(interval)?,
+                duration(jitter)?,
+            )?,
+        )))),
+        _ => Err("Series are only supported for TIMESTAMP fields".to_string()),
+    }
+}
+
+fn numeric_series(
+    field_type: FieldType,
+    generator: Box<dyn DataGenerator<f64>>,
+) -> Result<FieldType, String> {
+    match field_type {
+        FieldType::Float(_) => Ok(FieldType::Float(FieldDefinition::new(generator))),
+        FieldType::Integer(_) => Ok(FieldType::Integer(FieldDefinition::new(Box::new(
+            RoundedGenerator::new(generator),
+        )))),
+        _ => Err("Only supported for FLOAT and INTEGER fields".to_string()),
+    }
+}
+
+fn read_csv_columns(
+    path: &Path,
+    column: &str,
+    weight_column: Option<&String>,
+) -> Result<(Vec<String>, Option<Vec<f64>>), String> {
+    let mut reader = csv::Reader::from_path(path).map_err(|e| e.to_string())?;
+    let headers = reader.headers().map_err(|e| e.to_string())?.clone();
+    let find_column = |name: &str| {
+        headers
+            .iter()
+            .position(|h| h == name)
+            .ok_or(format!("No column named {}", name))
+    };
+    let value_index = find_column(column)?;
+    let weight_index = weight_column.map(|c| find_column(c)).transpose()?;
+
+    let mut values = Vec::new();
+    let mut weights = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| e.to_string())?;
+        values.push(record.get(value_index).unwrap_or_default().to_string());
+        if let Some(i) = weight_index {
+            let weight = record.get(i).unwrap_or_default();
+            weights.push(
+                weight
+                    .trim()
+                    .parse::<f64>()
+                    .map_err(|e| format!("Invalid weight '{}': {}", weight, e))?,
+            );
+        }
+    }
+    Ok((values, weight_index.map(|_| weights)))
+}
+
+fn sample_field_type(
+    field_type: FieldType,
+    values: Vec<String>,
+    weights: Option<Vec<f64>>,
+) -> Result<FieldType, String> {
+    if values.is_empty() {
+        return Err("No values to sample from".to_string());
+    }
+    match field_type {
+        FieldType::String(_) => Ok(FieldType::String(sample_definition(values, weights)?)),
+        FieldType::Integer(_) => Ok(FieldType::Integer(sample_definition(values, weights)?)),
+        FieldType::Float(_) => Ok(FieldType::Float(sample_definition(values, weights)?)),
+        FieldType::Boolean(_) => Ok(FieldType::Boolean(sample_definition(values, weights)?)),
+        FieldType::Uuid(_) => Ok(FieldType::Uuid(sample_definition(values, weights)?)),
+        _ => Err(
+            "Sampling values is only supported for STRING, INTEGER, FLOAT, BOOLEAN and UUID fields"
+                .to_string(),
+        ),
+    }
+}
+
+fn sample_definition<T>(
+    values: Vec<String>,
+    weights: Option<Vec<f64>>,
+) -> Result<FieldDefinition<T>, String>
+where
+    T: 'static + Debug + Clone + FromStr,
+    T::Err: std::fmt::Display,
+{
+    let values = values
+        .iter()
+        .map(|v| {
+            v.parse::<T>()
+                .map_err(|e| format!("Invalid value '{}': {}", v, e))
+        })
+        .collect::<Result<Vec<T>, String>>()?;
+    let generator = match weights {
+        Some(weights) => SampleGenerator::with_weights(values, weights)?,
+        None => SampleGenerator::new(values),
+    };
+    Ok(FieldDefinition::new(Box::new(generator)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(call: &str) -> Vec<String> {
+        let (rest, (_, args)) = generator_call(call).unwrap();
+        assert_eq!(rest, "");
+        args
+    }
+
+    #[test]
+    fn quoted_args() {
+        assert_eq!(args(r#"f('a', "b", c.txt)"#), ["a", "b", "c.txt"]);
+        assert_eq!(args("f('')"), [""]);
+        assert_eq!(args("f('a, (b)')"), ["a, (b)"]);
+    }
+
+    #[test]
+    fn doubled_quotes_are_escapes() {
+        assert_eq!(args("from_file('O''Brien.txt')"), ["O'Brien.txt"]);
+        assert_eq!(args(r#"f("say ""hi""")"#), [r#"say "hi""#]);
+        assert_eq!(args("f('''')"), ["'"]);
+        assert_eq!(args(r#"f('say "hi"', "it's")"#), [r#"say "hi""#, "it's"]);
+    }
+
+    #[test]
+    fn unterminated_quote_is_an_error() {
+        assert!(generator_call("f('abc)").is_err());
+        assert!(generator_call("f('a''b)").is_err());
+    }
+
+    #[test]
+    fn from_file_path_with_apostrophe() {
+        let dir = std::env::temp_dir().join(format!("datablaster-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("o'brien.txt"), "x\n").unwrap();
+        let field_type = apply_generator(
+            FieldType::String(Default::default()),
+            "from_file",
+            &args("from_file('o''brien.txt')"),
+            &dir,
+        );
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(field_type.is_ok());
+    }
+}
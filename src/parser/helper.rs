@@ -2,7 +2,8 @@ macro_rules! peek_parsed {
     ($parser_result:expr) => {{
         let log_seperator: &str = "<========================>";
         let mid_seperator: &str = "--------------------------";
-        match $parser_result {
+        let parser_result = $parser_result;
+        match &parser_result {
             Ok((input, matched)) => trace!(
                 "\n{}\n{}\n{}\nmatched: ```{:?}```\nremaining: ```{}```\n{}",
                 log_seperator,
@@ -21,6 +22,6 @@ macro_rules! peek_parsed {
                 log_seperator
             ),
         };
-        $parser_result
+        parser_result
     }};
 }
@@ -0,0 +1,823 @@
+/*!
+ * Reads message definitions from .proto files, proto2 or proto3. Options,
+ * services and extensions are skipped since they don't change how a message
+ * is encoded.
+ */
+use crate::definition::proto::{ProtoField, ProtoMessage, ProtoType};
+use crate::definition::schema::TableSchema;
+#[allow(unused_imports)]
+use log::{debug, error, info, trace};
+use nom::{
+    branch::alt,
+    bytes::complete::{tag, tag_no_case, take_until, take_while, take_while1},
+    character::complete::{char, digit1, hex_digit1, multispace1, not_line_ending},
+    combinator::{map_res, opt, peek, recognize, value, verify},
+    error::{Error, ErrorKind, ParseError},
+    multi::{many0_count, separated_list1},
+    sequence::{delimited, pair, preceded, separated_pair, tuple},
+    Err, Finish, IResult,
+};
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fs;
+use std::path::Path;
+
+struct EnumDef {
+    name: String,
+    values: Vec<(String, i32)>,
+}
+
+struct FieldDef {
+    name: String,
+    number: u32,
+    type_name: String,
+    repeated: bool,
+    required: bool,
+    oneof: Option<String>,
+}
+
+struct MessageDef {
+    name: String,
+    fields: Vec<FieldDef>,
+    messages: Vec<MessageDef>,
+    enums: Vec<EnumDef>,
+}
+
+impl MessageDef {
+    fn new<S: Into<String>>(name: S) -> Self {
+        MessageDef {
+            name: name.into(),
+            fields: Vec::new(),
+            messages: Vec::new(),
+            enums: Vec::new(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct ProtoFile {
+    package: String,
+    imports: Vec<String>,
+    messages: Vec<MessageDef>,
+    enums: Vec<EnumDef>,
+}
+
+/// Whitespace and comments
+fn ws(input: &str) -> IResult<&str, ()> {
+    value(
+        (),
+        many0_count(alt((
+            multispace1,
+            recognize(pair(tag("//"), not_line_ending)),
+            recognize(tuple((tag("/*"), take_until("*/"), tag("*/")))),
+        ))),
+    )(input)
+}
+
+fn symbol<'a>(s: &'static str) -> impl FnMut(&'a str) -> IResult<&'a str, &'a str> {
+    preceded(ws, tag(s))
+}
+
+fn ident_raw(input: &str) -> IResult<&str, &str> {
+    recognize(pair(
+        take_while1(|c: char| c.is_ascii_alphabetic() || c == '_'),
+        take_while(|c: char| c.is_ascii_alphanumeric() || c == '_'),
+    ))(input)
+}
+
+fn ident(input: &str) -> IResult<&str, &str> {
+    preceded(ws, ident_raw)(input)
+}
+
+/// Dotted name, optionally fully qualified with a leading dot
+fn full_ident(input: &str) -> IResult<&str, &str> {
+    preceded(
+        ws,
+        recognize(pair(opt(char('.')), separated_list1(char('.'), ident_raw))),
+    )(input)
+}
+
+fn keyword<'a>(word: &'static str) -> impl FnMut(&'a str) -> IResult<&'a str, &'a str> {
+    verify(ident, move |i: &str| i == word)
+}
+
+fn string_lit(input: &str) -> IResult<&str, &str> {
+    preceded(
+        ws,
+        alt((
+            delimited(char('"'), take_while(|c| c != '"'), char('"')),
+            delimited(char('\''), take_while(|c| c != '\''), char('\'')),
+        )),
+    )(input)
+}
+
+/// Decimal, hex or octal integer
+fn int_lit(input: &str) -> IResult<&str, i64> {
+    fn parse_int(s: &str) -> Result<i64, std::num::ParseIntError> {
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, s),
+        };
+        let value = if let Some(hex) = digits
+            .strip_prefix("0x")
+            .or_else(|| digits.strip_prefix("0X"))
+        {
+            i64::from_str_radix(hex, 16)?
+        } else if digits.len() > 1 && digits.starts_with('0') {
+            i64::from_str_radix(&digits[1..], 8)?
+        } else {
+            digits.parse()?
+        };
+        Ok(if negative { -value } else { value })
+    }
+    preceded(
+        ws,
+        map_res(
+            recognize(pair(
+                opt(char('-')),
+                alt((recognize(pair(tag_no_case("0x"), hex_digit1)), digit1)),
+            )),
+            parse_int,
+        ),
+    )(input)
+}
+
+fn field_number(input: &str) -> IResult<&str, u32> {
+    map_res(preceded(symbol("="), int_lit), |n| match n {
+        1..=536_870_911 => Ok(n as u32),
+        n => Err(format!("Invalid field number: {}", n)),
+    })(input)
+}
+
+/// Skips past the `;` ending a statement, or past the bracketed block the input
+/// starts with. Brackets inside strings and comments don't count.
+fn skip(input: &str, until_semicolon: bool) -> IResult<&str, ()> {
+    let bytes = input.as_bytes();
+    let mut depth = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            quote @ (b'"' | b'\'') => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != quote {
+                    if bytes[i] == b'\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = match input[i + 2..].find("*/") {
+                    Some(end) => i + end + 3,
+                    None => bytes.len(),
+                };
+            }
+            b'{' | b'[' => depth += 1,
+            b'}' | b']' => {
+                depth -= 1;
+                if depth == 0 && !until_semicolon {
+                    return Ok((&input[i + 1..], ()));
+                }
+            }
+            b';' if depth == 0 && until_semicolon => return Ok((&input[i + 1..], ())),
+            _ => (),
+        }
+        i += 1;
+    }
+    Err(Err::Error(Error::from_error_kind(
+        input,
+        ErrorKind::TakeUntil,
+    )))
+}
+
+fn statement(input: &str) -> IResult<&str, ()> {
+    skip(input, true)
+}
+
+fn block(input: &str) -> IResult<&str, ()> {
+    let (i, _) = preceded(ws, peek(alt((char('{'), char('[')))))(input)?;
+    skip(i, false)
+}
+
+/// `[packed = true]` and the like
+fn field_options(input: &str) -> IResult<&str, ()> {
+    value((), opt(preceded(peek(symbol("[")), block)))(input)
+}
+
+fn field(input: &str) -> IResult<&str, FieldDef> {
+    let (i, label) = opt(alt((
+        keyword("repeated"),
+        keyword("optional"),
+        keyword("required"),
+    )))(input)?;
+    let (i, type_name) = full_ident(i)?;
+    let (i, name) = ident(i)?;
+    let (i, number) = field_number(i)?;
+    let (i, _) = field_options(i)?;
+    let (i, _) = symbol(";")(i)?;
+    Ok((
+        i,
+        FieldDef {
+            name: name.to_string(),
+            number,
+            type_name: type_name.to_string(),
+            repeated: label == Some("repeated"),
+            required: label == Some("required"),
+            oneof: None,
+        },
+    ))
+}
+
+/// A map is a repeated entry message with a key and a value field
+fn map_field(input: &str) -> IResult<&str, (FieldDef, MessageDef)> {
+    let (i, (key_type, value_type)) = preceded(
+        keyword("map"),
+        delimited(
+            symbol("<"),
+            separated_pair(full_ident, symbol(","), full_ident),
+            symbol(">"),
+        ),
+    )(input)?;
+    let (i, name) = ident(i)?;
+    let (i, number) = field_number(i)?;
+    let (i, _) = field_options(i)?;
+    let (i, _) = symbol(";")(i)?;
+    let mut entry_name: String = name
+        .split('_')
+        .map(|part| {
+            let mut chars = part.chars();
+            chars
+                .next()
+                .map(|c| c.to_ascii_uppercase().to_string() + chars.as_str())
+                .unwrap_or_default()
+        })
+        .collect();
+    entry_name.push_str("Entry");
+    let mut entry = MessageDef::new(entry_name.as_str());
+    for (name, number, type_name) in [("key", 1, key_type), ("value", 2, value_type)] {
+        entry.fields.push(FieldDef {
+            name: name.to_string(),
+            number,
+            type_name: type_name.to_string(),
+            repeated: false,
+            required: false,
+            oneof: None,
+        });
+    }
+    let field = FieldDef {
+        name: name.to_string(),
+        number,
+        type_name: entry_name,
+        repeated: true,
+        required: false,
+        oneof: None,
+    };
+    Ok((i, (field, entry)))
+}
+
+/// Every branch of a oneof, marked with the oneof's name
+fn oneof(input: &str) -> IResult<&str, Vec<FieldDef>> {
+    let (mut i, (_, name, _)) = tuple((keyword("oneof"), ident, symbol("{")))(input)?;
+    let mut branches = Vec::new();
+    loop {
+        if let Ok((rest, _)) = symbol("}")(i) {
+            return Ok((rest, branches));
+        }
+        i = if let Ok((rest, _)) = symbol(";")(i) {
+            rest
+        } else if keyword("option")(i).is_ok() {
+            statement(i)?.0
+        } else {
+            let (rest, mut field) = field(i)?;
+            field.oneof = Some(name.to_string());
+            branches.push(field);
+            rest
+        };
+    }
+}
+
+fn enum_def(input: &str) -> IResult<&str, EnumDef> {
+    let (mut i, name) = delimited(keyword("enum"), ident, symbol("{"))(input)?;
+    let mut values = Vec::new();
+    loop {
+        if let Ok((rest, _)) = symbol("}")(i) {
+            let name = name.to_string();
+            return Ok((rest, EnumDef { name, values }));
+        }
+        i = if let Ok((rest, _)) = symbol(";")(i) {
+            rest
+        } else if alt((keyword("option"), keyword("reserved")))(i).is_ok() {
+            statement(i)?.0
+        } else {
+            let (rest, (value_name, number)) = pair(
+                ident,
+                map_res(preceded(symbol("="), int_lit), i32::try_from),
+            )(i)?;
+            let (rest, _) = field_options(rest)?;
+            let (rest, _) = symbol(";")(rest)?;
+            values.push((value_name.to_string(), number));
+            rest
+        };
+    }
+}
+
+fn message_def(input: &str) -> IResult<&str, MessageDef> {
+    let (mut i, name) = delimited(keyword("message"), ident, symbol("{"))(input)?;
+    let mut message = MessageDef::new(name);
+    loop {
+        if let Ok((rest, _)) = symbol("}")(i) {
+            return Ok((rest, message));
+        }
+        if let Ok((rest, _)) = symbol(";")(i) {
+            i = rest;
+            continue;
+        }
+        // Fully qualified field types start with a dot instead
+        let word = peek(ident)(i).map_or("", |(_, word)| word);
+        i = match word {
+            "message" => {
+                let (rest, nested) = message_def(i)?;
+                message.messages.push(nested);
+                rest
+            }
+            "enum" => {
+                let (rest, nested) = enum_def(i)?;
+                message.enums.push(nested);
+                rest
+            }
+            "oneof" => {
+                let (rest, branches) = oneof(i)?;
+                message.fields.extend(branches);
+                rest
+            }
+            "map" if map_field(i).is_ok() => {
+                let (rest, (field, entry)) = map_field(i)?;
+                message.fields.push(field);
+                message.messages.push(entry);
+                rest
+            }
+            "option" | "reserved" | "extensions" => statement(i)?.0,
+            "extend" => preceded(pair(ident, full_ident), block)(i)?.0,
+            _ => {
+                let (rest, field) = field(i)?;
+                message.fields.push(field);
+                rest
+            }
+        };
+    }
+}
+
+fn proto_file(input: &str) -> IResult<&str, ProtoFile> {
+    let mut file = ProtoFile::default();
+    let mut i = input;
+    loop {
+        let (rest, _) = ws(i)?;
+        if rest.is_empty() {
+            return Ok((rest, file));
+        }
+        if let Ok((rest, _)) = symbol(";")(rest) {
+            i = rest;
+            continue;
+        }
+        let (_, word) = peek(ident)(rest)?;
+        i = match word {
+            "syntax" | "edition" | "option" => statement(rest)?.0,
+            "package" => {
+                let (rest, package) = delimited(keyword("package"), full_ident, symbol(";"))(rest)?;
+                file.package = package.to_string();
+                rest
+            }
+            "import" => {
+                let (rest, path) = delimited(
+                    pair(
+                        keyword("import"),
+                        opt(alt((keyword("public"), keyword("weak")))),
+                    ),
+                    string_lit,
+                    symbol(";"),
+                )(rest)?;
+                file.imports.push(path.to_string());
+                rest
+            }
+            "message" => {
+                let (rest, message) = message_def(rest)?;
+                file.messages.push(message);
+                rest
+            }
+            "enum" => {
+                let (rest, nested) = enum_def(rest)?;
+                file.enums.push(nested);
+                rest
+            }
+            "service" | "extend" => preceded(pair(ident, full_ident), block)(rest)?.0,
+            _ => return Err(Err::Error(Error::from_error_kind(rest, ErrorKind::Tag))),
+        };
+    }
+}
+
+fn parse_file(input: &str) -> Result<ProtoFile, String> {
+    proto_file(input)
+        .finish()
+        .map(|(_, file)| file)
+        .map_err(|e| {
+            let offset = input.len() - e.input.trim_start().len();
+            let line = input[..offset].matches('\n').count() + 1;
+            format!("Invalid .proto definition on line {}", line)
+        })
+}
+
+// How many times a message can be nested in itself
+const MAX_RECURSION_DEPTH: usize = 3;
+
+enum Definition<'a> {
+    Message(&'a MessageDef),
+    Enum(&'a EnumDef),
+    Timestamp,
+}
+
+fn qualify(scope: &str, name: &str) -> String {
+    if scope.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", scope, name)
+    }
+}
+
+/**
+ * Resolver
+ *
+ * Every message and enum by its fully qualified name
+ */
+struct Resolver<'a> {
+    definitions: HashMap<String, Definition<'a>>,
+}
+
+impl<'a> Resolver<'a> {
+    fn new() -> Self {
+        let mut definitions = HashMap::new();
+        definitions.insert(
+            "google.protobuf.Timestamp".to_string(),
+            Definition::Timestamp,
+        );
+        Resolver { definitions }
+    }
+
+    fn register(&mut self, scope: &str, messages: &'a [MessageDef], enums: &'a [EnumDef]) {
+        for e in enums {
+            self.definitions
+                .insert(qualify(scope, &e.name), Definition::Enum(e));
+        }
+        for m in messages {
+            let name = qualify(scope, &m.name);
+            self.register(&name, &m.messages, &m.enums);
+            self.definitions.insert(name, Definition::Message(m));
+        }
+    }
+
+    /// Looks `type_name` up from the innermost scope outwards, like protoc does
+    fn lookup(&self, scope: &str, type_name: &str) -> Option<(String, &Definition<'a>)> {
+        if let Some(full_name) = type_name.strip_prefix('.') {
+            return self
+                .definitions
+                .get(full_name)
+                .map(|d| (full_name.to_string(), d));
+        }
+        let mut scope = scope;
+        loop {
+            let name = qualify(scope, type_name);
+            if let Some(definition) = self.definitions.get(&name) {
+                return Some((name, definition));
+            }
+            if scope.is_empty() {
+                return None;
+            }
+            scope = scope.rfind('.').map_or("", |i| &scope[..i]);
+        }
+    }
+
+    /// `stack` holds the messages being resolved. A message that contains itself
+    /// is nested `MAX_RECURSION_DEPTH` times, after which the optional or
+    /// repeated fields that lead back to it are left out.
+    fn message(
+        &self,
+        full_name: &str,
+        definition: &MessageDef,
+        stack: &mut Vec<String>,
+    ) -> Result<ProtoMessage, String> {
+        stack.push(full_name.to_string());
+        let mut message = ProtoMessage::new(definition.name.as_str());
+        for field in &definition.fields {
+            let proto_type = match ProtoType::scalar(&field.type_name) {
+                Some(proto_type) => proto_type,
+                None => match self.lookup(full_name, &field.type_name) {
+                    Some((_, Definition::Timestamp)) => ProtoType::Timestamp,
+                    Some((name, Definition::Enum(e))) if e.values.is_empty() => {
+                        return Err(format!("Enum {} has no values", name))
+                    }
+                    Some((_, Definition::Enum(e))) => ProtoType::Enum(e.values.clone()),
+                    Some((name, Definition::Message(_))) if field.required && stack.contains(&name) => {
+                        return Err(format!(
+                            "{} contains itself through required field {}.{}, which can't be generated",
+                            name, full_name, field.name
+                        ))
+                    }
+                    Some((name, Definition::Message(_)))
+                        if stack.iter().filter(|m| **m == name).count() >= MAX_RECURSION_DEPTH =>
+                    {
+                        debug!("{}.{}: leaving out the next level of {}", full_name, field.name, name);
+                        continue;
+                    }
+                    Some((name, Definition::Message(m))) => {
+                        ProtoType::Message(self.message(&name, m, stack)?)
+                    }
+                    None => {
+                        return Err(format!(
+                            "{}.{}: unknown type {}",
+                            full_name, field.name, field.type_name
+                        ))
+                    }
+                },
+            };
+            let proto_field = ProtoField::new(
+                field.name.as_str(),
+                field.number,
+                proto_type,
+                field.repeated,
+            );
+            message.add_field(match &field.oneof {
+                Some(oneof) => proto_field.with_oneof(oneof.as_str()),
+                None => proto_field,
+            });
+        }
+        stack.pop();
+        Ok(message)
+    }
+}
+
+/**
+ * Parses a .proto file and resolves `message`, which may be left out when the file
+ * defines a single top level message. Imports are resolved relative to `base_dir`,
+ * except for the well-known types.
+ */
+pub fn parse_proto(
+    input: &str,
+    base_dir: &Path,
+    message: Option<&str>,
+) -> Result<(TableSchema, ProtoMessage), String> {
+    let file = parse_file(input)?;
+    let mut imported = Vec::new();
+    let mut pending = file.imports.clone();
+    let mut seen = HashSet::new();
+    while let Some(import) = pending.pop() {
+        if import.starts_with("google/protobuf/") || !seen.insert(import.clone()) {
+            continue;
+        }
+        let path = base_dir.join(&import);
+        let contents =
+            fs::read_to_string(&path).map_err(|e| format!("{} - {}", path.display(), e))?;
+        let imported_file =
+            parse_file(&contents).map_err(|e| format!("{} - {}", path.display(), e))?;
+        pending.extend(imported_file.imports.iter().cloned());
+        imported.push(imported_file);
+    }
+
+    let mut resolver = Resolver::new();
+    for f in imported.iter().chain(std::iter::once(&file)) {
+        resolver.register(&f.package, &f.messages, &f.enums);
+    }
+    let (full_name, definition) = match message {
+        Some(name) => match resolver.lookup(&file.package, name) {
+            Some((full_name, Definition::Message(m))) => (full_name, *m),
+            _ => return Err(format!("Message {} is not defined", name)),
+        },
+        None => match file.messages.as_slice() {
+            [m] => (qualify(&file.package, &m.name), m),
+            [] => return Err("No message is defined".to_string()),
+            messages => {
+                let names: Vec<&str> = messages.iter().map(|m| m.name.as_str()).collect();
+                return Err(format!(
+                    "Choose the message to generate with --message, one of {}",
+                    names.join(", ")
+                ));
+            }
+        },
+    };
+    debug!("Message: {}", full_name);
+    let proto = resolver.message(&full_name, definition, &mut Vec::new())?;
+    let table = TableSchema::new(definition.name.as_str(), proto.record_schema());
+    Ok((table, proto))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str, message: &str) -> Result<ProtoMessage, String> {
+        parse_proto(input, Path::new("."), Some(message)).map(|(_, proto)| proto)
+    }
+
+    fn field_type<'a>(message: &'a ProtoMessage, name: &str) -> &'a ProtoType {
+        message.field(name).unwrap().get_type()
+    }
+
+    #[test]
+    fn nested_and_repeated() {
+        let proto = parse(
+            r#"
+            syntax = "proto3";
+            package shop;
+            message Order {
+                message Line {
+                    string sku = 1;
+                    uint32 quantity = 2;
+                }
+                int64 id = 1;
+                repeated Line lines = 2;
+                repeated sint32 codes = 3 [packed = true];
+                Line first = 4; // same type, once more
+            }
+            "#,
+            "Order",
+        )
+        .unwrap();
+        let numbers: Vec<_> = proto.iter().map(|f| f.get_number()).collect();
+        assert_eq!(numbers, [1, 2, 3, 4]);
+        assert!(proto.field("lines").unwrap().is_repeated());
+        assert!(proto.field("codes").unwrap().is_repeated());
+        assert!(!proto.field("first").unwrap().is_repeated());
+        assert_eq!(field_type(&proto, "codes"), &ProtoType::Sint32);
+        match field_type(&proto, "lines") {
+            ProtoType::Message(m) => {
+                assert_eq!(m.get_name(), "Line");
+                assert_eq!(field_type(m, "sku"), &ProtoType::String);
+                assert_eq!(field_type(m, "quantity"), &ProtoType::Uint32);
+            }
+            t => panic!("lines is {:?}", t),
+        }
+        assert_eq!(field_type(&proto, "lines"), field_type(&proto, "first"));
+        // Nested messages are found by their qualified name too
+        assert!(parse_proto(
+            "package shop; message Order { message Line { bool b = 1; } }",
+            Path::new("."),
+            Some("shop.Order.Line"),
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn maps_are_repeated_entries() {
+        let proto = parse(
+            "message Stock { map<string, int64> item_counts = 1; }",
+            "Stock",
+        )
+        .unwrap();
+        let field = proto.field("item_counts").unwrap();
+        assert!(field.is_repeated());
+        match field.get_type() {
+            ProtoType::Message(entry) => {
+                assert_eq!(entry.get_name(), "ItemCountsEntry");
+                let fields: Vec<_> = entry
+                    .iter()
+                    .map(|f| (f.get_name(), f.get_number(), f.get_type().clone()))
+                    .collect();
+                assert_eq!(
+                    fields,
+                    [
+                        ("key", 1, ProtoType::String),
+                        ("value", 2, ProtoType::Int64)
+                    ]
+                );
+            }
+            t => panic!("item_counts is {:?}", t),
+        }
+    }
+
+    #[test]
+    fn oneof_keeps_every_branch() {
+        let proto = parse(
+            r#"
+            message Payment {
+                int64 id = 1;
+                oneof method {
+                    option (custom) = "x";
+                    string card = 2;
+                    string iban = 3;
+                    bool cash = 4;
+                }
+                string note = 5;
+            }
+            "#,
+            "Payment",
+        )
+        .unwrap();
+        let fields: Vec<_> = proto
+            .iter()
+            .map(|f| (f.get_name(), f.get_oneof()))
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                ("id", None),
+                ("card", Some("method")),
+                ("iban", Some("method")),
+                ("cash", Some("method")),
+                ("note", None)
+            ]
+        );
+    }
+
+    #[test]
+    fn enums() {
+        let proto = parse(
+            r#"
+            package paint;
+            enum Finish { MATTE = 0; GLOSS = 0x2; }
+            message Can {
+                enum Color {
+                    option allow_alias = true;
+                    reserved 3;
+                    RED = 0;
+                    GREEN = 1 [deprecated = true];
+                    BLUE = -1;
+                }
+                Color color = 1;
+                .paint.Finish finish = 2;
+            }
+            "#,
+            "Can",
+        )
+        .unwrap();
+        let values = |values: &[(&str, i32)]| {
+            ProtoType::Enum(values.iter().map(|(n, v)| (n.to_string(), *v)).collect())
+        };
+        assert_eq!(
+            field_type(&proto, "color"),
+            &values(&[("RED", 0), ("GREEN", 1), ("BLUE", -1)])
+        );
+        assert_eq!(
+            field_type(&proto, "finish"),
+            &values(&[("MATTE", 0), ("GLOSS", 2)])
+        );
+    }
+
+    /// How many times `field` nests `message` in itself
+    fn depth(message: &ProtoMessage, field: &str) -> usize {
+        match message.field(field).map(|f| f.get_type()) {
+            Some(ProtoType::Message(m)) => 1 + depth(m, field),
+            Some(t) => panic!("{} is {:?}", field, t),
+            None => 0,
+        }
+    }
+
+    #[test]
+    fn recursion_is_limited() {
+        let direct = parse("message Node { int32 v = 1; Node next = 2; }", "Node").unwrap();
+        assert_eq!(depth(&direct, "next"), MAX_RECURSION_DEPTH - 1);
+        assert_eq!(direct.record_schema().iter().count(), 2);
+
+        let indirect = parse(
+            "message A { B b = 1; } message B { repeated A a = 1; int32 v = 2; }",
+            "A",
+        )
+        .unwrap();
+        let mut levels = 0;
+        let mut a = &indirect;
+        while let Some(ProtoType::Message(b)) = a.field("b").map(|f| f.get_type()) {
+            assert!(b.field("a").is_none_or(|f| f.is_repeated()));
+            levels += 1;
+            a = match b.field("a").map(|f| f.get_type()) {
+                Some(ProtoType::Message(a)) => a,
+                _ => break,
+            };
+        }
+        assert_eq!(levels, MAX_RECURSION_DEPTH);
+
+        let required = parse(
+            "syntax = \"proto2\"; message Node { required Node next = 1; }",
+            "Node",
+        )
+        .unwrap_err();
+        assert!(
+            required.contains("Node contains itself through required field Node.next"),
+            "{}",
+            required
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            parse("message M {\n  int32 a = ;\n}", "M").unwrap_err(),
+            "Invalid .proto definition on line 2"
+        );
+        assert!(parse("message M { Missing m = 1; }", "M")
+            .unwrap_err()
+            .contains("unknown type Missing"));
+        assert!(parse("message M { int32 a = 0; }", "M").is_err());
+    }
+}
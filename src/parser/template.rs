@@ -0,0 +1,171 @@
+use crate::definition::gen::TemplatePart;
+
+/**
+ * Splits a template such as `ORD-{year}-{int:0:9999:04}` into its parts.
+ * Placeholders that aren't built in generators refer to other fields, and
+ * `{{` / `}}` produce literal braces. `{year}`, `{month}` and `{day}` use a
+ * field of that name when one is generated before the template.
+ */
+pub fn parse_template(template: &str) -> Result<Vec<TemplatePart>, String> {
+    let mut parts = Vec::new();
+    let mut literal = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                literal.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                literal.push('}');
+            }
+            '{' => {
+                let mut placeholder = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => placeholder.push(c),
+                        None => return Err(format!("Unclosed placeholder in '{}'", template)),
+                    }
+                }
+                if !literal.is_empty() {
+                    parts.push(TemplatePart::Literal(std::mem::take(&mut literal)));
+                }
+                parts.push(placeholder_part(&placeholder)?);
+            }
+            '}' => return Err(format!("Unmatched '}}' in '{}'", template)),
+            c => literal.push(c),
+        }
+    }
+    if !literal.is_empty() {
+        parts.push(TemplatePart::Literal(literal));
+    }
+    Ok(parts)
+}
+
+fn placeholder_part(placeholder: &str) -> Result<TemplatePart, String> {
+    let mut segments = placeholder.split(':').map(|s| s.trim());
+    let name = segments.next().unwrap_or_default();
+    let args: Vec<&str> = segments.collect();
+    let part = match (name, args.as_slice()) {
+        ("int", [min, max]) => TemplatePart::Integer {
+            min: parse_arg(min)?,
+            max: parse_arg(max)?,
+            width: 0,
+            zero_pad: false,
+        },
+        // A width such as 4, or 04 to pad with zeros
+        ("int", [min, max, width]) => TemplatePart::Integer {
+            min: parse_arg(min)?,
+            max: parse_arg(max)?,
+            width: parse_arg(width)?,
+            zero_pad: width.starts_with('0'),
+        },
+        ("float", [min, max]) => TemplatePart::Float {
+            min: parse_arg(min)?,
+            max: parse_arg(max)?,
+            precision: 2,
+        },
+        ("float", [min, max, precision]) => TemplatePart::Float {
+            min: parse_arg(min)?,
+            max: parse_arg(max)?,
+            precision: parse_arg(precision)?,
+        },
+        ("digits", [n]) => TemplatePart::Digits(parse_arg(n)?),
+        ("letters", [n]) => TemplatePart::Letters(parse_arg(n)?),
+        ("alnum", [n]) => TemplatePart::Alphanumeric(parse_arg(n)?),
+        ("hex", [n]) => TemplatePart::Hex(parse_arg(n)?),
+        ("uuid", []) => TemplatePart::Uuid,
+        ("choice", [choices]) => {
+            TemplatePart::Choice(choices.split('|').map(String::from).collect())
+        }
+        ("year", []) => TemplatePart::Year,
+        ("month", []) => TemplatePart::Month,
+        ("day", []) => TemplatePart::Day,
+        (field, []) if !field.is_empty() => TemplatePart::Field(field.to_string()),
+        _ => return Err(format!("Invalid placeholder {{{}}}", placeholder)),
+    };
+    match &part {
+        TemplatePart::Integer { min, max, .. } if min > max => {
+            Err(format!("Invalid range in {{{}}}", placeholder))
+        }
+        TemplatePart::Float { min, max, .. } if min >= max => {
+            Err(format!("Invalid range in {{{}}}", placeholder))
+        }
+        _ => Ok(part),
+    }
+}
+
+fn parse_arg<T: std::str::FromStr>(arg: &str) -> Result<T, String> {
+    arg.parse::<T>()
+        .map_err(|_| format!("Invalid placeholder argument '{}'", arg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_repr::{ColumnData, Tuple};
+    use crate::definition::gen::{DataGenerator, GeneratorContext, TemplateGenerator};
+
+    fn generate(template: &str, record: &Tuple) -> String {
+        let generator = TemplateGenerator::new(parse_template(template).unwrap());
+        generator.generate_data(&GeneratorContext::new(0, record))
+    }
+
+    #[test]
+    fn literals_and_escaped_braces() {
+        let parts = parse_template("{{a}} b").unwrap();
+        assert!(matches!(parts.as_slice(), [TemplatePart::Literal(s)] if s == "{a} b"));
+        assert_eq!(generate("x{{}}y", &Tuple::new()), "x{}y");
+    }
+
+    #[test]
+    fn placeholders() {
+        let parts = parse_template("ORD-{digits:3}-{name}{uuid}").unwrap();
+        assert!(matches!(
+            parts.as_slice(),
+            [
+                TemplatePart::Literal(_),
+                TemplatePart::Digits(3),
+                TemplatePart::Literal(_),
+                TemplatePart::Field(name),
+                TemplatePart::Uuid,
+            ] if name == "name"
+        ));
+    }
+
+    #[test]
+    fn int_width() {
+        assert_eq!(generate("{int:7:7:04}", &Tuple::new()), "0007");
+        assert_eq!(generate("{int:7:7:3}", &Tuple::new()), "  7");
+        assert_eq!(generate("{int:-7:-7:04}", &Tuple::new()), "-007");
+        assert_eq!(generate("{int:7:7}", &Tuple::new()), "7");
+    }
+
+    #[test]
+    fn invalid_int_width_is_an_error() {
+        assert!(parse_template("{int:0:9:x4}").is_err());
+        assert!(parse_template("{int:0:9:-4}").is_err());
+        assert!(parse_template("{int:0:9:}").is_err());
+    }
+
+    #[test]
+    fn invalid_placeholders() {
+        assert!(parse_template("{digits:3").is_err());
+        assert!(parse_template("a}").is_err());
+        assert!(parse_template("{}").is_err());
+        assert!(parse_template("{int:9:0}").is_err());
+        assert!(parse_template("{float:1:1}").is_err());
+        assert!(parse_template("{digits:many}").is_err());
+    }
+
+    #[test]
+    fn fields_take_precedence_over_built_ins() {
+        let mut record = Tuple::new();
+        record.add_field_data("year", ColumnData::Integer(1999));
+        record.add_field_data("day", ColumnData::String("Mon".to_string()));
+        assert_eq!(generate("{year}/{day}", &record), "1999/Mon");
+        assert_eq!(generate("{year}", &Tuple::new()).len(), 4);
+    }
+}
@@ -1,7 +1,24 @@
+pub mod arrow;
+pub mod cbor;
+pub mod counting;
 pub mod csv;
+pub mod fixed_width;
 pub mod json;
+pub mod msgpack;
+pub mod orc;
+pub mod partitioned;
+pub mod pgcopy;
+pub mod protobuf;
+pub mod rotating;
+pub mod serialize;
+pub mod sql;
+pub mod sqlite;
+pub mod toml;
+pub mod xml;
+pub mod yaml;
 
 use crate::data_repr::*;
+use std::convert::TryFrom;
 
 pub trait TupleWriter {
     fn supports_list(&self) -> bool {
@@ -12,4 +29,119 @@ pub trait TupleWriter {
     }
     fn write_tuple(&mut self, tuple: &Tuple) -> std::io::Result<()>;
     fn flush(&mut self) -> std::io::Result<()>;
+    /// Bytes taken in but not yet written to the output, such as a batch or stripe being
+    /// collected. Byte limits count these as already written.
+    fn buffered_bytes(&self) -> u64 {
+        0
+    }
+    /// Completes the output document. Nothing is written after this is called
+    fn finish(&mut self) -> std::io::Result<()> {
+        self.flush()
+    }
+}
+
+/**
+ * BinaryEncoding
+ *
+ * How text based writers render byte data
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum BinaryEncoding {
+    #[default]
+    Hex,
+    Base64,
+}
+
+impl BinaryEncoding {
+    pub fn encode(&self, bytes: &[u8]) -> String {
+        match self {
+            BinaryEncoding::Hex => hex::encode(bytes),
+            BinaryEncoding::Base64 => {
+                use base64::Engine;
+                base64::engine::general_purpose::STANDARD.encode(bytes)
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for BinaryEncoding {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hex" => Ok(BinaryEncoding::Hex),
+            "base64" => Ok(BinaryEncoding::Base64),
+            _ => Err(format!("Unknown binary encoding: {}", s)),
+        }
+    }
+}
+
+/**
+ * Framing
+ *
+ * How binary writers separate the encoded tuples
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Framing {
+    /// Values back to back, which decoders read as a stream
+    #[default]
+    Sequence,
+    /// Each value prefixed with its length as a 4 byte big-endian integer
+    LengthDelimited,
+    /// Each value prefixed with its length as a varint, as protobuf's writeDelimitedTo does
+    VarintDelimited,
+}
+
+impl Framing {
+    pub fn write_frame<W: std::io::Write>(&self, wrt: &mut W, frame: &[u8]) -> std::io::Result<()> {
+        match self {
+            Framing::Sequence => (),
+            Framing::LengthDelimited => {
+                let length = u32::try_from(frame.len()).map_err(|_| {
+                    std::io::Error::new(std::io::ErrorKind::InvalidData, "Tuple too large to frame")
+                })?;
+                wrt.write_all(&length.to_be_bytes())?;
+            }
+            Framing::VarintDelimited => {
+                let mut length = Vec::with_capacity(10);
+                protobuf::encode_varint(&mut length, frame.len() as u64);
+                wrt.write_all(&length)?;
+            }
+        }
+        wrt.write_all(frame)
+    }
+}
+
+impl std::str::FromStr for Framing {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sequence" => Ok(Framing::Sequence),
+            "length-delimited" => Ok(Framing::LengthDelimited),
+            "varint-delimited" => Ok(Framing::VarintDelimited),
+            _ => Err(format!("Unknown framing: {}", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binary_encodings() {
+        let bytes = [0x00, 0xfb, 0xff, 0x10];
+        assert_eq!(BinaryEncoding::default(), BinaryEncoding::Hex);
+        assert_eq!(BinaryEncoding::Hex.encode(&bytes), "00fbff10");
+        // The standard alphabet, padded
+        assert_eq!(BinaryEncoding::Base64.encode(&bytes), "APv/EA==");
+        assert_eq!(BinaryEncoding::Hex.encode(&[]), "");
+        assert_eq!(BinaryEncoding::Base64.encode(&[]), "");
+
+        assert_eq!("hex".parse(), Ok(BinaryEncoding::Hex));
+        assert_eq!("base64".parse(), Ok(BinaryEncoding::Base64));
+        assert_eq!(
+            "base32".parse::<BinaryEncoding>(),
+            Err("Unknown binary encoding: base32".to_string())
+        );
+    }
 }
@@ -0,0 +1,418 @@
+use super::*;
+use crate::data_repr::ColumnData;
+use crate::data_repr::*;
+use crate::definition::schema::{FieldSchema, FieldType, RecordSchema};
+use arrow_array::builder::{
+    make_builder, ArrayBuilder, BinaryBuilder, BooleanBuilder, Float64Builder, Int64Builder,
+    ListBuilder, StringBuilder, StructBuilder, TimestampMicrosecondBuilder,
+};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_ipc::writer::{FileWriter, StreamWriter};
+use arrow_schema::{ArrowError, DataType, Field, Fields, Schema, SchemaRef, TimeUnit};
+use chrono::{DateTime, Utc};
+use std::io::{Error, ErrorKind, Write};
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// Arrow type a field is written as
+fn data_type(field_type: &FieldType) -> DataType {
+    match field_type {
+        FieldType::Integer(_) => DataType::Int64,
+        FieldType::Float(_) => DataType::Float64,
+        FieldType::Boolean(_) => DataType::Boolean,
+        FieldType::String(_) => DataType::Utf8,
+        // Plain strings, which every Arrow reader understands
+        FieldType::Uuid(_) => DataType::Utf8,
+        FieldType::Bytes(_) => DataType::Binary,
+        FieldType::Timestamp(_) => DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+        FieldType::List(t) => DataType::List(Arc::new(Field::new("item", data_type(t), true))),
+        FieldType::Record(r) => DataType::Struct(fields(r.iter())),
+    }
+}
+
+fn fields<'a, I: Iterator<Item = &'a FieldSchema>>(schema: I) -> Fields {
+    schema
+        .map(|f| Field::new(f.get_name(), data_type(f.get_type()), true))
+        .collect()
+}
+
+fn wrong_type(data_type: &DataType, value: &ColumnData) -> ArrowError {
+    ArrowError::InvalidArgumentError(format!("Can't write {:?} as {}", value, data_type))
+}
+
+fn downcast<B: ArrayBuilder>(builder: &mut dyn ArrayBuilder) -> &mut B {
+    builder
+        .as_any_mut()
+        .downcast_mut()
+        .expect("Builders are made for their data type")
+}
+
+/// Appends `value` to a builder that `make_builder` made for `data_type`
+fn append(
+    builder: &mut dyn ArrayBuilder,
+    data_type: &DataType,
+    value: &ColumnData,
+) -> Result<(), ArrowError> {
+    macro_rules! scalar {
+        ($builder:ty, $variant:ident, $convert:expr) => {{
+            let builder = downcast::<$builder>(builder);
+            match value {
+                ColumnData::$variant(v) => builder.append_value($convert(v)),
+                ColumnData::Null => builder.append_null(),
+                v => return Err(wrong_type(data_type, v)),
+            }
+        }};
+    }
+    match data_type {
+        DataType::Int64 => scalar!(Int64Builder, Integer, |v: &i64| *v),
+        DataType::Float64 => scalar!(Float64Builder, Float, |v: &f64| *v),
+        DataType::Boolean => scalar!(BooleanBuilder, Boolean, |v: &bool| *v),
+        DataType::Binary => scalar!(BinaryBuilder, Bytes, Vec::as_slice),
+        DataType::Timestamp(..) => {
+            scalar!(TimestampMicrosecondBuilder, Timestamp, |v: &DateTime<
+                Utc,
+            >| v
+                .timestamp_micros())
+        }
+        DataType::Utf8 => {
+            let builder = downcast::<StringBuilder>(builder);
+            match value {
+                ColumnData::String(v) => builder.append_value(v),
+                ColumnData::Uuid(v) => builder.append_value(
+                    v.hyphenated()
+                        .encode_lower(&mut uuid::Uuid::encode_buffer()),
+                ),
+                ColumnData::Null => builder.append_null(),
+                v => return Err(wrong_type(data_type, v)),
+            }
+        }
+        DataType::List(item) => {
+            let builder = downcast::<ListBuilder<Box<dyn ArrayBuilder>>>(builder);
+            match value {
+                ColumnData::List(list) => {
+                    for v in list {
+                        append(builder.values().as_mut(), item.data_type(), v)?;
+                    }
+                    builder.append(true);
+                }
+                ColumnData::Null => builder.append(false),
+                v => return Err(wrong_type(data_type, v)),
+            }
+        }
+        DataType::Struct(fields) => {
+            let builder = downcast::<StructBuilder>(builder);
+            let record = match value {
+                ColumnData::Record(t) => Some(t),
+                ColumnData::Null => None,
+                v => return Err(wrong_type(data_type, v)),
+            };
+            // Null records still need a (null) value in every child
+            for (f, child) in fields.iter().zip(builder.field_builders_mut()) {
+                let v = record
+                    .and_then(|t| t.get(f.name()))
+                    .unwrap_or(&ColumnData::Null);
+                append(child.as_mut(), f.data_type(), v)?;
+            }
+            builder.append(record.is_some());
+        }
+        t => {
+            return Err(ArrowError::NotYetImplemented(format!(
+                "Writing {} columns",
+                t
+            )))
+        }
+    }
+    Ok(())
+}
+
+fn arrow_error(e: ArrowError) -> Error {
+    match e {
+        ArrowError::IoError(_, e) => e,
+        e => Error::new(ErrorKind::InvalidData, e),
+    }
+}
+
+enum IpcWriter<T: Write> {
+    File(FileWriter<T>),
+    Stream(StreamWriter<T>),
+}
+
+/**
+ * TupleToArrowSerializer
+ *
+ * Writes the Arrow IPC file (Feather v2) or stream format, appending tuples to
+ * column builders that are written as record batches of `batch_size` rows.
+ */
+pub struct TupleToArrowSerializer<T: Write> {
+    // Taken when the writer is opened with the first batch
+    wrt: Option<T>,
+    stream: bool,
+    schema: Rc<RecordSchema>,
+    batch_size: usize,
+    // One per field of the opened schema
+    builders: Vec<Box<dyn ArrayBuilder>>,
+    buffered_rows: usize,
+    buffered_bytes: u64,
+    writer: Option<(SchemaRef, IpcWriter<T>)>,
+}
+
+impl<T: Write> TupleToArrowSerializer<T> {
+    pub fn new(wrt: T, schema: Rc<RecordSchema>, stream: bool) -> Self {
+        TupleToArrowSerializer {
+            wrt: Some(wrt),
+            stream,
+            schema,
+            batch_size: 65536,
+            builders: Vec::new(),
+            buffered_rows: 0,
+            buffered_bytes: 0,
+            writer: None,
+        }
+    }
+
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Opens the IPC writer with the fields in `tuple`, as partitioned output leaves some out
+    fn open(&mut self, tuple: Option<&Tuple>) -> Result<(), ArrowError> {
+        let wrt = match self.wrt.take() {
+            Some(wrt) => wrt,
+            None => return Ok(()),
+        };
+        let schema =
+            Arc::new(Schema::new(fields(self.schema.iter().filter(|f| {
+                tuple.is_none_or(|t| t.get(f.get_name()).is_some())
+            }))));
+        let writer = if self.stream {
+            IpcWriter::Stream(StreamWriter::try_new(wrt, &schema)?)
+        } else {
+            IpcWriter::File(FileWriter::try_new(wrt, &schema)?)
+        };
+        self.builders = schema
+            .fields()
+            .iter()
+            .map(|f| make_builder(f.data_type(), self.batch_size.min(1024)))
+            .collect();
+        self.writer = Some((schema, writer));
+        Ok(())
+    }
+
+    fn append(&mut self, tuple: &Tuple) -> Result<(), ArrowError> {
+        self.open(Some(tuple))?;
+        let (schema, _) = self.writer.as_ref().unwrap(); // just opened
+        for (f, builder) in schema.fields().iter().zip(&mut self.builders) {
+            let value = tuple.get(f.name()).unwrap_or(&ColumnData::Null);
+            append(builder.as_mut(), f.data_type(), value)?;
+        }
+        self.buffered_rows += 1;
+        self.buffered_bytes += tuple.estimated_size();
+        Ok(())
+    }
+
+    fn write_batch(&mut self) -> Result<(), ArrowError> {
+        if self.buffered_rows == 0 {
+            return Ok(());
+        }
+        let (schema, writer) = self.writer.as_mut().unwrap(); // opened by the first tuple
+        let columns: Vec<ArrayRef> = self.builders.iter_mut().map(|b| b.finish()).collect();
+        let batch = RecordBatch::try_new(schema.clone(), columns)?;
+        match writer {
+            IpcWriter::File(w) => w.write(&batch)?,
+            IpcWriter::Stream(w) => w.write(&batch)?,
+        }
+        self.buffered_rows = 0;
+        self.buffered_bytes = 0;
+        Ok(())
+    }
+}
+
+impl<T: Write> TupleWriter for TupleToArrowSerializer<T> {
+    fn supports_list(&self) -> bool {
+        true
+    }
+    fn supports_record(&self) -> bool {
+        true
+    }
+
+    fn write_tuple(&mut self, tuple: &Tuple) -> std::io::Result<()> {
+        self.append(tuple).map_err(arrow_error)?;
+        if self.buffered_rows >= self.batch_size {
+            self.write_batch().map_err(arrow_error)?;
+        }
+        Ok(())
+    }
+
+    /// Only flushes batches that are already written, a partial batch stays buffered
+    fn flush(&mut self) -> std::io::Result<()> {
+        match &mut self.writer {
+            Some((_, IpcWriter::File(w))) => w.get_mut().flush(),
+            Some((_, IpcWriter::Stream(w))) => w.get_mut().flush(),
+            None => Ok(()),
+        }
+    }
+
+    fn buffered_bytes(&self) -> u64 {
+        self.buffered_bytes
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        self.write_batch().map_err(arrow_error)?;
+        self.open(None).map_err(arrow_error)?;
+        match &mut self.writer {
+            Some((_, IpcWriter::File(w))) => w.finish().map_err(arrow_error)?,
+            Some((_, IpcWriter::Stream(w))) => w.finish().map_err(arrow_error)?,
+            None => (),
+        }
+        self.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{Int64Type, TimestampMicrosecondType};
+    use arrow_array::Array;
+    use arrow_ipc::reader::{FileReader, StreamReader};
+    use chrono::TimeZone;
+
+    fn schema() -> Rc<RecordSchema> {
+        let field = |name: &str, field_type: FieldType| FieldSchema::new(name, field_type);
+        let record = RecordSchema::new()
+            .with_field(field("a", FieldType::Integer(Default::default())))
+            .with_field(field("b", FieldType::String(Default::default())));
+        Rc::new(
+            RecordSchema::new()
+                .with_field(field("id", FieldType::Integer(Default::default())))
+                .with_field(field("uuid", FieldType::Uuid(Default::default())))
+                .with_field(field("data", FieldType::Bytes(Default::default())))
+                .with_field(field("at", FieldType::Timestamp(Default::default())))
+                .with_field(field(
+                    "tags",
+                    FieldType::List(Box::new(FieldType::Integer(Default::default()))),
+                ))
+                .with_field(field("record", FieldType::Record(record))),
+        )
+    }
+
+    fn at(i: i64) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap() + chrono::Duration::microseconds(i)
+    }
+
+    /// Every other tuple has nulls, or fields left out
+    fn tuple(i: i64) -> Tuple {
+        let mut tuple = Tuple::new();
+        tuple.add_field_data("id", ColumnData::Integer(i));
+        tuple.add_field_data("uuid", ColumnData::Uuid(uuid::Uuid::nil()));
+        if i % 2 == 0 {
+            tuple.add_field_data("data", ColumnData::Bytes(vec![i as u8]));
+            tuple.add_field_data("at", ColumnData::Timestamp(at(i)));
+            tuple.add_field_data(
+                "tags",
+                ColumnData::List((0..i).map(ColumnData::Integer).collect()),
+            );
+            let mut record = Tuple::new();
+            record.add_field_data("a", ColumnData::Integer(i));
+            record.add_field_data("b", ColumnData::String(i.to_string()));
+            tuple.add_field_data("record", ColumnData::Record(record));
+        } else {
+            tuple.add_field_data("data", ColumnData::Null);
+            tuple.add_field_data("at", ColumnData::Null);
+            tuple.add_field_data("tags", ColumnData::Null);
+            tuple.add_field_data("record", ColumnData::Null);
+        }
+        tuple
+    }
+
+    fn write(stream: bool, rows: i64) -> Vec<u8> {
+        let mut output = Vec::new();
+        let mut writer =
+            TupleToArrowSerializer::new(&mut output, schema(), stream).with_batch_size(2);
+        for i in 0..rows {
+            writer.write_tuple(&tuple(i)).unwrap();
+        }
+        writer.finish().unwrap();
+        drop(writer);
+        output
+    }
+
+    fn check(schema: &Schema, batches: &[RecordBatch]) {
+        let timestamp = DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into()));
+        let types: Vec<(&str, &DataType)> = schema
+            .fields()
+            .iter()
+            .map(|f| (f.name().as_str(), f.data_type()))
+            .collect();
+        assert_eq!(
+            types,
+            [
+                ("id", &DataType::Int64),
+                ("uuid", &DataType::Utf8),
+                ("data", &DataType::Binary),
+                ("at", &timestamp),
+                (
+                    "tags",
+                    &DataType::List(Arc::new(Field::new("item", DataType::Int64, true)))
+                ),
+                (
+                    "record",
+                    &DataType::Struct(Fields::from(vec![
+                        Field::new("a", DataType::Int64, true),
+                        Field::new("b", DataType::Utf8, true),
+                    ]))
+                ),
+            ]
+        );
+        let sizes: Vec<usize> = batches.iter().map(|b| b.num_rows()).collect();
+        assert_eq!(sizes, [2, 2, 1]);
+
+        let batch = &batches[1];
+        let ids = batch.column(0).as_primitive::<Int64Type>();
+        assert_eq!(ids.values(), &[2, 3]);
+        assert_eq!(
+            batch.column(1).as_string::<i32>().value(0),
+            "00000000-0000-0000-0000-000000000000"
+        );
+        let data = batch.column(2).as_binary::<i32>();
+        assert_eq!(data.value(0), [2]);
+        assert!(data.is_null(1));
+        let ats = batch.column(3).as_primitive::<TimestampMicrosecondType>();
+        assert_eq!(ats.value(0), at(2).timestamp_micros());
+        assert!(ats.is_null(1));
+        let tags = batch.column(4).as_list::<i32>();
+        assert_eq!(tags.value(0).as_primitive::<Int64Type>().values(), &[0, 1]);
+        assert!(tags.is_null(1));
+        let record = batch.column(5).as_struct();
+        assert_eq!(record.column(0).as_primitive::<Int64Type>().value(0), 2);
+        assert_eq!(record.column(1).as_string::<i32>().value(0), "2");
+        assert!(record.is_null(1));
+    }
+
+    #[test]
+    fn files_read_back() {
+        let output = write(false, 5);
+        let reader = FileReader::try_new(std::io::Cursor::new(output), None).unwrap();
+        let schema = reader.schema();
+        let batches: Vec<RecordBatch> = reader.map(|b| b.unwrap()).collect();
+        check(&schema, &batches);
+    }
+
+    #[test]
+    fn streams_read_back() {
+        let output = write(true, 5);
+        let reader = StreamReader::try_new(&output[..], None).unwrap();
+        let schema = reader.schema();
+        let batches: Vec<RecordBatch> = reader.map(|b| b.unwrap()).collect();
+        check(&schema, &batches);
+    }
+
+    #[test]
+    fn empty_output_has_the_schema() {
+        let output = write(false, 0);
+        let reader = FileReader::try_new(std::io::Cursor::new(output), None).unwrap();
+        assert_eq!(reader.schema().fields().len(), 6);
+        assert_eq!(reader.count(), 0);
+    }
+}
@@ -0,0 +1,127 @@
+use super::serialize::{SerializableTuple, ValueEncoding};
+use super::*;
+use crate::data_repr::*;
+use std::io::{Error, ErrorKind, Write};
+
+/**
+ * TupleToCborSerializer
+ *
+ * Writes each tuple as a CBOR map, so sequence framing gives an RFC 8742 CBOR
+ * sequence. Bytes are byte strings, timestamps are epoch based (tag 1), or
+ * RFC 3339 strings (tag 0) when they have fractional seconds, and UUIDs are
+ * binary (tag 37).
+ */
+pub struct TupleToCborSerializer<T: Write> {
+    wrt: T,
+    framing: Framing,
+    buffer: Vec<u8>,
+}
+
+impl<T: Write> TupleToCborSerializer<T> {
+    pub fn new(wrt: T, framing: Framing) -> Self {
+        TupleToCborSerializer {
+            wrt,
+            framing,
+            buffer: Vec::new(),
+        }
+    }
+}
+
+impl<T: Write> TupleWriter for TupleToCborSerializer<T> {
+    fn supports_list(&self) -> bool {
+        true
+    }
+    fn supports_record(&self) -> bool {
+        true
+    }
+
+    fn write_tuple(&mut self, tuple: &Tuple) -> std::io::Result<()> {
+        self.buffer.clear();
+        let record = SerializableTuple::new(tuple, ValueEncoding::Cbor);
+        ciborium::ser::into_writer(&record, &mut self.buffer)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e.to_string()))?;
+        self.framing.write_frame(&mut self.wrt, &self.buffer)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.wrt.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, TimeZone, Utc};
+    use ciborium::value::Value;
+
+    fn round_trip(tuples: &[Tuple]) -> Vec<Vec<(String, Value)>> {
+        let mut output = Vec::new();
+        let mut writer = TupleToCborSerializer::new(&mut output, Framing::Sequence);
+        for tuple in tuples {
+            writer.write_tuple(tuple).unwrap();
+        }
+        writer.flush().unwrap();
+        drop(writer);
+
+        let mut input = &output[..];
+        let mut records = Vec::new();
+        while !input.is_empty() {
+            let record = match ciborium::de::from_reader(&mut input).unwrap() {
+                Value::Map(entries) => entries,
+                value => panic!("Not a map: {:?}", value),
+            };
+            let record = record
+                .into_iter()
+                .map(|(key, value)| (key.into_text().unwrap(), value))
+                .collect();
+            records.push(record);
+        }
+        records
+    }
+
+    #[test]
+    fn uuids_are_tagged_bytes() {
+        let uuid = uuid::Uuid::new_v4();
+        let mut tuple = Tuple::new();
+        tuple.add_field_data("id", ColumnData::Uuid(uuid));
+        tuple.add_field_data("data", ColumnData::Bytes(vec![0, 1, 255]));
+        let records = round_trip(&[tuple]);
+        assert_eq!(
+            records,
+            [vec![
+                (
+                    "id".to_string(),
+                    Value::Tag(37, Box::new(Value::Bytes(uuid.as_bytes().to_vec())))
+                ),
+                ("data".to_string(), Value::Bytes(vec![0, 1, 255])),
+            ]]
+        );
+    }
+
+    #[test]
+    fn timestamps_keep_their_nanoseconds() {
+        let whole = Utc.with_ymd_and_hms(2024, 5, 6, 7, 8, 9).unwrap();
+        let fractional = Utc.timestamp_opt(1_714_979_289, 123_456_789).unwrap();
+        let tuples: Vec<Tuple> = [whole, fractional]
+            .iter()
+            .map(|ts| {
+                let mut tuple = Tuple::new();
+                tuple.add_field_data("at", ColumnData::Timestamp(*ts));
+                tuple
+            })
+            .collect();
+        let records = round_trip(&tuples);
+
+        assert_eq!(
+            records[0][0].1,
+            Value::Tag(1, Box::new(Value::Integer(whole.timestamp().into())))
+        );
+        match &records[1][0].1 {
+            Value::Tag(0, value) => {
+                let text = value.as_text().unwrap();
+                assert_eq!(DateTime::parse_from_rfc3339(text).unwrap(), fractional);
+            }
+            value => panic!("Not an RFC 3339 timestamp: {:?}", value),
+        }
+    }
+}
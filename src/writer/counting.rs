@@ -0,0 +1,56 @@
+use std::cell::Cell;
+use std::io::Write;
+use std::rc::Rc;
+
+/**
+ * ByteCounter
+ *
+ * Shared handle to the number of bytes a CountingWriter has written
+ */
+#[derive(Debug, Clone, Default)]
+pub struct ByteCounter {
+    count: Rc<Cell<u64>>,
+}
+
+impl ByteCounter {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn get(&self) -> u64 {
+        self.count.get()
+    }
+
+    pub fn add(&self, bytes: u64) {
+        self.count.set(self.count.get() + bytes)
+    }
+}
+
+/**
+ * CountingWriter
+ *
+ * Passes writes through while keeping count of the bytes written. The counter
+ * can be read after the writer has been handed to a TupleWriter.
+ */
+pub struct CountingWriter<W: Write> {
+    inner: W,
+    counter: ByteCounter,
+}
+
+impl<W: Write> CountingWriter<W> {
+    pub fn new(inner: W, counter: ByteCounter) -> Self {
+        CountingWriter { inner, counter }
+    }
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.counter.add(written as u64);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
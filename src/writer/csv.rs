@@ -1,15 +1,75 @@
 use super::*;
 use crate::data_repr::ColumnData;
 use crate::data_repr::*;
+use std::cell::RefCell;
 use std::io::{Error, ErrorKind, Write};
+use std::rc::Rc;
 
+/// Where the csv encoder puts a row until it's passed on
+#[derive(Clone, Default)]
+struct RowBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for RowBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/**
+ * TupleToCSVSerializer
+ *
+ * Each row is encoded into a buffer and passed on as soon as it's complete, so
+ * everything written so far is seen by whatever counts the output.
+ */
 pub struct TupleToCSVSerializer<T: Write> {
-    writer: T,
+    wrt: T,
+    encoder: ::csv::Writer<RowBuffer>,
+    row: RowBuffer,
+    binary_encoding: BinaryEncoding,
+    wrote_header: bool,
 }
 
 impl<T: Write> TupleToCSVSerializer<T> {
-    pub fn new(writer: T) -> Self {
-        TupleToCSVSerializer { writer }
+    pub fn new(wrt: T) -> Self {
+        let row = RowBuffer::default();
+        TupleToCSVSerializer {
+            wrt,
+            encoder: ::csv::Writer::from_writer(row.clone()),
+            row,
+            binary_encoding: Default::default(),
+            wrote_header: false,
+        }
+    }
+
+    fn write_record<I>(&mut self, record: I) -> std::io::Result<()>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        self.encoder.write_record(record).map_err(csv_error)?;
+        self.encoder.flush()?;
+        let mut row = self.row.0.borrow_mut();
+        let result = self.wrt.write_all(&row);
+        row.clear();
+        result
+    }
+
+    pub fn with_binary_encoding(mut self, binary_encoding: BinaryEncoding) -> Self {
+        self.binary_encoding = binary_encoding;
+        self
+    }
+}
+
+/// Keeps the kind of I/O errors, so callers can still tell a closed pipe apart
+fn csv_error(e: ::csv::Error) -> Error {
+    match e.into_kind() {
+        ::csv::ErrorKind::Io(e) => e,
+        kind => Error::new(ErrorKind::InvalidData, format!("{:?}", kind)),
     }
 }
 
@@ -22,12 +82,23 @@ impl<T: Write> TupleWriter for TupleToCSVSerializer<T> {
     }
 
     fn write_tuple(&mut self, tuple: &Tuple) -> std::io::Result<()> {
+        // Every file starts with the field names
+        if !self.wrote_header {
+            let names: Vec<&str> = tuple.into_iter().map(|(name, _)| name.as_str()).collect();
+            self.write_record(names)?;
+            self.wrote_header = true;
+        }
         let mut row: Vec<String> = vec![];
         for (_, data) in tuple {
             match data {
                 ColumnData::Integer(v) => row.push(v.to_string()),
                 ColumnData::Float(v) => row.push(v.to_string()),
+                ColumnData::Boolean(v) => row.push(v.to_string()),
                 ColumnData::String(v) => row.push(v.to_string()),
+                ColumnData::Uuid(v) => row.push(v.to_string()),
+                ColumnData::Bytes(v) => row.push(self.binary_encoding.encode(v)),
+                ColumnData::Timestamp(v) => row.push(format_timestamp(v)),
+                ColumnData::Null => row.push(String::new()),
                 ColumnData::Record(_) => {
                     return Err(Error::new(
                         ErrorKind::InvalidInput,
@@ -42,11 +113,53 @@ impl<T: Write> TupleWriter for TupleToCSVSerializer<T> {
                 }
             }
         }
-        let output = row.join(",");
-        self.writer.write_all(output.as_bytes())
+        self.write_record(&row)
     }
 
     fn flush(&mut self) -> std::io::Result<()> {
-        self.writer.flush()
+        self.wrt.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A pipe whose reader has gone away
+    struct ClosedPipe;
+
+    impl Write for ClosedPipe {
+        fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+            Err(Error::from(ErrorKind::BrokenPipe))
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Err(Error::from(ErrorKind::BrokenPipe))
+        }
+    }
+
+    #[test]
+    fn broken_pipes_keep_their_kind() {
+        let mut tuple = Tuple::new();
+        tuple.add_field_data("a", ColumnData::String("x".repeat(100)));
+        let mut writer = TupleToCSVSerializer::new(ClosedPipe);
+        let err = writer.write_tuple(&tuple).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn rows_are_passed_on_when_complete() {
+        let mut tuple = Tuple::new();
+        tuple.add_field_data("a", ColumnData::String("x,y".to_string()));
+        tuple.add_field_data("b", ColumnData::Integer(1));
+        let mut out = Vec::new();
+        let mut writer = TupleToCSVSerializer::new(&mut out);
+        writer.write_tuple(&tuple).unwrap();
+        writer.write_tuple(&tuple).unwrap();
+        drop(writer);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "a,b\n\"x,y\",1\n\"x,y\",1\n"
+        );
     }
 }
@@ -0,0 +1,397 @@
+use super::*;
+use crate::data_repr::ColumnData;
+use crate::data_repr::*;
+use crate::definition::schema::{Alignment, FieldFormat, FieldType, Overflow, RecordSchema};
+use std::io::{Error, ErrorKind, Write};
+
+/**
+ * TupleToFixedWidthSerializer
+ *
+ * Writes each tuple as a line of fields padded to the width their schema
+ * annotations give them. NULL fields are all padding. Integers, booleans,
+ * UUIDs and timestamps default to the width of their longest value, every
+ * other field that is written needs a width. Fields left out of the tuples,
+ * like partition columns, don't.
+ */
+pub struct TupleToFixedWidthSerializer<T: Write> {
+    wrt: T,
+    formats: Vec<(String, FieldFormat, Option<usize>)>,
+    binary_encoding: BinaryEncoding,
+}
+
+impl<T: Write> TupleToFixedWidthSerializer<T> {
+    pub fn new(wrt: T, schema: &RecordSchema) -> Self {
+        let formats = schema
+            .iter()
+            .map(|f| {
+                let format = f.get_format();
+                let width = format.get_width().or_else(|| default_width(f.get_type()));
+                (f.get_name().to_string(), format.clone(), width)
+            })
+            .collect();
+        TupleToFixedWidthSerializer {
+            wrt,
+            formats,
+            binary_encoding: Default::default(),
+        }
+    }
+
+    pub fn with_binary_encoding(mut self, binary_encoding: BinaryEncoding) -> Self {
+        self.binary_encoding = binary_encoding;
+        self
+    }
+}
+
+/// Width of the longest value of the types whose text has a bounded length
+fn default_width(field_type: &FieldType) -> Option<usize> {
+    match field_type {
+        // i64::MIN
+        FieldType::Integer(_) => Some(20),
+        FieldType::Boolean(_) => Some(5),
+        FieldType::Uuid(_) => Some(36),
+        // RFC 3339 with nanoseconds
+        FieldType::Timestamp(_) => Some(30),
+        _ => None,
+    }
+}
+
+fn pad_field(
+    line: &mut String,
+    name: &str,
+    value: &str,
+    width: usize,
+    format: &FieldFormat,
+    numeric: bool,
+) -> std::io::Result<()> {
+    let mut value = value;
+    let mut length = value.chars().count();
+    if length > width {
+        match format.get_overflow() {
+            Overflow::Error => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("{} is wider than {} characters: {}", name, width, value),
+                ))
+            }
+            // Dropping digits would change the number, so it's marked instead
+            Overflow::Truncate if numeric => {
+                line.extend(std::iter::repeat_n('*', width));
+                return Ok(());
+            }
+            Overflow::Truncate => {
+                let end = value
+                    .char_indices()
+                    .nth(width)
+                    .map_or(value.len(), |(i, _)| i);
+                value = &value[..end];
+                length = width;
+            }
+        }
+    }
+    let padding = std::iter::repeat_n(format.get_padding(), width - length);
+    match format.get_align(numeric) {
+        Alignment::Left => {
+            line.push_str(value);
+            line.extend(padding);
+        }
+        // Zeros go between the sign and the digits
+        Alignment::Right if numeric && format.get_padding() == '0' && value.starts_with('-') => {
+            line.push('-');
+            line.extend(padding);
+            line.push_str(&value[1..]);
+        }
+        Alignment::Right => {
+            line.extend(padding);
+            line.push_str(value);
+        }
+    }
+    Ok(())
+}
+
+impl<T: Write> TupleWriter for TupleToFixedWidthSerializer<T> {
+    fn supports_list(&self) -> bool {
+        false
+    }
+    fn supports_record(&self) -> bool {
+        false
+    }
+
+    fn write_tuple(&mut self, tuple: &Tuple) -> std::io::Result<()> {
+        let mut line = String::new();
+        for (name, data) in tuple {
+            let (_, format, width) = self
+                .formats
+                .iter()
+                .find(|(field, _, _)| field == name)
+                .ok_or_else(|| {
+                    Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("{} is not in the schema", name),
+                    )
+                })?;
+            let width = width.ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("{} needs a width(..) for fixed-width output", name),
+                )
+            })?;
+            let value = match data {
+                ColumnData::Integer(v) => v.to_string(),
+                ColumnData::Float(v) => v.to_string(),
+                ColumnData::Boolean(v) => v.to_string(),
+                ColumnData::String(v) => v.to_string(),
+                ColumnData::Uuid(v) => v.to_string(),
+                ColumnData::Bytes(v) => self.binary_encoding.encode(v),
+                ColumnData::Timestamp(v) => format_timestamp(v),
+                ColumnData::Null => String::new(),
+                ColumnData::Record(_) => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        "Record not supported by fixed-width",
+                    ))
+                }
+                ColumnData::List(_) => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        "List not supported by fixed-width",
+                    ))
+                }
+            };
+            let numeric = matches!(data, ColumnData::Integer(_) | ColumnData::Float(_));
+            pad_field(&mut line, name, &value, width, format, numeric)?;
+        }
+        writeln!(self.wrt, "{}", line)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.wrt.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::definition::schema::FieldSchema;
+    use chrono::{TimeZone, Utc};
+
+    fn field(name: &str, width: Option<usize>) -> FieldSchema {
+        let format = match width {
+            Some(width) => FieldFormat::default().with_width(width),
+            None => FieldFormat::default(),
+        };
+        FieldSchema::new(name, FieldType::String(Default::default())).with_format(format)
+    }
+
+    fn write(schema: &RecordSchema, fields: &[(&str, &str)]) -> std::io::Result<String> {
+        let mut tuple = Tuple::new();
+        for (name, value) in fields {
+            tuple.add_field_data(*name, ColumnData::String(value.to_string()));
+        }
+        let mut out = Vec::new();
+        TupleToFixedWidthSerializer::new(&mut out, schema).write_tuple(&tuple)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn fields_are_found_by_name() {
+        let schema = RecordSchema::new()
+            .with_field(field("a", Some(2)))
+            .with_field(field("b", None))
+            .with_field(field("c", Some(4)));
+        // b is left out, as partitioning does, so it needs no width
+        assert_eq!(
+            write(&schema, &[("a", "x"), ("c", "y")]).unwrap(),
+            "x y   \n"
+        );
+        let err = write(&schema, &[("a", "x"), ("b", "y")]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "b needs a width(..) for fixed-width output"
+        );
+        let err = write(&schema, &[("d", "x")]).unwrap_err();
+        assert_eq!(err.to_string(), "d is not in the schema");
+    }
+
+    fn typed(name: &str, field_type: FieldType, format: FieldFormat) -> FieldSchema {
+        FieldSchema::new(name, field_type).with_format(format)
+    }
+
+    fn write_data(
+        schema: &RecordSchema,
+        fields: Vec<(&str, ColumnData)>,
+    ) -> std::io::Result<String> {
+        let mut tuple = Tuple::new();
+        for (name, data) in fields {
+            tuple.add_field_data(name, data);
+        }
+        let mut out = Vec::new();
+        TupleToFixedWidthSerializer::new(&mut out, schema).write_tuple(&tuple)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn alignment_and_padding() {
+        let string = || FieldType::String(Default::default());
+        let integer = || FieldType::Integer(Default::default());
+        let format = FieldFormat::default().with_width(6);
+        let schema = RecordSchema::new()
+            .with_field(typed("s", string(), format.clone()))
+            .with_field(typed("i", integer(), format.clone()))
+            .with_field(typed(
+                "r",
+                string(),
+                format
+                    .clone()
+                    .with_align(Alignment::Right)
+                    .with_padding('.'),
+            ))
+            .with_field(typed(
+                "l",
+                integer(),
+                format.clone().with_align(Alignment::Left).with_padding('_'),
+            ))
+            .with_field(typed("n", integer(), format));
+        let line = write_data(
+            &schema,
+            vec![
+                ("s", ColumnData::String("ab".to_string())),
+                ("i", ColumnData::Integer(42)),
+                ("r", ColumnData::String("ab".to_string())),
+                ("l", ColumnData::Integer(-7)),
+                ("n", ColumnData::Null),
+            ],
+        )
+        .unwrap();
+        assert_eq!(line, "ab        42....ab-7____      \n");
+    }
+
+    #[test]
+    fn zeros_go_after_the_sign() {
+        let zeros = FieldFormat::default().with_width(6).with_padding('0');
+        let schema = RecordSchema::new()
+            .with_field(typed(
+                "i",
+                FieldType::Integer(Default::default()),
+                zeros.clone(),
+            ))
+            .with_field(typed(
+                "f",
+                FieldType::Float(Default::default()),
+                zeros.clone(),
+            ))
+            .with_field(typed(
+                "s",
+                FieldType::String(Default::default()),
+                zeros.with_align(Alignment::Right),
+            ));
+        let line = |i, f| {
+            write_data(
+                &schema,
+                vec![
+                    ("i", ColumnData::Integer(i)),
+                    ("f", ColumnData::Float(f)),
+                    ("s", ColumnData::String("-a".to_string())),
+                ],
+            )
+            .unwrap()
+        };
+        assert_eq!(line(42, 1.5), "0000420001.50000-a\n");
+        assert_eq!(line(-42, -1.5), "-00042-001.50000-a\n");
+    }
+
+    #[test]
+    fn overflow() {
+        let narrow = |overflow| FieldFormat::default().with_width(3).with_overflow(overflow);
+        let schema = |overflow| {
+            RecordSchema::new()
+                .with_field(typed(
+                    "s",
+                    FieldType::String(Default::default()),
+                    narrow(overflow),
+                ))
+                .with_field(typed(
+                    "i",
+                    FieldType::Integer(Default::default()),
+                    narrow(overflow),
+                ))
+                .with_field(typed(
+                    "f",
+                    FieldType::Float(Default::default()),
+                    narrow(overflow),
+                ))
+        };
+        let line = |overflow, s: &str, i, f| {
+            write_data(
+                &schema(overflow),
+                vec![
+                    ("s", ColumnData::String(s.to_string())),
+                    ("i", ColumnData::Integer(i)),
+                    ("f", ColumnData::Float(f)),
+                ],
+            )
+        };
+
+        // Strings are cut off, numbers never lose digits
+        assert_eq!(
+            line(Overflow::Truncate, "abcd", 12345, 0.125).unwrap(),
+            "abc******\n"
+        );
+        assert_eq!(
+            line(Overflow::Truncate, "ééééé", -12, 1.5).unwrap(),
+            "ééé-121.5\n"
+        );
+        assert_eq!(
+            line(Overflow::Error, "abcd", 1, 1.0)
+                .unwrap_err()
+                .to_string(),
+            "s is wider than 3 characters: abcd"
+        );
+        assert_eq!(
+            line(Overflow::Error, "abc", 1234, 1.0)
+                .unwrap_err()
+                .to_string(),
+            "i is wider than 3 characters: 1234"
+        );
+    }
+
+    #[test]
+    fn types_with_bounded_text_have_default_widths() {
+        let schema = RecordSchema::new()
+            .with_field(FieldSchema::new(
+                "i",
+                FieldType::Integer(Default::default()),
+            ))
+            .with_field(FieldSchema::new(
+                "b",
+                FieldType::Boolean(Default::default()),
+            ))
+            .with_field(FieldSchema::new("u", FieldType::Uuid(Default::default())))
+            .with_field(FieldSchema::new(
+                "t",
+                FieldType::Timestamp(Default::default()),
+            ))
+            .with_field(FieldSchema::new("f", FieldType::Float(Default::default())));
+        let line = write_data(
+            &schema,
+            vec![
+                ("i", ColumnData::Integer(i64::MIN)),
+                ("b", ColumnData::Boolean(false)),
+                ("u", ColumnData::Uuid(uuid::Uuid::nil())),
+                (
+                    "t",
+                    ColumnData::Timestamp(Utc.timestamp_opt(-1, 999_999_999).unwrap()),
+                ),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            line,
+            "-9223372036854775808false00000000-0000-0000-0000-0000000000001969-12-31T23:59:59.999999999Z\n"
+        );
+        let err = write_data(&schema, vec![("f", ColumnData::Float(1.0))]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "f needs a width(..) for fixed-width output"
+        );
+    }
+}
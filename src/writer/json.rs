@@ -1,51 +1,83 @@
-extern crate serde_json;
-
+use super::serialize::{SerializableColumn, SerializableTuple, ValueEncoding};
 use super::*;
 use crate::data_repr::ColumnData;
 use crate::data_repr::*;
-use serde_json::{json, to_string_pretty, Map, Value};
 use std::io::Write;
 
+/**
+ * JsonStyle
+ *
+ * Newline delimited objects, or a single array of objects, compact or indented
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum JsonStyle {
+    #[default]
+    Ndjson,
+    Array,
+    Pretty,
+}
+
+impl std::str::FromStr for JsonStyle {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ndjson" => Ok(JsonStyle::Ndjson),
+            "array" => Ok(JsonStyle::Array),
+            "pretty" => Ok(JsonStyle::Pretty),
+            _ => Err(format!("Unknown JSON style: {}", s)),
+        }
+    }
+}
+
 pub struct TupleToJsonSerializer<T: Write> {
     wrt: T,
-    pretty_print: bool,
+    style: JsonStyle,
+    binary_encoding: BinaryEncoding,
+    wrote_first: bool,
 }
 
 impl<T: Write> TupleToJsonSerializer<T> {
-    pub fn new(wrt: T, pretty_print: bool) -> Self {
-        TupleToJsonSerializer { wrt, pretty_print }
+    pub fn new(wrt: T, style: JsonStyle) -> Self {
+        TupleToJsonSerializer {
+            wrt,
+            style,
+            binary_encoding: Default::default(),
+            wrote_first: false,
+        }
     }
 
-    pub fn to_pretty_json_data(&self, tuple: &Tuple) -> Result<String, serde_json::Error> {
-        let output_value = self.to_json_data_recurse(tuple);
-        to_string_pretty(&output_value)
+    pub fn with_binary_encoding(mut self, binary_encoding: BinaryEncoding) -> Self {
+        self.binary_encoding = binary_encoding;
+        self
     }
+}
 
-    fn to_json_data_recurse(&self, tuple: &Tuple) -> Value {
-        let mut json_map = Map::new();
-        for (field_name, field_value) in tuple {
-            json_map.insert(
-                field_name.to_string(),
-                self.column_data_to_json_value(field_value),
-            );
-        }
-        json!(json_map)
-    }
-
-    fn column_data_to_json_value(&self, col_data: &ColumnData) -> Value {
-        match col_data {
-            ColumnData::Integer(v) => json!(v),
-            ColumnData::Float(v) => json!(v),
-            ColumnData::String(v) => json!(v),
-            ColumnData::Record(t) => self.to_json_data_recurse(t),
-            ColumnData::List(v) => {
-                let mut list = Vec::new();
-                for cd in v {
-                    list.push(self.column_data_to_json_value(cd))
-                }
-                json!(list)
+/// Nested data as JSON text, for formats that store it in a single column
+pub fn json_text(data: &ColumnData, binary_encoding: BinaryEncoding) -> String {
+    serde_json::to_string(&SerializableColumn::new(
+        data,
+        ValueEncoding::Text(binary_encoding),
+    ))
+    .expect("tuples only have string keys")
+}
+
+/// Indents everything after the first line, which is safe for JSON as strings
+/// can't hold a raw newline
+struct Indented<'a, W: Write>(&'a mut W);
+
+impl<W: Write> Write for Indented<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        for (i, line) in buf.split(|b| *b == b'\n').enumerate() {
+            if i > 0 {
+                self.0.write_all(b"\n  ")?;
             }
+            self.0.write_all(line)?;
         }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.flush()
     }
 }
 
@@ -58,16 +90,115 @@ impl<T: Write> TupleWriter for TupleToJsonSerializer<T> {
     }
 
     fn write_tuple(&mut self, tuple: &Tuple) -> std::io::Result<()> {
-        let mut record = if self.pretty_print {
-            self.to_pretty_json_data(tuple)?
-        } else {
-            self.to_json_data_recurse(tuple).to_string()
-        };
-        record.push('\n');
-        self.wrt.write_all(record.as_bytes())
+        let record = SerializableTuple::new(tuple, ValueEncoding::Text(self.binary_encoding));
+        match self.style {
+            JsonStyle::Ndjson => {
+                serde_json::to_writer(&mut self.wrt, &record)?;
+                self.wrt.write_all(b"\n")?
+            }
+            JsonStyle::Array => {
+                self.wrt
+                    .write_all(if self.wrote_first { b",\n" } else { b"[\n" })?;
+                serde_json::to_writer(&mut self.wrt, &record)?
+            }
+            JsonStyle::Pretty => {
+                self.wrt
+                    .write_all(if self.wrote_first { b",\n  " } else { b"[\n  " })?;
+                serde_json::to_writer_pretty(Indented(&mut self.wrt), &record)?
+            }
+        }
+        self.wrote_first = true;
+        Ok(())
     }
 
     fn flush(&mut self) -> std::io::Result<()> {
         self.wrt.flush()
     }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        match (self.style, self.wrote_first) {
+            (JsonStyle::Ndjson, _) => (),
+            (_, true) => writeln!(self.wrt, "\n]")?,
+            (_, false) => writeln!(self.wrt, "[]")?,
+        }
+        self.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fields added out of alphabetical order, to check that it's kept
+    fn tuple(id: i64) -> Tuple {
+        let mut record = Tuple::new();
+        record.add_field_data("y", ColumnData::Bytes(vec![0xca, 0xfe]));
+        let mut tuple = Tuple::new();
+        tuple.add_field_data("z", ColumnData::Integer(id));
+        tuple.add_field_data("a", ColumnData::String("x\ny".to_string()));
+        tuple.add_field_data(
+            "m",
+            ColumnData::List(vec![ColumnData::Record(record), ColumnData::Null]),
+        );
+        tuple
+    }
+
+    fn write(style: JsonStyle, rows: i64) -> String {
+        let mut output = Vec::new();
+        let mut writer = TupleToJsonSerializer::new(&mut output, style)
+            .with_binary_encoding(BinaryEncoding::Hex);
+        for id in 0..rows {
+            writer.write_tuple(&tuple(id)).unwrap();
+        }
+        writer.finish().unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn ndjson() {
+        assert_eq!(
+            write(JsonStyle::Ndjson, 2),
+            "{\"z\":0,\"a\":\"x\\ny\",\"m\":[{\"y\":\"cafe\"},null]}\n\
+             {\"z\":1,\"a\":\"x\\ny\",\"m\":[{\"y\":\"cafe\"},null]}\n"
+        );
+        assert_eq!(write(JsonStyle::Ndjson, 0), "");
+    }
+
+    #[test]
+    fn arrays() {
+        let output = write(JsonStyle::Array, 2);
+        assert_eq!(
+            output,
+            "[\n\
+             {\"z\":0,\"a\":\"x\\ny\",\"m\":[{\"y\":\"cafe\"},null]},\n\
+             {\"z\":1,\"a\":\"x\\ny\",\"m\":[{\"y\":\"cafe\"},null]}\n\
+             ]\n"
+        );
+        let parsed: serde_json::Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(parsed.as_array().unwrap().len(), 2);
+        assert_eq!(write(JsonStyle::Array, 0), "[]\n");
+    }
+
+    #[test]
+    fn pretty_arrays() {
+        let output = write(JsonStyle::Pretty, 2);
+        let object = |id| {
+            format!(
+                "  {{\n    \"z\": {},\n    \"a\": \"x\\ny\",\n    \"m\": [\n      {{\n        \"y\": \"cafe\"\n      }},\n      null\n    ]\n  }}",
+                id
+            )
+        };
+        assert_eq!(output, format!("[\n{},\n{}\n]\n", object(0), object(1)));
+        let parsed: serde_json::Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(parsed[1]["z"], 1);
+        assert_eq!(write(JsonStyle::Pretty, 0), "[]\n");
+    }
+
+    #[test]
+    fn styles() {
+        assert_eq!("ndjson".parse(), Ok(JsonStyle::Ndjson));
+        assert_eq!("array".parse(), Ok(JsonStyle::Array));
+        assert_eq!("pretty".parse(), Ok(JsonStyle::Pretty));
+        assert!("jsonl".parse::<JsonStyle>().is_err());
+    }
 }
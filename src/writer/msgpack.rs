@@ -0,0 +1,170 @@
+use super::serialize::{SerializableTuple, ValueEncoding};
+use super::*;
+use crate::data_repr::*;
+use serde::Serialize;
+use std::io::{Error, ErrorKind, Write};
+
+/**
+ * TupleToMessagePackSerializer
+ *
+ * Writes each tuple as a MessagePack map. Bytes and UUIDs are bin values and
+ * timestamps use the timestamp extension type.
+ */
+pub struct TupleToMessagePackSerializer<T: Write> {
+    wrt: T,
+    framing: Framing,
+    buffer: Vec<u8>,
+}
+
+impl<T: Write> TupleToMessagePackSerializer<T> {
+    pub fn new(wrt: T, framing: Framing) -> Self {
+        TupleToMessagePackSerializer {
+            wrt,
+            framing,
+            buffer: Vec::new(),
+        }
+    }
+}
+
+impl<T: Write> TupleWriter for TupleToMessagePackSerializer<T> {
+    fn supports_list(&self) -> bool {
+        true
+    }
+    fn supports_record(&self) -> bool {
+        true
+    }
+
+    fn write_tuple(&mut self, tuple: &Tuple) -> std::io::Result<()> {
+        self.buffer.clear();
+        let record = SerializableTuple::new(tuple, ValueEncoding::MessagePack);
+        // Field names as map keys rather than the struct-as-array default
+        record
+            .serialize(&mut rmp_serde::Serializer::new(&mut self.buffer).with_struct_map())
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        self.framing.write_frame(&mut self.wrt, &self.buffer)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.wrt.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, TimeZone, Utc};
+    use serde::de::{Deserialize, Deserializer, Visitor};
+    use std::collections::HashMap;
+    use std::convert::TryInto;
+    use std::fmt;
+
+    /// The payload of an extension or bin value
+    struct Payload(Vec<u8>);
+
+    impl<'de> Deserialize<'de> for Payload {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            struct BytesVisitor;
+            impl<'de> Visitor<'de> for BytesVisitor {
+                type Value = Payload;
+                fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    f.write_str("bytes")
+                }
+                fn visit_bytes<E>(self, v: &[u8]) -> Result<Payload, E> {
+                    Ok(Payload(v.to_vec()))
+                }
+            }
+            deserializer.deserialize_bytes(BytesVisitor)
+        }
+    }
+
+    /// A timestamp extension value, decoded as the MessagePack spec describes
+    struct Timestamp(DateTime<Utc>, usize);
+
+    impl<'de> Deserialize<'de> for Timestamp {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            struct ExtVisitor;
+            impl<'de> Visitor<'de> for ExtVisitor {
+                type Value = Timestamp;
+                fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    f.write_str("a timestamp extension")
+                }
+                fn visit_newtype_struct<D: Deserializer<'de>>(
+                    self,
+                    deserializer: D,
+                ) -> Result<Timestamp, D::Error> {
+                    let (kind, Payload(payload)) = <(i8, Payload)>::deserialize(deserializer)?;
+                    assert_eq!(kind, -1);
+                    let (seconds, nanos) = match payload.len() {
+                        4 => (
+                            i64::from(u32::from_be_bytes(payload[..].try_into().unwrap())),
+                            0,
+                        ),
+                        8 => {
+                            let packed = u64::from_be_bytes(payload[..].try_into().unwrap());
+                            ((packed & ((1 << 34) - 1)) as i64, (packed >> 34) as u32)
+                        }
+                        12 => (
+                            i64::from_be_bytes(payload[4..].try_into().unwrap()),
+                            u32::from_be_bytes(payload[..4].try_into().unwrap()),
+                        ),
+                        n => panic!("Timestamp payload of {} bytes", n),
+                    };
+                    let ts = Utc.timestamp_opt(seconds, nanos).unwrap();
+                    Ok(Timestamp(ts, payload.len()))
+                }
+            }
+            deserializer.deserialize_newtype_struct(rmp_serde::MSGPACK_EXT_STRUCT_NAME, ExtVisitor)
+        }
+    }
+
+    fn write(tuples: &[Tuple]) -> Vec<u8> {
+        let mut output = Vec::new();
+        let mut writer = TupleToMessagePackSerializer::new(&mut output, Framing::Sequence);
+        for tuple in tuples {
+            writer.write_tuple(tuple).unwrap();
+        }
+        writer.flush().unwrap();
+        drop(writer);
+        output
+    }
+
+    #[test]
+    fn timestamps_round_trip_in_their_smallest_form() {
+        let timestamps = [
+            (Utc.with_ymd_and_hms(2024, 5, 6, 7, 8, 9).unwrap(), 4),
+            (Utc.timestamp_opt(1_714_979_289, 123_456_789).unwrap(), 8),
+            (Utc.timestamp_opt(-1, 999_999_999).unwrap(), 12),
+            (Utc.with_ymd_and_hms(2600, 1, 1, 0, 0, 0).unwrap(), 12),
+        ];
+        let tuples: Vec<Tuple> = timestamps
+            .iter()
+            .map(|(ts, _)| {
+                let mut tuple = Tuple::new();
+                tuple.add_field_data("at", ColumnData::Timestamp(*ts));
+                tuple
+            })
+            .collect();
+        let output = write(&tuples);
+
+        let mut deserializer = rmp_serde::Deserializer::new(&output[..]);
+        for (ts, size) in timestamps {
+            let record = HashMap::<String, Timestamp>::deserialize(&mut deserializer).unwrap();
+            let Timestamp(decoded, payload_size) = &record["at"];
+            assert_eq!(*decoded, ts);
+            assert_eq!(*payload_size, size, "{}", ts);
+        }
+    }
+
+    #[test]
+    fn uuids_and_bytes_are_bin_values() {
+        let uuid = uuid::Uuid::new_v4();
+        let mut tuple = Tuple::new();
+        tuple.add_field_data("id", ColumnData::Uuid(uuid));
+        tuple.add_field_data("data", ColumnData::Bytes(vec![0, 1, 255]));
+        let output = write(&[tuple]);
+
+        let record: HashMap<String, Payload> = rmp_serde::from_slice(&output).unwrap();
+        assert_eq!(record["id"].0, uuid.as_bytes());
+        assert_eq!(record["data"].0, [0, 1, 255]);
+    }
+}
@@ -0,0 +1,1133 @@
+use super::protobuf::{encode_key, encode_length_delimited, encode_varint, I64, LEN, VARINT};
+use super::*;
+use crate::data_repr::ColumnData;
+use crate::data_repr::*;
+use crate::definition::schema::{FieldType, RecordSchema};
+use flate2::write::DeflateEncoder;
+use std::io::{Error, ErrorKind, Write};
+use std::rc::Rc;
+
+const MAGIC: &[u8] = b"ORC";
+/// Timestamps are stored as seconds since 2015-01-01 00:00:00 UTC
+const TIMESTAMP_BASE: i64 = 1_420_070_400;
+/// Largest chunk that is compressed on its own
+const BLOCK_SIZE: usize = 256 * 1024;
+/// ORC-135, timestamp statistics are in UTC
+const WRITER_VERSION: u64 = 6;
+
+// Type kinds
+const BOOLEAN: u64 = 0;
+const LONG: u64 = 4;
+const DOUBLE: u64 = 6;
+const STRING: u64 = 7;
+const BINARY: u64 = 8;
+const TIMESTAMP: u64 = 9;
+const LIST: u64 = 10;
+const STRUCT: u64 = 12;
+
+// Stream kinds
+const PRESENT: u64 = 0;
+const DATA: u64 = 1;
+const LENGTH: u64 = 2;
+const SECONDARY: u64 = 5;
+
+/**
+ * OrcCompression
+ *
+ * Compression of the streams and metadata inside an ORC file
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OrcCompression {
+    #[default]
+    None,
+    Zlib,
+    Zstd,
+}
+
+impl OrcCompression {
+    /// Value of the PostScript's compression kind
+    fn kind(&self) -> u64 {
+        match self {
+            OrcCompression::None => 0,
+            OrcCompression::Zlib => 1,
+            OrcCompression::Zstd => 5,
+        }
+    }
+}
+
+/// A protobuf message of the file metadata, built field by field
+#[derive(Default)]
+struct Message(Vec<u8>);
+
+impl Message {
+    fn uint(&mut self, number: u32, value: u64) -> &mut Self {
+        encode_key(&mut self.0, number, VARINT);
+        encode_varint(&mut self.0, value);
+        self
+    }
+
+    fn sint(&mut self, number: u32, value: i64) -> &mut Self {
+        self.uint(number, ((value << 1) ^ (value >> 63)) as u64)
+    }
+
+    fn double(&mut self, number: u32, value: f64) -> &mut Self {
+        encode_key(&mut self.0, number, I64);
+        self.0.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    fn bytes(&mut self, number: u32, value: &[u8]) -> &mut Self {
+        encode_key(&mut self.0, number, LEN);
+        encode_length_delimited(&mut self.0, value);
+        self
+    }
+
+    fn message(&mut self, number: u32, value: &Message) -> &mut Self {
+        self.bytes(number, &value.0)
+    }
+
+    fn packed<I: IntoIterator<Item = u64>>(&mut self, number: u32, values: I) -> &mut Self {
+        let mut packed = Vec::new();
+        for value in values {
+            encode_varint(&mut packed, value);
+        }
+        if !packed.is_empty() {
+            self.bytes(number, &packed);
+        }
+        self
+    }
+}
+
+/// Byte run length encoding: runs of 3 to 130 equal bytes, or up to 128 literals
+fn byte_rle(values: &[u8]) -> Vec<u8> {
+    fn literals(out: &mut Vec<u8>, literals: &[u8]) {
+        for chunk in literals.chunks(128) {
+            out.push(-(chunk.len() as i16) as u8);
+            out.extend_from_slice(chunk);
+        }
+    }
+    let mut out = Vec::new();
+    let mut literals_start = 0;
+    let mut i = 0;
+    while i < values.len() {
+        let mut run = 1;
+        while i + run < values.len() && run < 130 && values[i + run] == values[i] {
+            run += 1;
+        }
+        if run >= 3 {
+            literals(&mut out, &values[literals_start..i]);
+            out.push((run - 3) as u8);
+            out.push(values[i]);
+            i += run;
+            literals_start = i;
+        } else {
+            i += 1;
+        }
+    }
+    literals(&mut out, &values[literals_start..]);
+    out
+}
+
+/// Bits are packed from the most significant one down, then byte run length encoded
+fn boolean_rle(values: &[bool]) -> Vec<u8> {
+    let bytes: Vec<u8> = values
+        .chunks(8)
+        .map(|bits| {
+            bits.iter()
+                .enumerate()
+                .fold(0, |byte, (i, bit)| byte | (u8::from(*bit) << (7 - i)))
+        })
+        .collect();
+    byte_rle(&bytes)
+}
+
+/// Integer run length encoding, version 1: runs of 3 to 130 values with a fixed
+/// delta between -128 and 127, or up to 128 literal varints
+fn int_rle(values: &[i64], signed: bool) -> Vec<u8> {
+    fn varint(out: &mut Vec<u8>, value: i64, signed: bool) {
+        if signed {
+            encode_varint(out, ((value << 1) ^ (value >> 63)) as u64)
+        } else {
+            encode_varint(out, value as u64)
+        }
+    }
+    fn literals(out: &mut Vec<u8>, literals: &[i64], signed: bool) {
+        for chunk in literals.chunks(128) {
+            out.push(-(chunk.len() as i16) as u8);
+            for value in chunk {
+                varint(out, *value, signed);
+            }
+        }
+    }
+    let mut out = Vec::new();
+    let mut literals_start = 0;
+    let mut i = 0;
+    while i < values.len() {
+        let delta = values
+            .get(i + 1)
+            .and_then(|next| next.checked_sub(values[i]))
+            .filter(|delta| (-128..=127).contains(delta));
+        let mut run = 1;
+        if let Some(delta) = delta {
+            run = 2;
+            while i + run < values.len()
+                && run < 130
+                && values[i + run].checked_sub(values[i + run - 1]) == Some(delta)
+            {
+                run += 1;
+            }
+        }
+        if run >= 3 {
+            literals(&mut out, &values[literals_start..i], signed);
+            out.push((run - 3) as u8);
+            out.push(delta.unwrap_or_default() as i8 as u8);
+            varint(&mut out, values[i], signed);
+            i += run;
+            literals_start = i;
+        } else {
+            i += 1;
+        }
+    }
+    literals(&mut out, &values[literals_start..], signed);
+    out
+}
+
+/// Nanoseconds with their trailing decimal zeros folded into the low 3 bits
+fn encode_nanos(nanos: u32) -> i64 {
+    if nanos == 0 {
+        return 0;
+    }
+    let mut value = nanos;
+    let mut zeros = 0;
+    while value.is_multiple_of(10) {
+        value /= 10;
+        zeros += 1;
+    }
+    if zeros > 1 {
+        (i64::from(value) << 3) | (zeros - 1)
+    } else {
+        i64::from(nanos) << 3
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Statistics {
+    Plain,
+    Int {
+        min: i64,
+        max: i64,
+        sum: Option<i64>,
+    },
+    Double {
+        min: f64,
+        max: f64,
+        sum: f64,
+    },
+    String {
+        min: String,
+        max: String,
+        sum: i64,
+    },
+    Boolean {
+        trues: u64,
+    },
+    Binary {
+        sum: i64,
+    },
+    /// Milliseconds since the epoch
+    Timestamp {
+        min: i64,
+        max: i64,
+    },
+}
+
+impl Statistics {
+    fn merge(&self, other: &Statistics) -> Statistics {
+        match (self, other) {
+            (
+                Statistics::Int { min, max, sum },
+                Statistics::Int {
+                    min: other_min,
+                    max: other_max,
+                    sum: other_sum,
+                },
+            ) => Statistics::Int {
+                min: *min.min(other_min),
+                max: *max.max(other_max),
+                sum: sum.zip(*other_sum).and_then(|(a, b)| a.checked_add(b)),
+            },
+            (
+                Statistics::Double { min, max, sum },
+                Statistics::Double {
+                    min: other_min,
+                    max: other_max,
+                    sum: other_sum,
+                },
+            ) => Statistics::Double {
+                min: min.min(*other_min),
+                max: max.max(*other_max),
+                sum: sum + other_sum,
+            },
+            (
+                Statistics::String { min, max, sum },
+                Statistics::String {
+                    min: other_min,
+                    max: other_max,
+                    sum: other_sum,
+                },
+            ) => Statistics::String {
+                min: min.min(other_min).clone(),
+                max: max.max(other_max).clone(),
+                sum: sum + other_sum,
+            },
+            (Statistics::Boolean { trues }, Statistics::Boolean { trues: other }) => {
+                Statistics::Boolean {
+                    trues: trues + other,
+                }
+            }
+            (Statistics::Binary { sum }, Statistics::Binary { sum: other }) => {
+                Statistics::Binary { sum: sum + other }
+            }
+            (
+                Statistics::Timestamp { min, max },
+                Statistics::Timestamp {
+                    min: other_min,
+                    max: other_max,
+                },
+            ) => Statistics::Timestamp {
+                min: *min.min(other_min),
+                max: *max.max(other_max),
+            },
+            _ => Statistics::Plain,
+        }
+    }
+}
+
+/**
+ * ColumnStatistics
+ *
+ * The type specific part is left out while a column has no values
+ */
+#[derive(Debug, Clone)]
+struct ColumnStatistics {
+    values: u64,
+    has_null: bool,
+    statistics: Option<Statistics>,
+}
+
+impl ColumnStatistics {
+    fn merge(&self, other: &ColumnStatistics) -> ColumnStatistics {
+        ColumnStatistics {
+            values: self.values + other.values,
+            has_null: self.has_null || other.has_null,
+            statistics: match (&self.statistics, &other.statistics) {
+                (Some(a), Some(b)) => Some(a.merge(b)),
+                (a, b) => a.clone().or_else(|| b.clone()),
+            },
+        }
+    }
+
+    fn message(&self) -> Message {
+        let mut message = Message::default();
+        message.uint(1, self.values);
+        let mut typed = Message::default();
+        match &self.statistics {
+            None | Some(Statistics::Plain) => (),
+            Some(Statistics::Int { min, max, sum }) => {
+                typed.sint(1, *min).sint(2, *max);
+                if let Some(sum) = sum {
+                    typed.sint(3, *sum);
+                }
+                message.message(2, &typed);
+            }
+            Some(Statistics::Double { min, max, sum }) => {
+                typed.double(1, *min).double(2, *max).double(3, *sum);
+                message.message(3, &typed);
+            }
+            Some(Statistics::String { min, max, sum }) => {
+                typed
+                    .bytes(1, min.as_bytes())
+                    .bytes(2, max.as_bytes())
+                    .sint(3, *sum);
+                message.message(4, &typed);
+            }
+            Some(Statistics::Boolean { trues }) => {
+                typed.packed(1, Some(*trues));
+                message.message(5, &typed);
+            }
+            Some(Statistics::Binary { sum }) => {
+                typed.sint(1, *sum);
+                message.message(8, &typed);
+            }
+            Some(Statistics::Timestamp { min, max }) => {
+                // The writer's time zone is UTC, so local and UTC bounds are the same
+                typed
+                    .sint(1, *min)
+                    .sint(2, *max)
+                    .sint(3, *min)
+                    .sint(4, *max);
+                message.message(9, &typed);
+            }
+        }
+        message.uint(10, u64::from(self.has_null));
+        message
+    }
+}
+
+enum Values {
+    Boolean(Vec<bool>),
+    Long(Vec<i64>),
+    /// Little endian doubles
+    Double(Vec<u8>),
+    /// Strings and binary
+    Bytes {
+        data: Vec<u8>,
+        lengths: Vec<i64>,
+    },
+    Timestamp {
+        seconds: Vec<i64>,
+        nanos: Vec<u32>,
+    },
+    List {
+        lengths: Vec<i64>,
+        items: Box<Column>,
+    },
+    Struct(Vec<(String, Column)>),
+}
+
+/**
+ * Column
+ *
+ * Values buffered for the current stripe. Child columns only hold values for
+ * rows where their parent isn't NULL.
+ */
+struct Column {
+    kind: u64,
+    present: Vec<bool>,
+    values: Values,
+}
+
+impl Column {
+    fn new(field_type: &FieldType) -> Self {
+        match field_type {
+            FieldType::Integer(_) => Column::with_values(LONG, Values::Long(Vec::new())),
+            FieldType::Float(_) => Column::with_values(DOUBLE, Values::Double(Vec::new())),
+            FieldType::Boolean(_) => Column::with_values(BOOLEAN, Values::Boolean(Vec::new())),
+            FieldType::String(_) | FieldType::Uuid(_) => Column::bytes(STRING),
+            FieldType::Bytes(_) => Column::bytes(BINARY),
+            FieldType::Timestamp(_) => Column::with_values(
+                TIMESTAMP,
+                Values::Timestamp {
+                    seconds: Vec::new(),
+                    nanos: Vec::new(),
+                },
+            ),
+            FieldType::List(t) => Column::with_values(
+                LIST,
+                Values::List {
+                    lengths: Vec::new(),
+                    items: Box::new(Column::new(t)),
+                },
+            ),
+            FieldType::Record(r) => Column::record(r.iter().map(|f| (f.get_name(), f.get_type()))),
+        }
+    }
+
+    fn with_values(kind: u64, values: Values) -> Self {
+        Column {
+            kind,
+            present: Vec::new(),
+            values,
+        }
+    }
+
+    fn bytes(kind: u64) -> Self {
+        Column::with_values(
+            kind,
+            Values::Bytes {
+                data: Vec::new(),
+                lengths: Vec::new(),
+            },
+        )
+    }
+
+    fn record<'a, I: Iterator<Item = (&'a str, &'a FieldType)>>(fields: I) -> Self {
+        let fields = fields
+            .map(|(name, field_type)| (name.to_string(), Column::new(field_type)))
+            .collect();
+        Column::with_values(STRUCT, Values::Struct(fields))
+    }
+
+    fn add(&mut self, data: &ColumnData) -> std::io::Result<()> {
+        if let ColumnData::Null = data {
+            self.present.push(false);
+            return Ok(());
+        }
+        self.present.push(true);
+        match (&mut self.values, data) {
+            (Values::Boolean(values), ColumnData::Boolean(v)) => values.push(*v),
+            (Values::Long(values), ColumnData::Integer(v)) => values.push(*v),
+            (Values::Double(values), ColumnData::Float(v)) => {
+                values.extend_from_slice(&v.to_le_bytes())
+            }
+            (Values::Bytes { data, lengths }, ColumnData::String(v)) => {
+                data.extend_from_slice(v.as_bytes());
+                lengths.push(v.len() as i64);
+            }
+            (Values::Bytes { data, lengths }, ColumnData::Uuid(v)) => {
+                let v = v.to_string();
+                data.extend_from_slice(v.as_bytes());
+                lengths.push(v.len() as i64);
+            }
+            (Values::Bytes { data, lengths }, ColumnData::Bytes(v)) => {
+                data.extend_from_slice(v);
+                lengths.push(v.len() as i64);
+            }
+            (Values::Timestamp { seconds, nanos }, ColumnData::Timestamp(v)) => {
+                let mut s = v.timestamp();
+                let n = v.timestamp_subsec_nanos();
+                // Readers take a second off negative times with sub-millisecond
+                // nanos, as the original writer rounded towards zero
+                if s < 0 && n > 999_999 {
+                    s += 1;
+                }
+                seconds.push(s - TIMESTAMP_BASE);
+                nanos.push(n);
+            }
+            (Values::List { lengths, items }, ColumnData::List(v)) => {
+                lengths.push(v.len() as i64);
+                for item in v {
+                    items.add(item)?;
+                }
+            }
+            (Values::Struct(fields), ColumnData::Record(t)) => Column::add_fields(fields, t)?,
+            (_, data) => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "Can't write {:?} to an ORC column of kind {}",
+                        data, self.kind
+                    ),
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    fn add_fields(fields: &mut [(String, Column)], tuple: &Tuple) -> std::io::Result<()> {
+        for (name, column) in fields {
+            column.add(tuple.get(name).unwrap_or(&ColumnData::Null))?;
+        }
+        Ok(())
+    }
+
+    /// Adds a row of the root struct, which is never NULL
+    fn add_row(&mut self, tuple: &Tuple) -> std::io::Result<()> {
+        self.present.push(true);
+        match &mut self.values {
+            Values::Struct(fields) => Column::add_fields(fields, tuple),
+            _ => Ok(()),
+        }
+    }
+
+    /// Appends the types of this column and its children in column order
+    fn write_types(&self, types: &mut Vec<Message>) {
+        let index = types.len();
+        types.push(Message::default());
+        let mut subtypes = Vec::new();
+        let mut names = Vec::new();
+        match &self.values {
+            Values::List { items, .. } => {
+                subtypes.push(types.len() as u64);
+                items.write_types(types);
+            }
+            Values::Struct(fields) => {
+                for (name, column) in fields {
+                    subtypes.push(types.len() as u64);
+                    names.push(name.as_str());
+                    column.write_types(types);
+                }
+            }
+            _ => (),
+        }
+        let column_type = &mut types[index];
+        column_type.uint(1, self.kind).packed(2, subtypes);
+        for name in names {
+            column_type.bytes(3, name.as_bytes());
+        }
+    }
+
+    /// Encodes the buffered values of this column and its children into streams
+    /// of (kind, column, bytes), clearing them for the next stripe
+    fn flush(
+        &mut self,
+        streams: &mut Vec<(u64, u32, Vec<u8>)>,
+        statistics: &mut Vec<ColumnStatistics>,
+    ) {
+        let column = statistics.len() as u32;
+        let values = self.present.iter().filter(|p| **p).count();
+        let has_null = values < self.present.len();
+        if has_null {
+            streams.push((PRESENT, column, boolean_rle(&self.present)));
+        }
+        self.present.clear();
+        let typed = match &mut self.values {
+            Values::Boolean(v) => {
+                streams.push((DATA, column, boolean_rle(v)));
+                let trues = v.iter().filter(|b| **b).count() as u64;
+                v.clear();
+                Statistics::Boolean { trues }
+            }
+            Values::Long(v) => {
+                streams.push((DATA, column, int_rle(v, true)));
+                let statistics = Statistics::Int {
+                    min: v.iter().copied().min().unwrap_or_default(),
+                    max: v.iter().copied().max().unwrap_or_default(),
+                    sum: v.iter().try_fold(0i64, |sum, v| sum.checked_add(*v)),
+                };
+                v.clear();
+                statistics
+            }
+            Values::Double(v) => {
+                let doubles = v
+                    .chunks(8)
+                    .map(|b| f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]));
+                let statistics = Statistics::Double {
+                    min: doubles.clone().fold(f64::INFINITY, f64::min),
+                    max: doubles.clone().fold(f64::NEG_INFINITY, f64::max),
+                    sum: doubles.sum(),
+                };
+                streams.push((DATA, column, std::mem::take(v)));
+                statistics
+            }
+            Values::Bytes { data, lengths } => {
+                let sum = data.len() as i64;
+                let statistics = if self.kind == STRING {
+                    let mut offset = 0;
+                    let mut min: Option<&[u8]> = None;
+                    let mut max: Option<&[u8]> = None;
+                    for length in lengths.iter() {
+                        let value = &data[offset..offset + *length as usize];
+                        offset += *length as usize;
+                        min = Some(min.map_or(value, |m| m.min(value)));
+                        max = Some(max.map_or(value, |m| m.max(value)));
+                    }
+                    let text = |v: Option<&[u8]>| {
+                        String::from_utf8_lossy(v.unwrap_or_default()).to_string()
+                    };
+                    Statistics::String {
+                        min: text(min),
+                        max: text(max),
+                        sum,
+                    }
+                } else {
+                    Statistics::Binary { sum }
+                };
+                streams.push((DATA, column, std::mem::take(data)));
+                streams.push((LENGTH, column, int_rle(lengths, false)));
+                lengths.clear();
+                statistics
+            }
+            Values::Timestamp { seconds, nanos } => {
+                streams.push((DATA, column, int_rle(seconds, true)));
+                let encoded: Vec<i64> = nanos.iter().map(|n| encode_nanos(*n)).collect();
+                streams.push((SECONDARY, column, int_rle(&encoded, false)));
+                let millis: Vec<i64> = seconds
+                    .iter()
+                    .zip(nanos.iter())
+                    .map(|(s, n)| (s + TIMESTAMP_BASE) * 1000 + i64::from(n / 1_000_000))
+                    .collect();
+                seconds.clear();
+                nanos.clear();
+                Statistics::Timestamp {
+                    min: millis.iter().copied().min().unwrap_or_default(),
+                    max: millis.iter().copied().max().unwrap_or_default(),
+                }
+            }
+            Values::List { lengths, .. } => {
+                streams.push((LENGTH, column, int_rle(lengths, false)));
+                lengths.clear();
+                Statistics::Plain
+            }
+            Values::Struct(_) => Statistics::Plain,
+        };
+        statistics.push(ColumnStatistics {
+            values: values as u64,
+            has_null,
+            statistics: if values > 0 { Some(typed) } else { None },
+        });
+        match &mut self.values {
+            Values::List { items, .. } => items.flush(streams, statistics),
+            Values::Struct(fields) => {
+                for (_, column) in fields {
+                    column.flush(streams, statistics);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/**
+ * TupleToOrcWriter
+ *
+ * Writes an ORC file, buffering tuples column by column until a stripe is full.
+ * Integers, strings and timestamps use the version 1 run length encodings, which
+ * every ORC reader understands. There are no row indexes.
+ */
+pub struct TupleToOrcWriter<T: Write> {
+    wrt: T,
+    schema: Rc<RecordSchema>,
+    compression: OrcCompression,
+    compression_level: Option<u32>,
+    stripe_size: u64,
+    stripe_rows: Option<usize>,
+    // Created from the first tuple, as partitioned output leaves some fields out
+    root: Option<Column>,
+    rows_in_stripe: usize,
+    // Rough size of the rows in the current stripe
+    stripe_bytes: u64,
+    rows: u64,
+    // Bytes written so far
+    offset: u64,
+    stripes: Vec<Message>,
+    stripe_statistics: Vec<Vec<ColumnStatistics>>,
+}
+
+impl<T: Write> TupleToOrcWriter<T> {
+    pub fn new(wrt: T, schema: Rc<RecordSchema>) -> Self {
+        TupleToOrcWriter {
+            wrt,
+            schema,
+            compression: Default::default(),
+            compression_level: None,
+            stripe_size: 64 * 1024 * 1024,
+            stripe_rows: None,
+            root: None,
+            rows_in_stripe: 0,
+            stripe_bytes: 0,
+            rows: 0,
+            offset: 0,
+            stripes: Vec::new(),
+            stripe_statistics: Vec::new(),
+        }
+    }
+
+    pub fn with_compression(mut self, compression: OrcCompression, level: Option<u32>) -> Self {
+        self.compression = compression;
+        self.compression_level = level;
+        self
+    }
+
+    /// Ends stripes after this many rows rather than by size
+    pub fn with_stripe_rows(mut self, stripe_rows: Option<usize>) -> Self {
+        self.stripe_rows = stripe_rows;
+        self
+    }
+
+    fn open(&mut self, tuple: Option<&Tuple>) -> std::io::Result<()> {
+        if self.offset == 0 {
+            self.wrt.write_all(MAGIC)?;
+            self.offset = MAGIC.len() as u64;
+        }
+        if self.root.is_none() {
+            self.root = Some(Column::record(
+                self.schema
+                    .iter()
+                    .filter(|f| tuple.is_none_or(|t| t.get(f.get_name()).is_some()))
+                    .map(|f| (f.get_name(), f.get_type())),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Splits `data` into chunks that are compressed on their own, each with a
+    /// 3 byte header. Chunks that don't get smaller are stored as they are.
+    fn compress(&self, data: Vec<u8>) -> std::io::Result<Vec<u8>> {
+        if self.compression == OrcCompression::None {
+            return Ok(data);
+        }
+        let mut out = Vec::with_capacity(data.len() / 2);
+        for chunk in data.chunks(BLOCK_SIZE) {
+            let compressed = match self.compression {
+                OrcCompression::Zlib => {
+                    // Raw deflate, without a zlib header
+                    let level = self
+                        .compression_level
+                        .map_or(flate2::Compression::default(), flate2::Compression::new);
+                    let mut encoder = DeflateEncoder::new(Vec::new(), level);
+                    encoder.write_all(chunk)?;
+                    encoder.finish()?
+                }
+                OrcCompression::Zstd => zstd::bulk::compress(
+                    chunk,
+                    self.compression_level
+                        .map_or(zstd::DEFAULT_COMPRESSION_LEVEL, |l| l as i32),
+                )?,
+                OrcCompression::None => unreachable!(),
+            };
+            let (header, body) = if compressed.len() < chunk.len() {
+                (compressed.len() << 1, compressed.as_slice())
+            } else {
+                ((chunk.len() << 1) | 1, chunk)
+            };
+            out.extend_from_slice(&(header as u32).to_le_bytes()[..3]);
+            out.extend_from_slice(body);
+        }
+        Ok(out)
+    }
+
+    fn write_stripe(&mut self) -> std::io::Result<()> {
+        if self.rows_in_stripe == 0 {
+            return Ok(());
+        }
+        let mut streams = Vec::new();
+        let mut statistics = Vec::new();
+        let root = self.root.as_mut().unwrap(); // opened with the first tuple
+        root.flush(&mut streams, &mut statistics);
+
+        let mut stripe_footer = Message::default();
+        let mut data_length = 0;
+        for (kind, column, data) in streams {
+            let data = self.compress(data)?;
+            self.wrt.write_all(&data)?;
+            data_length += data.len() as u64;
+            let mut stream = Message::default();
+            stream
+                .uint(1, kind)
+                .uint(2, u64::from(column))
+                .uint(3, data.len() as u64);
+            stripe_footer.message(1, &stream);
+        }
+        for _ in &statistics {
+            // DIRECT
+            stripe_footer.message(2, Message::default().uint(1, 0));
+        }
+        stripe_footer.bytes(3, b"UTC");
+        let stripe_footer = self.compress(stripe_footer.0)?;
+        self.wrt.write_all(&stripe_footer)?;
+
+        let mut stripe = Message::default();
+        stripe
+            .uint(1, self.offset)
+            .uint(2, 0)
+            .uint(3, data_length)
+            .uint(4, stripe_footer.len() as u64)
+            .uint(5, self.rows_in_stripe as u64);
+        self.stripes.push(stripe);
+        self.stripe_statistics.push(statistics);
+        self.offset += data_length + stripe_footer.len() as u64;
+        self.rows_in_stripe = 0;
+        self.stripe_bytes = 0;
+        Ok(())
+    }
+}
+
+impl<T: Write> TupleWriter for TupleToOrcWriter<T> {
+    fn supports_list(&self) -> bool {
+        true
+    }
+    fn supports_record(&self) -> bool {
+        true
+    }
+
+    fn write_tuple(&mut self, tuple: &Tuple) -> std::io::Result<()> {
+        self.open(Some(tuple))?;
+        let root = self.root.as_mut().unwrap(); // just opened
+        root.add_row(tuple)?;
+        self.rows_in_stripe += 1;
+        self.stripe_bytes += tuple.estimated_size();
+        self.rows += 1;
+        let full = match self.stripe_rows {
+            Some(rows) => self.rows_in_stripe >= rows,
+            None => self.stripe_bytes >= self.stripe_size,
+        };
+        if full {
+            self.write_stripe()?;
+        }
+        Ok(())
+    }
+
+    /// Only flushes stripes that are already written, the current one stays buffered
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.wrt.flush()
+    }
+
+    fn buffered_bytes(&self) -> u64 {
+        self.stripe_bytes
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        self.open(None)?;
+        self.write_stripe()?;
+        let root = self.root.as_ref().unwrap(); // just opened
+
+        // File statistics are the merged stripe statistics
+        let mut file_statistics: Option<Vec<ColumnStatistics>> = None;
+        let mut metadata = Message::default();
+        for stripe in &self.stripe_statistics {
+            let mut stripe_message = Message::default();
+            for column in stripe {
+                stripe_message.message(1, &column.message());
+            }
+            metadata.message(1, &stripe_message);
+            file_statistics = Some(match file_statistics {
+                Some(file) => file.iter().zip(stripe).map(|(a, b)| a.merge(b)).collect(),
+                None => stripe.clone(),
+            });
+        }
+
+        let mut types = Vec::new();
+        root.write_types(&mut types);
+        let file_statistics = file_statistics.unwrap_or_else(|| {
+            let empty = ColumnStatistics {
+                values: 0,
+                has_null: false,
+                statistics: None,
+            };
+            vec![empty; types.len()]
+        });
+        let mut footer = Message::default();
+        footer
+            .uint(1, MAGIC.len() as u64)
+            .uint(2, self.offset - MAGIC.len() as u64);
+        for stripe in &self.stripes {
+            footer.message(3, stripe);
+        }
+        for column_type in &types {
+            footer.message(4, column_type);
+        }
+        footer.uint(6, self.rows);
+        for column in &file_statistics {
+            footer.message(7, &column.message());
+        }
+        footer.uint(8, 0);
+
+        let metadata = self.compress(metadata.0)?;
+        let footer = self.compress(footer.0)?;
+        let mut postscript = Message::default();
+        postscript
+            .uint(1, footer.len() as u64)
+            .uint(2, self.compression.kind())
+            .uint(3, BLOCK_SIZE as u64)
+            .packed(4, [0, 12])
+            .uint(5, metadata.len() as u64)
+            .uint(6, WRITER_VERSION)
+            .bytes(8000, MAGIC);
+        self.wrt.write_all(&metadata)?;
+        self.wrt.write_all(&footer)?;
+        self.wrt.write_all(&postscript.0)?;
+        self.wrt.write_all(&[postscript.0.len() as u8])?;
+        self.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::definition::schema::FieldSchema;
+    use std::io::Read;
+
+    // Examples from the ORC specification
+
+    #[test]
+    fn byte_runs() {
+        assert_eq!(byte_rle(&[0; 100]), [0x61, 0x00]);
+        assert_eq!(byte_rle(&[0x44, 0x45]), [0xfe, 0x44, 0x45]);
+        // Runs longer than 130 are split, literals longer than 128 too
+        assert_eq!(byte_rle(&[7; 131]), [0x7f, 0x07, 0xff, 0x07]);
+        let literals: Vec<u8> = (0..129).collect();
+        let encoded = byte_rle(&literals);
+        assert_eq!(encoded[0], 0x80);
+        assert_eq!(encoded[129..], [0xff, 128]);
+    }
+
+    #[test]
+    fn boolean_runs() {
+        let mut bits = [false; 8];
+        bits[0] = true;
+        assert_eq!(boolean_rle(&bits), [0xff, 0x80]);
+        // The last byte is padded with zeros
+        assert_eq!(boolean_rle(&[true, true, false]), [0xff, 0xc0]);
+        assert_eq!(boolean_rle(&[true; 24]), [0x00, 0xff]);
+    }
+
+    #[test]
+    fn integer_runs() {
+        assert_eq!(int_rle(&[7; 100], false), [0x61, 0x00, 0x07]);
+        let descending: Vec<i64> = (1..=100).rev().collect();
+        assert_eq!(int_rle(&descending, false), [0x61, 0xff, 0x64]);
+        assert_eq!(
+            int_rle(&[2, 3, 6, 7, 11], false),
+            [0xfb, 0x02, 0x03, 0x06, 0x07, 0x0b]
+        );
+        // Signed values are zigzag encoded
+        assert_eq!(int_rle(&[-1, 1], true), [0xfe, 0x01, 0x02]);
+        assert_eq!(int_rle(&[-1, -2, -3], true), [0x00, 0xff, 0x01]);
+        // Deltas must fit a byte
+        assert_eq!(
+            int_rle(&[0, 200, 400], false),
+            [0xfd, 0x00, 0xc8, 0x01, 0x90, 0x03]
+        );
+    }
+
+    #[test]
+    fn nanos() {
+        assert_eq!(encode_nanos(0), 0);
+        assert_eq!(encode_nanos(1000), 0x0a);
+        assert_eq!(encode_nanos(100_000), 0x0c);
+        // A single trailing zero isn't worth folding
+        assert_eq!(encode_nanos(10), 10 << 3);
+        assert_eq!(encode_nanos(123_456_789), 123_456_789 << 3);
+        assert_eq!(encode_nanos(500_000_000), (5 << 3) | 7);
+    }
+
+    enum Value {
+        Varint(u64),
+        Bytes(Vec<u8>),
+    }
+
+    /// Fields of a protobuf message, with fixed width values left out
+    fn decode(mut buf: &[u8]) -> Vec<(u32, Value)> {
+        fn varint(buf: &mut &[u8]) -> u64 {
+            let mut value = 0;
+            let mut shift = 0;
+            loop {
+                let byte = buf[0];
+                *buf = &buf[1..];
+                value |= u64::from(byte & 0x7f) << shift;
+                if byte < 0x80 {
+                    return value;
+                }
+                shift += 7;
+            }
+        }
+        let mut fields = Vec::new();
+        while !buf.is_empty() {
+            let key = varint(&mut buf);
+            let number = (key >> 3) as u32;
+            match key as u8 & 7 {
+                VARINT => fields.push((number, Value::Varint(varint(&mut buf)))),
+                LEN => {
+                    let length = varint(&mut buf) as usize;
+                    fields.push((number, Value::Bytes(buf[..length].to_vec())));
+                    buf = &buf[length..];
+                }
+                I64 => buf = &buf[8..],
+                wire_type => panic!("Unexpected wire type {}", wire_type),
+            }
+        }
+        fields
+    }
+
+    fn uint(fields: &[(u32, Value)], number: u32) -> u64 {
+        match fields.iter().find(|(n, _)| *n == number) {
+            Some((_, Value::Varint(v))) => *v,
+            _ => panic!("No varint field {}", number),
+        }
+    }
+
+    fn messages(fields: &[(u32, Value)], number: u32) -> Vec<Vec<(u32, Value)>> {
+        fields
+            .iter()
+            .filter_map(|(n, v)| match v {
+                Value::Bytes(b) if *n == number => Some(decode(b)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn decompress(compression: OrcCompression, mut data: &[u8]) -> Vec<u8> {
+        if compression == OrcCompression::None {
+            return data.to_vec();
+        }
+        let mut out = Vec::new();
+        while !data.is_empty() {
+            let header = u32::from_le_bytes([data[0], data[1], data[2], 0]);
+            let (length, original) = ((header >> 1) as usize, header & 1 == 1);
+            let chunk = &data[3..3 + length];
+            if original {
+                out.extend_from_slice(chunk);
+            } else {
+                flate2::read::DeflateDecoder::new(chunk)
+                    .read_to_end(&mut out)
+                    .unwrap();
+            }
+            data = &data[3 + length..];
+        }
+        out
+    }
+
+    #[test]
+    fn file_structure() {
+        let schema = RecordSchema::new()
+            .with_field(FieldSchema::new(
+                "id",
+                FieldType::Integer(Default::default()),
+            ))
+            .with_field(FieldSchema::new(
+                "name",
+                FieldType::String(Default::default()),
+            ));
+        for compression in [OrcCompression::None, OrcCompression::Zlib] {
+            let mut file = Vec::new();
+            let mut writer = TupleToOrcWriter::new(&mut file, Rc::new(schema.clone()))
+                .with_compression(compression, None)
+                .with_stripe_rows(Some(2));
+            for id in 0..5 {
+                let mut tuple = Tuple::new();
+                tuple.add_field_data("id", ColumnData::Integer(id));
+                let name = match id {
+                    3 => ColumnData::Null,
+                    _ => ColumnData::String("x".repeat(id as usize)),
+                };
+                tuple.add_field_data("name", name);
+                writer.write_tuple(&tuple).unwrap();
+            }
+            writer.finish().unwrap();
+            drop(writer);
+
+            assert_eq!(&file[..3], MAGIC);
+            let postscript_length = *file.last().unwrap() as usize;
+            let postscript_start = file.len() - 1 - postscript_length;
+            let postscript = decode(&file[postscript_start..file.len() - 1]);
+            assert_eq!(uint(&postscript, 2), compression.kind());
+            let footer_start = postscript_start - uint(&postscript, 1) as usize;
+            let metadata_start = footer_start - uint(&postscript, 5) as usize;
+            let footer = decode(&decompress(
+                compression,
+                &file[footer_start..postscript_start],
+            ));
+
+            assert_eq!(uint(&footer, 1), 3);
+            assert_eq!(uint(&footer, 2) as usize, metadata_start - 3);
+            assert_eq!(uint(&footer, 6), 5);
+            assert_eq!(messages(&footer, 4).len(), 3); // struct, id and name
+            assert_eq!(messages(&footer, 7).len(), 3);
+
+            // Stripes follow each other, and each one's streams fill its data
+            let stripes = messages(&footer, 3);
+            let rows: Vec<_> = stripes.iter().map(|s| uint(s, 5)).collect();
+            assert_eq!(rows, [2, 2, 1]);
+            let mut offset = 3;
+            for (i, stripe) in stripes.iter().enumerate() {
+                assert_eq!(uint(stripe, 1), offset);
+                assert_eq!(uint(stripe, 2), 0);
+                let data_end = (offset + uint(stripe, 3)) as usize;
+                let stripe_end = data_end + uint(stripe, 4) as usize;
+                let stripe_footer = decode(&decompress(compression, &file[data_end..stripe_end]));
+                let streams = messages(&stripe_footer, 1);
+                let stream_lengths: u64 = streams.iter().map(|s| uint(s, 3)).sum();
+                assert_eq!(stream_lengths, uint(stripe, 3));
+                // Only the stripe with the NULL name has a present stream
+                let present = streams
+                    .iter()
+                    .any(|s| uint(s, 1) == PRESENT && uint(s, 2) == 2);
+                assert_eq!(present, i == 1);
+                assert_eq!(messages(&stripe_footer, 2).len(), 3);
+                offset = stripe_end as u64;
+            }
+            assert_eq!(offset as usize, metadata_start);
+        }
+    }
+}
@@ -0,0 +1,275 @@
+use super::rotating::PartCounter;
+use super::*;
+use crate::data_repr::ColumnData;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Opens a TupleWriter for a new file in the given partition directory,
+/// numbering it from the partition's PartCounter.
+pub type PartitionWriterFactory =
+    Box<dyn FnMut(&Path, &PartCounter) -> std::io::Result<Box<dyn TupleWriter>>>;
+
+// Hive's name for partitions whose value is missing
+const DEFAULT_PARTITION: &str = "__HIVE_DEFAULT_PARTITION__";
+
+/**
+ * PartitionedTupleWriter
+ *
+ * Routes tuples into Hive style `field=value` directories under `base_dir`,
+ * leaving the partition fields out of the written tuples. Only `max_open`
+ * partitions are kept open; the least recently used one is finished to make
+ * room, and gets a new file if it's written to again.
+ */
+pub struct PartitionedTupleWriter {
+    factory: PartitionWriterFactory,
+    base_dir: PathBuf,
+    partition_by: Vec<String>,
+    max_open: usize,
+    open: HashMap<PathBuf, (Box<dyn TupleWriter>, u64)>,
+    parts: HashMap<PathBuf, PartCounter>,
+    writes: u64,
+}
+
+impl PartitionedTupleWriter {
+    /// `max_open` has to be at least 1
+    pub fn new<P: Into<PathBuf>>(
+        factory: PartitionWriterFactory,
+        base_dir: P,
+        partition_by: Vec<String>,
+        max_open: usize,
+    ) -> Self {
+        PartitionedTupleWriter {
+            factory,
+            base_dir: base_dir.into(),
+            partition_by,
+            max_open,
+            open: HashMap::new(),
+            parts: HashMap::new(),
+            writes: 0,
+        }
+    }
+
+    fn partition_dir(&self, tuple: &Tuple) -> std::io::Result<PathBuf> {
+        let mut dir = self.base_dir.clone();
+        for field in &self.partition_by {
+            let value = match tuple.get(field) {
+                Some(ColumnData::Null) | None => DEFAULT_PARTITION.to_string(),
+                Some(ColumnData::Record(_)) | Some(ColumnData::List(_)) => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        format!("Can't partition by {}, it isn't a scalar", field),
+                    ))
+                }
+                Some(data) => escape_path_name(&data.to_string()),
+            };
+            dir.push(format!("{}={}", escape_path_name(field), value));
+        }
+        Ok(dir)
+    }
+
+    fn evict_least_recently_used(&mut self) -> std::io::Result<()> {
+        let oldest = self
+            .open
+            .iter()
+            .min_by_key(|(_, (_, last_write))| *last_write)
+            .map(|(dir, _)| dir.clone());
+        if let Some((mut writer, _)) = oldest.and_then(|dir| self.open.remove(&dir)) {
+            writer.finish()?;
+        }
+        Ok(())
+    }
+}
+
+// Whether nested data can be written is up to the writers for each partition
+impl TupleWriter for PartitionedTupleWriter {
+    fn supports_list(&self) -> bool {
+        true
+    }
+    fn supports_record(&self) -> bool {
+        true
+    }
+
+    fn write_tuple(&mut self, tuple: &Tuple) -> std::io::Result<()> {
+        let dir = self.partition_dir(tuple)?;
+        if !self.open.contains_key(&dir) {
+            if self.open.len() >= self.max_open {
+                self.evict_least_recently_used()?;
+            }
+            std::fs::create_dir_all(&dir)?;
+            let parts = self.parts.entry(dir.clone()).or_default();
+            let writer = (self.factory)(&dir, parts)?;
+            self.open.insert(dir.clone(), (writer, 0));
+        }
+
+        let mut data = Tuple::new();
+        for (name, value) in tuple {
+            if !self.partition_by.contains(name) {
+                data.add_field_data(name.as_str(), value.clone());
+            }
+        }
+        self.writes += 1;
+        let (writer, last_write) = self.open.get_mut(&dir).unwrap();
+        *last_write = self.writes;
+        writer.write_tuple(&data)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        for (writer, _) in self.open.values_mut() {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    fn buffered_bytes(&self) -> u64 {
+        self.open
+            .values()
+            .map(|(writer, _)| writer.buffered_bytes())
+            .sum()
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        for (_, (mut writer, _)) in self.open.drain() {
+            writer.finish()?;
+        }
+        Ok(())
+    }
+}
+
+/// Percent encodes the characters Hive doesn't allow in partition directory names
+fn escape_path_name(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for c in name.chars() {
+        match c {
+            '\u{01}'..='\u{1F}'
+            | '"'
+            | '#'
+            | '%'
+            | '\''
+            | '*'
+            | '/'
+            | ':'
+            | '='
+            | '?'
+            | '\\'
+            | '\u{7F}'
+            | '{'
+            | '['
+            | ']'
+            | '^' => escaped.push_str(&format!("%{:02X}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    if escaped.is_empty() {
+        DEFAULT_PARTITION.to_string()
+    } else {
+        escaped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::writer::json::{JsonStyle, TupleToJsonSerializer};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn hive_escaping() {
+        assert_eq!(escape_path_name("plain value-1.5_é"), "plain value-1.5_é");
+        assert_eq!(
+            escape_path_name("2024-01-01T10:30:00Z"),
+            "2024-01-01T10%3A30%3A00Z"
+        );
+        assert_eq!(escape_path_name("a/b=c"), "a%2Fb%3Dc");
+        assert_eq!(escape_path_name("100%"), "100%25");
+        assert_eq!(escape_path_name("[x]\t\u{7f}"), "%5Bx%5D%09%7F");
+        assert_eq!(escape_path_name(""), DEFAULT_PARTITION);
+    }
+
+    /// (partition directory, part number) of every file opened
+    type Opened = Rc<RefCell<Vec<(PathBuf, u64)>>>;
+
+    fn writer(base_dir: &Path, max_open: usize) -> (PartitionedTupleWriter, Opened) {
+        let opened = Opened::default();
+        let log = opened.clone();
+        let base = base_dir.to_path_buf();
+        let factory: PartitionWriterFactory = Box::new(move |dir, parts| {
+            let dir = dir.strip_prefix(&base).unwrap().to_path_buf();
+            log.borrow_mut().push((dir, parts.get()));
+            parts.set(parts.get() + 1);
+            Ok(Box::new(TupleToJsonSerializer::new(
+                std::io::sink(),
+                JsonStyle::Array,
+            )))
+        });
+        let partition_by = vec!["region".to_string(), "day".to_string()];
+        (
+            PartitionedTupleWriter::new(factory, base_dir, partition_by, max_open),
+            opened,
+        )
+    }
+
+    fn tuple(region: ColumnData, day: &str) -> Tuple {
+        let mut tuple = Tuple::new();
+        tuple.add_field_data("id", ColumnData::Integer(1));
+        tuple.add_field_data("region", region);
+        tuple.add_field_data("day", ColumnData::String(day.to_string()));
+        tuple
+    }
+
+    fn region(name: &str) -> ColumnData {
+        ColumnData::String(name.to_string())
+    }
+
+    #[test]
+    fn least_recently_used_partitions_are_reopened_with_a_new_file() {
+        let base = std::env::temp_dir().join(format!("datablaster-lru-{}", std::process::id()));
+        let (mut writer, opened) = writer(&base, 2);
+        for name in ["a", "b", "a", "c", "b", "b", "a"] {
+            writer.write_tuple(&tuple(region(name), "1")).unwrap();
+            assert!(writer.open.len() <= 2);
+        }
+        writer.finish().unwrap();
+        assert!(writer.open.is_empty());
+        let opened: Vec<_> = opened
+            .borrow()
+            .iter()
+            .map(|(dir, part)| (dir.to_string_lossy().to_string(), *part))
+            .collect();
+        // c pushes b out, as a was written more recently, then b pushes a out
+        let expected = [
+            ("region=a/day=1", 0),
+            ("region=b/day=1", 0),
+            ("region=c/day=1", 0),
+            ("region=b/day=1", 1),
+            ("region=a/day=1", 1),
+        ];
+        let expected: Vec<_> = expected.iter().map(|(d, p)| (d.to_string(), *p)).collect();
+        assert_eq!(opened, expected);
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn partition_directories() {
+        let base = std::env::temp_dir().join(format!("datablaster-dirs-{}", std::process::id()));
+        let (writer, _) = writer(&base, 1);
+        let dir = |region, day| {
+            writer
+                .partition_dir(&tuple(region, day))
+                .unwrap()
+                .strip_prefix(&base)
+                .unwrap()
+                .to_string_lossy()
+                .to_string()
+        };
+        assert_eq!(dir(region("x"), "09:00"), "region=x/day=09%3A00");
+        assert_eq!(
+            dir(ColumnData::Null, ""),
+            format!("region={0}/day={0}", DEFAULT_PARTITION)
+        );
+        assert_eq!(dir(ColumnData::Integer(-3), "1"), "region=-3/day=1");
+        assert!(writer
+            .partition_dir(&tuple(ColumnData::List(Vec::new()), "1"))
+            .is_err());
+    }
+}
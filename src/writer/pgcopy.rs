@@ -0,0 +1,443 @@
+use super::*;
+use crate::data_repr::ColumnData;
+use crate::data_repr::*;
+use crate::definition::schema::{FieldType, RecordSchema};
+use std::io::{Error, ErrorKind, Write};
+use std::rc::Rc;
+
+/**
+ * TupleToPgCopySerializer
+ *
+ * PostgreSQL's COPY text format: tab separated columns, `\N` for NULL and
+ * backslash escapes. Records and lists become composite and array literals.
+ */
+pub struct TupleToPgCopySerializer<T: Write> {
+    wrt: T,
+}
+
+impl<T: Write> TupleToPgCopySerializer<T> {
+    pub fn new(wrt: T) -> Self {
+        TupleToPgCopySerializer { wrt }
+    }
+}
+
+/// The value as PostgreSQL would print it, or None for NULL
+fn text_value(data: &ColumnData) -> Option<String> {
+    match data {
+        ColumnData::Integer(v) => Some(v.to_string()),
+        ColumnData::Float(v) if v.is_nan() => Some("NaN".to_string()),
+        ColumnData::Float(v) if v.is_infinite() && *v > 0.0 => Some("Infinity".to_string()),
+        ColumnData::Float(v) if v.is_infinite() => Some("-Infinity".to_string()),
+        ColumnData::Float(v) => Some(v.to_string()),
+        ColumnData::Boolean(v) => Some(if *v { "t" } else { "f" }.to_string()),
+        ColumnData::String(v) => Some(v.to_string()),
+        ColumnData::Uuid(v) => Some(v.to_string()),
+        ColumnData::Bytes(v) => Some(format!("\\x{}", hex::encode(v))),
+        ColumnData::Timestamp(v) => Some(format_timestamp(v)),
+        ColumnData::Null => None,
+        ColumnData::Record(t) => Some(composite_literal(t)),
+        ColumnData::List(v) => Some(array_literal(v)),
+    }
+}
+
+fn quote_literal(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn composite_literal(tuple: &Tuple) -> String {
+    let fields: Vec<String> = tuple
+        .into_iter()
+        .map(|(_, data)| match text_value(data) {
+            // A NULL field is left empty, so an empty string has to be quoted
+            None => String::new(),
+            Some(s)
+                if s.is_empty()
+                    || s.chars()
+                        .any(|c| "(),\"\\".contains(c) || c.is_whitespace()) =>
+            {
+                quote_literal(&s)
+            }
+            Some(s) => s,
+        })
+        .collect();
+    format!("({})", fields.join(","))
+}
+
+fn array_literal(list: &[ColumnData]) -> String {
+    let elements: Vec<String> = list
+        .iter()
+        .map(|data| match (data, text_value(data)) {
+            (_, None) => "NULL".to_string(),
+            // Nested lists are further dimensions of the same array
+            (ColumnData::List(_), Some(s)) => s,
+            (_, Some(s))
+                if s.is_empty()
+                    || s.eq_ignore_ascii_case("NULL")
+                    || s.chars()
+                        .any(|c| "{},\"\\".contains(c) || c.is_whitespace()) =>
+            {
+                quote_literal(&s)
+            }
+            (_, Some(s)) => s,
+        })
+        .collect();
+    format!("{{{}}}", elements.join(","))
+}
+
+fn copy_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+impl<T: Write> TupleWriter for TupleToPgCopySerializer<T> {
+    fn supports_list(&self) -> bool {
+        true
+    }
+    fn supports_record(&self) -> bool {
+        true
+    }
+
+    fn write_tuple(&mut self, tuple: &Tuple) -> std::io::Result<()> {
+        let row: Vec<String> = tuple
+            .into_iter()
+            .map(|(_, data)| match text_value(data) {
+                Some(s) => copy_escape(&s),
+                None => "\\N".to_string(),
+            })
+            .collect();
+        writeln!(self.wrt, "{}", row.join("\t"))
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.wrt.flush()
+    }
+}
+
+/**
+ * TupleToPgCopyBinarySerializer
+ *
+ * PostgreSQL's binary COPY format. Lists become one dimensional arrays. Records
+ * aren't supported, as their binary form needs the type ids of the target table.
+ */
+pub struct TupleToPgCopyBinarySerializer<T: Write> {
+    wrt: T,
+    schema: Rc<RecordSchema>,
+    wrote_header: bool,
+}
+
+const SIGNATURE: &[u8] = b"PGCOPY\n\xff\r\n\0";
+// Microseconds between the Unix and PostgreSQL epochs (2000-01-01)
+const POSTGRES_EPOCH_MICROS: i64 = 946_684_800_000_000;
+
+impl<T: Write> TupleToPgCopyBinarySerializer<T> {
+    pub fn new(wrt: T, schema: Rc<RecordSchema>) -> Self {
+        TupleToPgCopyBinarySerializer {
+            wrt,
+            schema,
+            wrote_header: false,
+        }
+    }
+
+    fn write_header(&mut self) -> std::io::Result<()> {
+        if !self.wrote_header {
+            self.wrt.write_all(SIGNATURE)?;
+            // Flags, then the length of the header extension
+            self.wrt.write_all(&0i32.to_be_bytes())?;
+            self.wrt.write_all(&0i32.to_be_bytes())?;
+            self.wrote_header = true;
+        }
+        Ok(())
+    }
+}
+
+/// Type id of the PostgreSQL type a scalar field is written as
+fn type_oid(field_type: &FieldType) -> std::io::Result<u32> {
+    match field_type {
+        FieldType::Integer(_) => Ok(20),     // int8
+        FieldType::Float(_) => Ok(701),      // float8
+        FieldType::Boolean(_) => Ok(16),     // bool
+        FieldType::String(_) => Ok(25),      // text
+        FieldType::Uuid(_) => Ok(2950),      // uuid
+        FieldType::Bytes(_) => Ok(17),       // bytea
+        FieldType::Timestamp(_) => Ok(1184), // timestamptz
+        FieldType::List(_) => Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Nested lists not supported by binary COPY",
+        )),
+        FieldType::Record(_) => Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Record not supported by binary COPY",
+        )),
+    }
+}
+
+/// Appends a non NULL value of `field_type` to `buf`
+fn binary_value(
+    data: &ColumnData,
+    field_type: &FieldType,
+    buf: &mut Vec<u8>,
+) -> std::io::Result<()> {
+    match data {
+        ColumnData::Integer(v) => buf.extend_from_slice(&v.to_be_bytes()),
+        ColumnData::Float(v) => buf.extend_from_slice(&v.to_be_bytes()),
+        ColumnData::Boolean(v) => buf.push(*v as u8),
+        ColumnData::String(v) => buf.extend_from_slice(v.as_bytes()),
+        ColumnData::Uuid(v) => buf.extend_from_slice(v.as_bytes()),
+        ColumnData::Bytes(v) => buf.extend_from_slice(v),
+        ColumnData::Timestamp(v) => {
+            buf.extend_from_slice(&(v.timestamp_micros() - POSTGRES_EPOCH_MICROS).to_be_bytes())
+        }
+        ColumnData::List(list) => {
+            let element_type = match field_type {
+                FieldType::List(t) => t,
+                _ => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        "List for a scalar field",
+                    ))
+                }
+            };
+            let dimensions: i32 = if list.is_empty() { 0 } else { 1 };
+            let has_null = list.iter().any(|d| matches!(d, ColumnData::Null));
+            buf.extend_from_slice(&dimensions.to_be_bytes());
+            buf.extend_from_slice(&(has_null as i32).to_be_bytes());
+            buf.extend_from_slice(&type_oid(element_type)?.to_be_bytes());
+            if dimensions > 0 {
+                buf.extend_from_slice(&(list.len() as i32).to_be_bytes());
+                // Lower bound
+                buf.extend_from_slice(&1i32.to_be_bytes());
+            }
+            for element in list {
+                write_field(element, element_type, buf)?;
+            }
+        }
+        ColumnData::Record(_) => {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Record not supported by binary COPY",
+            ))
+        }
+        ColumnData::Null => (),
+    }
+    Ok(())
+}
+
+/// Appends the length prefixed value, or -1 for NULL
+fn write_field(
+    data: &ColumnData,
+    field_type: &FieldType,
+    buf: &mut Vec<u8>,
+) -> std::io::Result<()> {
+    if let ColumnData::Null = data {
+        buf.extend_from_slice(&(-1i32).to_be_bytes());
+        return Ok(());
+    }
+    let start = buf.len();
+    buf.extend_from_slice(&0i32.to_be_bytes());
+    binary_value(data, field_type, buf)?;
+    let len = (buf.len() - start - 4) as i32;
+    buf[start..start + 4].copy_from_slice(&len.to_be_bytes());
+    Ok(())
+}
+
+impl<T: Write> TupleWriter for TupleToPgCopyBinarySerializer<T> {
+    fn supports_list(&self) -> bool {
+        true
+    }
+
+    fn write_tuple(&mut self, tuple: &Tuple) -> std::io::Result<()> {
+        self.write_header()?;
+        let mut buf = Vec::new();
+        let mut fields = 0i16;
+        for (name, data) in tuple {
+            let field = self
+                .schema
+                .iter()
+                .find(|f| f.get_name() == name)
+                .ok_or_else(|| {
+                    Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("{} is not in the schema", name),
+                    )
+                })?;
+            write_field(data, field.get_type(), &mut buf)?;
+            fields += 1;
+        }
+        self.wrt.write_all(&fields.to_be_bytes())?;
+        self.wrt.write_all(&buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.wrt.flush()
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        self.write_header()?;
+        // File trailer
+        self.wrt.write_all(&(-1i16).to_be_bytes())?;
+        self.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::definition::schema::FieldSchema;
+    use chrono::{TimeZone, Utc};
+
+    fn string(s: &str) -> ColumnData {
+        ColumnData::String(s.to_string())
+    }
+
+    fn text_row(fields: Vec<ColumnData>) -> String {
+        let mut tuple = Tuple::new();
+        for (i, data) in fields.into_iter().enumerate() {
+            tuple.add_field_data(format!("f{}", i), data);
+        }
+        let mut out = Vec::new();
+        TupleToPgCopySerializer::new(&mut out)
+            .write_tuple(&tuple)
+            .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn text_escapes() {
+        assert_eq!(
+            text_row(vec![
+                ColumnData::Integer(-1),
+                ColumnData::Float(f64::NAN),
+                ColumnData::Float(f64::NEG_INFINITY),
+                ColumnData::Boolean(true),
+                string("a\tb\\c\nd\re"),
+                ColumnData::Null,
+                string(""),
+                ColumnData::Bytes(vec![0xab, 0x01]),
+                ColumnData::Timestamp(Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap()),
+            ]),
+            "-1\tNaN\t-Infinity\tt\ta\\tb\\\\c\\nd\\re\t\\N\t\t\\\\xab01\t2024-01-02T03:04:05Z\n"
+        );
+    }
+
+    #[test]
+    fn composite_and_array_literals() {
+        let mut record = Tuple::new();
+        record.add_field_data("a", ColumnData::Integer(1));
+        record.add_field_data("b", string("x y"));
+        record.add_field_data("c", ColumnData::Null);
+        record.add_field_data("d", string(""));
+        record.add_field_data("e", string("say \"hi\""));
+        assert_eq!(
+            composite_literal(&record),
+            "(1,\"x y\",,\"\",\"say \\\"hi\\\"\")"
+        );
+        let list = vec![
+            string("a"),
+            ColumnData::Null,
+            string("NULL"),
+            string("b,c"),
+            string(""),
+            string("{x}"),
+        ];
+        assert_eq!(
+            array_literal(&list),
+            "{a,NULL,\"NULL\",\"b,c\",\"\",\"{x}\"}"
+        );
+        let nested = vec![
+            ColumnData::List(vec![ColumnData::Integer(1), ColumnData::Integer(2)]),
+            ColumnData::List(vec![ColumnData::Integer(3), ColumnData::Null]),
+        ];
+        assert_eq!(array_literal(&nested), "{{1,2},{3,NULL}}");
+        assert_eq!(array_literal(&[]), "{}");
+        // Backslashes in literals are escaped again for COPY
+        assert_eq!(
+            text_row(vec![ColumnData::List(vec![string("a\\b")])]),
+            "{\"a\\\\\\\\b\"}\n"
+        );
+    }
+
+    #[test]
+    fn binary_format() {
+        let field = |name: &str, field_type: FieldType| FieldSchema::new(name, field_type);
+        let schema = RecordSchema::new()
+            .with_field(field("i", FieldType::Integer(Default::default())))
+            .with_field(field("f", FieldType::Float(Default::default())))
+            .with_field(field("b", FieldType::Boolean(Default::default())))
+            .with_field(field("s", FieldType::String(Default::default())))
+            .with_field(field("u", FieldType::Uuid(Default::default())))
+            .with_field(field("y", FieldType::Bytes(Default::default())))
+            .with_field(field("t", FieldType::Timestamp(Default::default())))
+            .with_field(field(
+                "l",
+                FieldType::List(Box::new(FieldType::Integer(Default::default()))),
+            ))
+            .with_field(field("n", FieldType::String(Default::default())));
+        let mut tuple = Tuple::new();
+        tuple.add_field_data("i", ColumnData::Integer(1));
+        tuple.add_field_data("f", ColumnData::Float(1.5));
+        tuple.add_field_data("b", ColumnData::Boolean(true));
+        tuple.add_field_data("s", string("hi"));
+        tuple.add_field_data("u", ColumnData::Uuid(uuid::Uuid::nil()));
+        tuple.add_field_data("y", ColumnData::Bytes(vec![1, 2]));
+        tuple.add_field_data(
+            "t",
+            ColumnData::Timestamp(Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 1).unwrap()),
+        );
+        tuple.add_field_data(
+            "l",
+            ColumnData::List(vec![ColumnData::Integer(7), ColumnData::Null]),
+        );
+        tuple.add_field_data("n", ColumnData::Null);
+
+        let mut out = Vec::new();
+        let mut writer = TupleToPgCopyBinarySerializer::new(&mut out, Rc::new(schema));
+        writer.write_tuple(&tuple).unwrap();
+        writer.finish().unwrap();
+        drop(writer);
+
+        let expected: Vec<&[u8]> = vec![
+            // Signature, flags and header extension length
+            b"PGCOPY\n\xff\r\n\0",
+            &[0, 0, 0, 0, 0, 0, 0, 0],
+            // Field count
+            &[0, 9],
+            &[0, 0, 0, 8, 0, 0, 0, 0, 0, 0, 0, 1],
+            &[0, 0, 0, 8, 0x3f, 0xf8, 0, 0, 0, 0, 0, 0],
+            &[0, 0, 0, 1, 1],
+            &[0, 0, 0, 2, b'h', b'i'],
+            &[0, 0, 0, 16],
+            &[0; 16],
+            &[0, 0, 0, 2, 1, 2],
+            // A second after the PostgreSQL epoch, in microseconds
+            &[0, 0, 0, 8, 0, 0, 0, 0, 0, 0x0f, 0x42, 0x40],
+            // Array: dimensions, has NULLs, int8, then the length and lower bound
+            &[0, 0, 0, 36],
+            &[0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 20, 0, 0, 0, 2, 0, 0, 0, 1],
+            &[0, 0, 0, 8, 0, 0, 0, 0, 0, 0, 0, 7],
+            &[0xff, 0xff, 0xff, 0xff],
+            // NULL field
+            &[0xff, 0xff, 0xff, 0xff],
+            // Trailer
+            &[0xff, 0xff],
+        ];
+        assert_eq!(out, expected.concat());
+    }
+
+    #[test]
+    fn binary_output_without_rows_is_complete() {
+        let mut out = Vec::new();
+        TupleToPgCopyBinarySerializer::new(&mut out, Rc::new(RecordSchema::new()))
+            .finish()
+            .unwrap();
+        assert_eq!(out, b"PGCOPY\n\xff\r\n\0\0\0\0\0\0\0\0\0\xff\xff");
+    }
+}
@@ -0,0 +1,423 @@
+use super::*;
+use crate::data_repr::ColumnData;
+use crate::data_repr::*;
+use crate::definition::proto::{ProtoField, ProtoMessage, ProtoType};
+use rand::seq::SliceRandom;
+use std::io::{Error, ErrorKind, Write};
+use std::rc::Rc;
+
+// Wire types
+pub const VARINT: u8 = 0;
+pub const I64: u8 = 1;
+pub const LEN: u8 = 2;
+pub const I32: u8 = 5;
+
+pub fn encode_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+pub fn encode_key(buf: &mut Vec<u8>, number: u32, wire_type: u8) {
+    encode_varint(buf, (u64::from(number) << 3) | u64::from(wire_type));
+}
+
+pub fn encode_length_delimited(buf: &mut Vec<u8>, bytes: &[u8]) {
+    encode_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+fn wire_type(proto_type: &ProtoType) -> u8 {
+    match proto_type {
+        ProtoType::Double | ProtoType::Fixed64 | ProtoType::Sfixed64 => I64,
+        ProtoType::Float | ProtoType::Fixed32 | ProtoType::Sfixed32 => I32,
+        ProtoType::String | ProtoType::Bytes | ProtoType::Message(_) | ProtoType::Timestamp => LEN,
+        _ => VARINT,
+    }
+}
+
+fn wrong_type(field: &ProtoField, data: &ColumnData) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!(
+            "Can't write {:?} to {} {}",
+            data,
+            field.get_type().name(),
+            field.get_name()
+        ),
+    )
+}
+
+/// Appends a single value of `field` without its key. Integers that don't fit
+/// the field's type are truncated, like a cast in the generated code would.
+fn encode_value(buf: &mut Vec<u8>, field: &ProtoField, data: &ColumnData) -> std::io::Result<()> {
+    match (field.get_type(), data) {
+        (ProtoType::Double, ColumnData::Float(v)) => buf.extend_from_slice(&v.to_le_bytes()),
+        (ProtoType::Float, ColumnData::Float(v)) => {
+            buf.extend_from_slice(&(*v as f32).to_le_bytes())
+        }
+        (ProtoType::Int32, ColumnData::Integer(v)) => {
+            // Negative values are sign extended to 64 bits
+            encode_varint(buf, i64::from(*v as i32) as u64)
+        }
+        (ProtoType::Int64, ColumnData::Integer(v))
+        | (ProtoType::Uint64, ColumnData::Integer(v)) => encode_varint(buf, *v as u64),
+        (ProtoType::Uint32, ColumnData::Integer(v)) => encode_varint(buf, u64::from(*v as u32)),
+        (ProtoType::Sint32, ColumnData::Integer(v)) => {
+            let v = *v as i32;
+            encode_varint(buf, u64::from(((v << 1) ^ (v >> 31)) as u32))
+        }
+        (ProtoType::Sint64, ColumnData::Integer(v)) => {
+            encode_varint(buf, ((v << 1) ^ (v >> 63)) as u64)
+        }
+        (ProtoType::Fixed32, ColumnData::Integer(v)) => {
+            buf.extend_from_slice(&(*v as u32).to_le_bytes())
+        }
+        (ProtoType::Sfixed32, ColumnData::Integer(v)) => {
+            buf.extend_from_slice(&(*v as i32).to_le_bytes())
+        }
+        (ProtoType::Fixed64, ColumnData::Integer(v))
+        | (ProtoType::Sfixed64, ColumnData::Integer(v)) => buf.extend_from_slice(&v.to_le_bytes()),
+        (ProtoType::Bool, ColumnData::Boolean(v)) => encode_varint(buf, u64::from(*v)),
+        (ProtoType::String, ColumnData::String(v)) => encode_length_delimited(buf, v.as_bytes()),
+        (ProtoType::String, ColumnData::Uuid(v)) => {
+            encode_length_delimited(buf, v.to_string().as_bytes())
+        }
+        (ProtoType::Bytes, ColumnData::Bytes(v)) => encode_length_delimited(buf, v),
+        (ProtoType::Bytes, ColumnData::Uuid(v)) => encode_length_delimited(buf, v.as_bytes()),
+        (ProtoType::Enum(values), ColumnData::String(name)) => {
+            match values.iter().find(|(value_name, _)| value_name == name) {
+                Some((_, number)) => encode_varint(buf, i64::from(*number) as u64),
+                None => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("{} is not a value of {}", name, field.get_name()),
+                    ))
+                }
+            }
+        }
+        (ProtoType::Enum(_), ColumnData::Integer(v)) => {
+            encode_varint(buf, i64::from(*v as i32) as u64)
+        }
+        (ProtoType::Message(message), ColumnData::Record(tuple)) => {
+            let mut nested = Vec::new();
+            encode_message(&mut nested, message, tuple)?;
+            encode_length_delimited(buf, &nested)
+        }
+        (ProtoType::Timestamp, ColumnData::Timestamp(v)) => {
+            // Zero seconds and nanos are the defaults, so they're left out
+            let mut nested = Vec::new();
+            if v.timestamp() != 0 {
+                encode_key(&mut nested, 1, VARINT);
+                encode_varint(&mut nested, v.timestamp() as u64);
+            }
+            if v.timestamp_subsec_nanos() != 0 {
+                encode_key(&mut nested, 2, VARINT);
+                encode_varint(&mut nested, u64::from(v.timestamp_subsec_nanos()));
+            }
+            encode_length_delimited(buf, &nested)
+        }
+        (_, data) => return Err(wrong_type(field, data)),
+    }
+    Ok(())
+}
+
+/// The branch written for each oneof, picked from the ones set in `tuple`
+fn oneof_branches<'a>(message: &'a ProtoMessage, tuple: &Tuple) -> Vec<&'a str> {
+    let mut oneofs: Vec<(&str, Vec<&str>)> = Vec::new();
+    for field in message.iter() {
+        let set = !matches!(tuple.get(field.get_name()), None | Some(ColumnData::Null));
+        match field.get_oneof() {
+            Some(oneof) if set => match oneofs.iter_mut().find(|(name, _)| *name == oneof) {
+                Some((_, branches)) => branches.push(field.get_name()),
+                None => oneofs.push((oneof, vec![field.get_name()])),
+            },
+            _ => (),
+        }
+    }
+    let mut rng = rand::thread_rng();
+    oneofs
+        .iter()
+        .filter_map(|(_, branches)| branches.choose(&mut rng).copied())
+        .collect()
+}
+
+/// Appends the fields of `tuple` in the order they're declared in. NULL fields
+/// and NULL list items are left out, as protobuf has no null. A message holds a
+/// single branch of a oneof, so one of the branches set is picked at random.
+pub fn encode_message(
+    buf: &mut Vec<u8>,
+    message: &ProtoMessage,
+    tuple: &Tuple,
+) -> std::io::Result<()> {
+    for (name, _) in tuple {
+        if message.field(name).is_none() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("{} is not a field of message {}", name, message.get_name()),
+            ));
+        }
+    }
+    let branches = oneof_branches(message, tuple);
+    for field in message.iter() {
+        if field.get_oneof().is_some() && !branches.contains(&field.get_name()) {
+            continue;
+        }
+        match tuple.get(field.get_name()) {
+            None | Some(ColumnData::Null) => (),
+            Some(ColumnData::List(items)) if field.is_repeated() => {
+                let items = items.iter().filter(|d| !matches!(d, ColumnData::Null));
+                if field.get_type().is_packable() {
+                    let mut packed = Vec::new();
+                    for item in items {
+                        encode_value(&mut packed, field, item)?;
+                    }
+                    if !packed.is_empty() {
+                        encode_key(buf, field.get_number(), LEN);
+                        encode_length_delimited(buf, &packed);
+                    }
+                } else {
+                    for item in items {
+                        encode_key(buf, field.get_number(), wire_type(field.get_type()));
+                        encode_value(buf, field, item)?;
+                    }
+                }
+            }
+            Some(data) if field.is_repeated() => return Err(wrong_type(field, data)),
+            Some(data) => {
+                encode_key(buf, field.get_number(), wire_type(field.get_type()));
+                encode_value(buf, field, data)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/**
+ * TupleToProtobufSerializer
+ *
+ * Encodes each tuple as `message`. Protobuf messages don't mark where they end,
+ * so they have to be written with a length-delimited framing.
+ */
+pub struct TupleToProtobufSerializer<T: Write> {
+    wrt: T,
+    message: Rc<ProtoMessage>,
+    framing: Framing,
+    buffer: Vec<u8>,
+}
+
+impl<T: Write> TupleToProtobufSerializer<T> {
+    pub fn new(wrt: T, message: Rc<ProtoMessage>, framing: Framing) -> Self {
+        TupleToProtobufSerializer {
+            wrt,
+            message,
+            framing,
+            buffer: Vec::new(),
+        }
+    }
+}
+
+impl<T: Write> TupleWriter for TupleToProtobufSerializer<T> {
+    fn supports_list(&self) -> bool {
+        true
+    }
+    fn supports_record(&self) -> bool {
+        true
+    }
+
+    fn write_tuple(&mut self, tuple: &Tuple) -> std::io::Result<()> {
+        self.buffer.clear();
+        encode_message(&mut self.buffer, &self.message, tuple)?;
+        self.framing.write_frame(&mut self.wrt, &self.buffer)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.wrt.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    /// Message with a single field `f`, encoded with `f` set to `data`
+    fn encode(number: u32, proto_type: ProtoType, repeated: bool, data: ColumnData) -> Vec<u8> {
+        let mut message = ProtoMessage::new("Test");
+        message.add_field(ProtoField::new("f", number, proto_type, repeated));
+        let mut tuple = Tuple::new();
+        tuple.add_field_data("f", data);
+        let mut buf = Vec::new();
+        encode_message(&mut buf, &message, &tuple).unwrap();
+        buf
+    }
+
+    #[test]
+    fn varints() {
+        for (value, bytes) in [
+            (0, &[0x00][..]),
+            (1, &[0x01]),
+            (150, &[0x96, 0x01]),
+            (300, &[0xac, 0x02]),
+            (
+                u64::MAX,
+                &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01],
+            ),
+        ] {
+            let mut buf = Vec::new();
+            encode_varint(&mut buf, value);
+            assert_eq!(buf, bytes, "{}", value);
+        }
+        // The encoding guide's first example
+        assert_eq!(
+            encode(1, ProtoType::Int32, false, ColumnData::Integer(150)),
+            [0x08, 0x96, 0x01]
+        );
+        // Negative int32s take ten bytes
+        assert_eq!(
+            encode(1, ProtoType::Int32, false, ColumnData::Integer(-1)),
+            [0x08, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]
+        );
+    }
+
+    #[test]
+    fn zigzag() {
+        for (value, bytes) in [
+            (0, &[0x00][..]),
+            (-1, &[0x01]),
+            (1, &[0x02]),
+            (-2, &[0x03]),
+            (i64::from(i32::MAX), &[0xfe, 0xff, 0xff, 0xff, 0x0f]),
+            (i64::from(i32::MIN), &[0xff, 0xff, 0xff, 0xff, 0x0f]),
+        ] {
+            let encoded = encode(1, ProtoType::Sint32, false, ColumnData::Integer(value));
+            assert_eq!(encoded[0], 0x08);
+            assert_eq!(&encoded[1..], bytes, "{}", value);
+        }
+        assert_eq!(
+            encode(1, ProtoType::Sint64, false, ColumnData::Integer(i64::MIN)),
+            [0x08, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]
+        );
+    }
+
+    #[test]
+    fn fixed_width_values() {
+        assert_eq!(
+            encode(1, ProtoType::Double, false, ColumnData::Float(1.0)),
+            [0x09, 0, 0, 0, 0, 0, 0, 0xf0, 0x3f]
+        );
+        assert_eq!(
+            encode(2, ProtoType::Sfixed32, false, ColumnData::Integer(-2)),
+            [0x15, 0xfe, 0xff, 0xff, 0xff]
+        );
+    }
+
+    #[test]
+    fn length_delimited_values() {
+        assert_eq!(
+            encode(
+                2,
+                ProtoType::String,
+                false,
+                ColumnData::String("testing".to_string())
+            ),
+            b"\x12\x07testing"
+        );
+        // Embedded message, field 3 holding the first example
+        let mut inner = ProtoMessage::new("Inner");
+        inner.add_field(ProtoField::new("a", 1, ProtoType::Int32, false));
+        let mut record = Tuple::new();
+        record.add_field_data("a", ColumnData::Integer(150));
+        assert_eq!(
+            encode(
+                3,
+                ProtoType::Message(inner),
+                false,
+                ColumnData::Record(record)
+            ),
+            [0x1a, 0x03, 0x08, 0x96, 0x01]
+        );
+    }
+
+    #[test]
+    fn repeated_fields() {
+        let list = |values: &[i64]| {
+            ColumnData::List(values.iter().map(|v| ColumnData::Integer(*v)).collect())
+        };
+        // Scalars are packed
+        assert_eq!(
+            encode(4, ProtoType::Int32, true, list(&[3, 270, 86942])),
+            [0x22, 0x06, 0x03, 0x8e, 0x02, 0x9e, 0xa7, 0x05]
+        );
+        // and left out when there's nothing to pack
+        assert!(encode(4, ProtoType::Int32, true, list(&[])).is_empty());
+        // Strings repeat the key, without their NULLs
+        let strings = ColumnData::List(vec![
+            ColumnData::String("a".to_string()),
+            ColumnData::Null,
+            ColumnData::String("bc".to_string()),
+        ]);
+        assert_eq!(
+            encode(1, ProtoType::String, true, strings),
+            b"\x0a\x01a\x0a\x02bc"
+        );
+        assert!(encode(1, ProtoType::Int32, false, ColumnData::Null).is_empty());
+    }
+
+    #[test]
+    fn framing() {
+        let mut message = ProtoMessage::new("Test");
+        message.add_field(ProtoField::new("a", 1, ProtoType::Int32, false));
+        let message = Rc::new(message);
+        let mut tuple = Tuple::new();
+        tuple.add_field_data("a", ColumnData::Integer(150));
+        for (framing, frame) in [
+            (Framing::VarintDelimited, &[0x03][..]),
+            (Framing::LengthDelimited, &[0x00, 0x00, 0x00, 0x03]),
+        ] {
+            let mut out = Vec::new();
+            let mut writer = TupleToProtobufSerializer::new(&mut out, message.clone(), framing);
+            writer.write_tuple(&tuple).unwrap();
+            writer.write_tuple(&tuple).unwrap();
+            let expected: Vec<u8> = [frame, &[0x08, 0x96, 0x01]].concat().repeat(2);
+            assert_eq!(out, expected, "{:?}", framing);
+        }
+    }
+
+    #[test]
+    fn one_branch_of_a_oneof_is_written() {
+        let mut message = ProtoMessage::new("Test");
+        message.add_field(ProtoField::new("id", 1, ProtoType::Int32, false));
+        for (name, number) in [("a", 2), ("b", 3), ("c", 4)] {
+            message
+                .add_field(ProtoField::new(name, number, ProtoType::Int32, false).with_oneof("x"));
+        }
+        let mut tuple = Tuple::new();
+        tuple.add_field_data("id", ColumnData::Integer(1));
+        tuple.add_field_data("a", ColumnData::Integer(2));
+        tuple.add_field_data("b", ColumnData::Integer(3));
+        tuple.add_field_data("c", ColumnData::Null);
+        let mut written = HashSet::new();
+        for _ in 0..100 {
+            let mut buf = Vec::new();
+            encode_message(&mut buf, &message, &tuple).unwrap();
+            assert_eq!(buf.len(), 4);
+            assert_eq!(buf[..2], [0x08, 0x01]);
+            written.insert(buf[2..].to_vec());
+        }
+        // Either branch that is set, never the NULL one
+        let expected: HashSet<Vec<u8>> = [vec![0x10, 0x02], vec![0x18, 0x03]].into();
+        assert_eq!(written, expected);
+    }
+
+    #[test]
+    fn fields_must_be_in_the_message() {
+        let mut buf = Vec::new();
+        let mut tuple = Tuple::new();
+        tuple.add_field_data("b", ColumnData::Integer(1));
+        let err = encode_message(&mut buf, &ProtoMessage::new("Test"), &tuple).unwrap_err();
+        assert_eq!(err.to_string(), "b is not a field of message Test");
+    }
+}
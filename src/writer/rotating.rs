@@ -0,0 +1,300 @@
+use super::counting::ByteCounter;
+use super::*;
+use std::cell::Cell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Opens a TupleWriter for the given path. Bytes written to the file must be
+/// counted on the given ByteCounter.
+pub type TupleWriterFactory =
+    Box<dyn FnMut(&Path, &ByteCounter) -> std::io::Result<Box<dyn TupleWriter>>>;
+
+/// The next part number to use for a file pattern. Shared so that writers
+/// reopening the same pattern carry on numbering where the last one stopped.
+pub type PartCounter = Rc<Cell<u64>>;
+
+/**
+ * FilePattern
+ *
+ * A file name with a `{part}` placeholder, optionally zero padded like `{part:05}`
+ */
+#[derive(Debug, Clone)]
+pub struct FilePattern {
+    prefix: String,
+    width: usize,
+    suffix: String,
+}
+
+impl FilePattern {
+    pub fn new(pattern: &str) -> Result<Self, String> {
+        let start = pattern
+            .find("{part")
+            .ok_or(format!("{} has no {{part}} placeholder", pattern))?;
+        let end = start
+            + pattern[start..]
+                .find('}')
+                .ok_or(format!("Unclosed placeholder in {}", pattern))?;
+        let width = match &pattern[start + "{part".len()..end] {
+            "" => 0,
+            format => format
+                .strip_prefix(":0")
+                .and_then(|w| w.parse::<usize>().ok())
+                .ok_or(format!("Invalid part format in {}", pattern))?,
+        };
+        Ok(FilePattern {
+            prefix: pattern[..start].to_string(),
+            width,
+            suffix: pattern[end + 1..].to_string(),
+        })
+    }
+
+    /// Adds a part number to a plain file name, before its extensions: `out.csv.gz` -> `out-{part:05}.csv.gz`
+    pub fn from_path(path: &str) -> Result<Self, String> {
+        if path.contains("{part") {
+            return FilePattern::new(path);
+        }
+        let name_start = path.rfind('/').map_or(0, |i| i + 1);
+        // A leading dot belongs to the name
+        let extension_start = path
+            .get(name_start + 1..)
+            .and_then(|name| name.find('.'))
+            .map_or(path.len(), |i| name_start + 1 + i);
+        FilePattern::new(&format!(
+            "{}-{{part:05}}{}",
+            &path[..extension_start],
+            &path[extension_start..]
+        ))
+    }
+
+    pub fn path(&self, part: u64) -> PathBuf {
+        PathBuf::from(format!(
+            "{}{:0width$}{}",
+            self.prefix,
+            part,
+            self.suffix,
+            width = self.width
+        ))
+    }
+}
+
+/**
+ * RotatingTupleWriter
+ *
+ * Splits output into numbered files once a file holds enough records or bytes.
+ * Each file is finished before the next is started so it is a complete
+ * document for its format.
+ */
+pub struct RotatingTupleWriter {
+    factory: TupleWriterFactory,
+    pattern: FilePattern,
+    parts: PartCounter,
+    max_records: Option<u64>,
+    max_bytes: Option<u64>,
+    current: Option<(Box<dyn TupleWriter>, ByteCounter)>,
+    records_in_file: u64,
+    supports_list: bool,
+    supports_record: bool,
+}
+
+impl RotatingTupleWriter {
+    pub fn new(
+        mut factory: TupleWriterFactory,
+        pattern: FilePattern,
+        parts: PartCounter,
+        max_records: Option<u64>,
+        max_bytes: Option<u64>,
+    ) -> std::io::Result<Self> {
+        // Open the first file straight away so problems with the path show up early
+        let counter = ByteCounter::new();
+        let first = factory(&pattern.path(parts.get()), &counter)?;
+        parts.set(parts.get() + 1);
+        Ok(RotatingTupleWriter {
+            supports_list: first.supports_list(),
+            supports_record: first.supports_record(),
+            factory,
+            pattern,
+            parts,
+            max_records,
+            max_bytes,
+            current: Some((first, counter)),
+            records_in_file: 0,
+        })
+    }
+
+    fn current_is_full(&self, bytes_in_file: u64) -> bool {
+        self.max_records
+            .is_some_and(|max| self.records_in_file >= max)
+            || self.max_bytes.is_some_and(|max| bytes_in_file >= max)
+    }
+}
+
+impl TupleWriter for RotatingTupleWriter {
+    fn supports_list(&self) -> bool {
+        self.supports_list
+    }
+    fn supports_record(&self) -> bool {
+        self.supports_record
+    }
+
+    fn write_tuple(&mut self, tuple: &Tuple) -> std::io::Result<()> {
+        if self.current.is_none() {
+            let counter = ByteCounter::new();
+            let writer = (self.factory)(&self.pattern.path(self.parts.get()), &counter)?;
+            self.current = Some((writer, counter));
+            self.parts.set(self.parts.get() + 1);
+            self.records_in_file = 0;
+        }
+        let (writer, counter) = self.current.as_mut().unwrap();
+        writer.write_tuple(tuple)?;
+        self.records_in_file += 1;
+
+        // Batches the writer is still collecting count too
+        let bytes_in_file = counter.get() + writer.buffered_bytes();
+        if self.current_is_full(bytes_in_file) {
+            if let Some((mut writer, _)) = self.current.take() {
+                writer.finish()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self.current.as_mut() {
+            Some((writer, _)) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+
+    fn buffered_bytes(&self) -> u64 {
+        self.current
+            .as_ref()
+            .map_or(0, |(writer, _)| writer.buffered_bytes())
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        match self.current.take() {
+            Some((mut writer, _)) => writer.finish(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_repr::ColumnData;
+    use crate::writer::counting::CountingWriter;
+    use crate::writer::json::{JsonStyle, TupleToJsonSerializer};
+    use std::cell::RefCell;
+    use std::io::Write;
+
+    #[test]
+    fn file_patterns() {
+        let pattern = FilePattern::new("out-{part}.csv").unwrap();
+        assert_eq!(pattern.path(7), PathBuf::from("out-7.csv"));
+        let pattern = FilePattern::new("{part:03}/data").unwrap();
+        assert_eq!(pattern.path(7), PathBuf::from("007/data"));
+        assert_eq!(pattern.path(1234), PathBuf::from("1234/data"));
+        assert!(FilePattern::new("out.csv").is_err());
+        assert!(FilePattern::new("out-{part.csv").is_err());
+        assert!(FilePattern::new("out-{part:5}.csv").is_err());
+        assert!(FilePattern::new("out-{part:0x}.csv").is_err());
+    }
+
+    #[test]
+    fn part_numbers_go_before_the_extensions() {
+        let path = |p: &str| FilePattern::from_path(p).unwrap().path(3);
+        assert_eq!(path("out.csv.gz"), PathBuf::from("out-00003.csv.gz"));
+        assert_eq!(
+            path("dir.d/out.json"),
+            PathBuf::from("dir.d/out-00003.json")
+        );
+        assert_eq!(path("out"), PathBuf::from("out-00003"));
+        // A leading dot isn't an extension
+        assert_eq!(path(".hidden.txt"), PathBuf::from(".hidden-00003.txt"));
+        assert_eq!(path("dir/.hidden"), PathBuf::from("dir/.hidden-00003"));
+        assert_eq!(path("out-{part:02}.csv"), PathBuf::from("out-03.csv"));
+    }
+
+    /// Files written in memory, by path
+    type Files = Rc<RefCell<Vec<(PathBuf, Rc<RefCell<Vec<u8>>>)>>>;
+
+    struct MemoryFile(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for MemoryFile {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Writes `rows` tuples as JSON arrays, returning the files
+    fn rotate(rows: i64, max_records: Option<u64>, max_bytes: Option<u64>) -> Vec<String> {
+        let files = Files::default();
+        let opened = files.clone();
+        let factory: TupleWriterFactory = Box::new(move |path, counter| {
+            let data = Rc::new(RefCell::new(Vec::new()));
+            opened.borrow_mut().push((path.to_path_buf(), data.clone()));
+            let output = CountingWriter::new(MemoryFile(data), counter.clone());
+            Ok(Box::new(TupleToJsonSerializer::new(
+                output,
+                JsonStyle::Array,
+            )))
+        });
+        let pattern = FilePattern::new("out-{part}.json").unwrap();
+        let mut writer =
+            RotatingTupleWriter::new(factory, pattern, Default::default(), max_records, max_bytes)
+                .unwrap();
+        for id in 0..rows {
+            let mut tuple = Tuple::new();
+            tuple.add_field_data("id", ColumnData::Integer(id));
+            writer.write_tuple(&tuple).unwrap();
+        }
+        writer.finish().unwrap();
+        drop(writer);
+        let files = files.borrow();
+        for (i, (path, _)) in files.iter().enumerate() {
+            assert_eq!(path, &PathBuf::from(format!("out-{}.json", i)));
+        }
+        files
+            .iter()
+            .map(|(_, data)| String::from_utf8(data.borrow().clone()).unwrap())
+            .collect()
+    }
+
+    fn ids(file: &str) -> Vec<i64> {
+        let rows: Vec<serde_json::Value> = serde_json::from_str(file).unwrap();
+        rows.iter().map(|r| r["id"].as_i64().unwrap()).collect()
+    }
+
+    #[test]
+    fn splits_by_records() {
+        let files = rotate(5, Some(2), None);
+        let ids: Vec<_> = files.iter().map(|f| ids(f)).collect();
+        assert_eq!(ids, [vec![0, 1], vec![2, 3], vec![4]]);
+        // No empty file is left when the rows divide evenly
+        assert_eq!(rotate(4, Some(2), None).len(), 2);
+    }
+
+    #[test]
+    fn splits_by_bytes() {
+        let files = rotate(100, None, Some(50));
+        assert!(files.len() > 2);
+        let mut all = Vec::new();
+        for file in &files {
+            // Every file is a complete array
+            let file_ids = ids(file);
+            assert!(!file_ids.is_empty());
+            all.extend(file_ids);
+        }
+        assert_eq!(all, (0..100).collect::<Vec<_>>());
+        // Only the last file stops short of the limit
+        for file in &files[..files.len() - 1] {
+            assert!(file.len() >= 50, "{}", file);
+        }
+    }
+}
@@ -0,0 +1,276 @@
+/*!
+ * Serde views of tuples, so serde based formats can write them directly
+ * without building an intermediate document first.
+ */
+use super::BinaryEncoding;
+use crate::data_repr::*;
+use chrono::{DateTime, Utc};
+use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
+
+/**
+ * ValueEncoding
+ *
+ * How values without a serde type of their own are written. Text formats get
+ * strings, binary formats get their native representation.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ValueEncoding {
+    /// Bytes encoded as strings, timestamps as RFC 3339 and UUIDs hyphenated
+    Text(BinaryEncoding),
+    /// Native bytes, the timestamp extension type and UUIDs as 16 bytes
+    MessagePack,
+    /// Native bytes, epoch timestamps (tag 1, or tag 0 strings when they have
+    /// fractional seconds) and binary UUIDs (tag 37)
+    Cbor,
+}
+
+/// Serializes as a byte string rather than a sequence of integers
+struct RawBytes<'a>(&'a [u8]);
+
+impl Serialize for RawBytes<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+/// Payload of the MessagePack timestamp extension, in its smallest form
+fn msgpack_timestamp(ts: &DateTime<Utc>) -> Vec<u8> {
+    let seconds = ts.timestamp();
+    let nanos = ts.timestamp_subsec_nanos();
+    if seconds >> 34 == 0 {
+        let packed = (u64::from(nanos) << 34) | seconds as u64;
+        if packed >> 32 == 0 {
+            (packed as u32).to_be_bytes().to_vec()
+        } else {
+            packed.to_be_bytes().to_vec()
+        }
+    } else {
+        let mut payload = nanos.to_be_bytes().to_vec();
+        payload.extend_from_slice(&seconds.to_be_bytes());
+        payload
+    }
+}
+
+/**
+ * SerializableTuple
+ *
+ * Serializes a tuple as a map, keeping the fields in schema order
+ */
+pub struct SerializableTuple<'a> {
+    tuple: &'a Tuple,
+    encoding: ValueEncoding,
+}
+
+impl<'a> SerializableTuple<'a> {
+    pub fn new(tuple: &'a Tuple, encoding: ValueEncoding) -> Self {
+        SerializableTuple { tuple, encoding }
+    }
+}
+
+impl Serialize for SerializableTuple<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // Binary formats write a shorter header when the length is known
+        let mut map = serializer.serialize_map(Some(self.tuple.into_iter().count()))?;
+        for (name, data) in self.tuple {
+            map.serialize_entry(name, &SerializableColumn::new(data, self.encoding))?;
+        }
+        map.end()
+    }
+}
+
+/**
+ * SerializableColumn
+ */
+pub struct SerializableColumn<'a> {
+    data: &'a ColumnData,
+    encoding: ValueEncoding,
+}
+
+impl<'a> SerializableColumn<'a> {
+    pub fn new(data: &'a ColumnData, encoding: ValueEncoding) -> Self {
+        SerializableColumn { data, encoding }
+    }
+}
+
+impl Serialize for SerializableColumn<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match (self.data, self.encoding) {
+            (ColumnData::Integer(v), _) => serializer.serialize_i64(*v),
+            (ColumnData::Float(v), _) => serializer.serialize_f64(*v),
+            (ColumnData::Boolean(v), _) => serializer.serialize_bool(*v),
+            (ColumnData::String(v), _) => serializer.serialize_str(v),
+            (ColumnData::Uuid(v), ValueEncoding::Text(_)) => serializer.serialize_str(
+                v.hyphenated()
+                    .encode_lower(&mut uuid::Uuid::encode_buffer()),
+            ),
+            (ColumnData::Uuid(v), ValueEncoding::MessagePack) => {
+                serializer.serialize_bytes(v.as_bytes())
+            }
+            (ColumnData::Uuid(v), ValueEncoding::Cbor) => {
+                ciborium::tag::Required::<_, 37>(RawBytes(v.as_bytes())).serialize(serializer)
+            }
+            (ColumnData::Bytes(v), ValueEncoding::Text(encoding)) => {
+                serializer.serialize_str(&encoding.encode(v))
+            }
+            (ColumnData::Bytes(v), _) => serializer.serialize_bytes(v),
+            (ColumnData::Timestamp(v), ValueEncoding::Text(_)) => {
+                serializer.serialize_str(&format_timestamp(v))
+            }
+            (ColumnData::Timestamp(v), ValueEncoding::MessagePack) => serializer
+                .serialize_newtype_struct(
+                    rmp_serde::MSGPACK_EXT_STRUCT_NAME,
+                    &(-1i8, RawBytes(&msgpack_timestamp(v))),
+                ),
+            (ColumnData::Timestamp(v), ValueEncoding::Cbor) => {
+                if v.timestamp_subsec_nanos() == 0 {
+                    ciborium::tag::Required::<_, 1>(v.timestamp()).serialize(serializer)
+                } else {
+                    // An f64 epoch can't hold nanoseconds, so use an RFC 3339 string
+                    ciborium::tag::Required::<_, 0>(format_timestamp(v)).serialize(serializer)
+                }
+            }
+            (ColumnData::Null, _) => serializer.serialize_none(),
+            (ColumnData::Record(t), _) => {
+                SerializableTuple::new(t, self.encoding).serialize(serializer)
+            }
+            (ColumnData::List(v), _) => {
+                let mut seq = serializer.serialize_seq(Some(v.len()))?;
+                for data in v {
+                    seq.serialize_element(&SerializableColumn::new(data, self.encoding))?;
+                }
+                seq.end()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn json(data: ColumnData, encoding: BinaryEncoding) -> String {
+        serde_json::to_string(&SerializableColumn::new(
+            &data,
+            ValueEncoding::Text(encoding),
+        ))
+        .unwrap()
+    }
+
+    fn msgpack(data: ColumnData) -> Vec<u8> {
+        let column = SerializableColumn::new(&data, ValueEncoding::MessagePack);
+        rmp_serde::to_vec(&column).unwrap()
+    }
+
+    fn cbor(data: ColumnData) -> Vec<u8> {
+        let mut output = Vec::new();
+        let column = SerializableColumn::new(&data, ValueEncoding::Cbor);
+        ciborium::ser::into_writer(&column, &mut output).unwrap();
+        output
+    }
+
+    fn uuid() -> uuid::Uuid {
+        uuid::Uuid::from_bytes([0xab; 16])
+    }
+
+    #[test]
+    fn text_values() {
+        let hex = BinaryEncoding::Hex;
+        assert_eq!(json(ColumnData::Integer(-3), hex), "-3");
+        assert_eq!(json(ColumnData::Float(1.5), hex), "1.5");
+        assert_eq!(json(ColumnData::Boolean(true), hex), "true");
+        assert_eq!(json(ColumnData::String("\"".to_string()), hex), r#""\"""#);
+        assert_eq!(json(ColumnData::Null, hex), "null");
+        assert_eq!(
+            json(ColumnData::Uuid(uuid()), hex),
+            r#""abababab-abab-abab-abab-abababababab""#
+        );
+        assert_eq!(json(ColumnData::Bytes(vec![0xfb, 0xff]), hex), r#""fbff""#);
+        assert_eq!(
+            json(ColumnData::Bytes(vec![0xfb, 0xff]), BinaryEncoding::Base64),
+            r#""+/8=""#
+        );
+        let ts = Utc.timestamp_opt(1_700_000_000, 5_000_000).unwrap();
+        assert_eq!(
+            json(ColumnData::Timestamp(ts), hex),
+            r#""2023-11-14T22:13:20.005Z""#
+        );
+    }
+
+    #[test]
+    fn tuples_keep_schema_order() {
+        let mut inner = Tuple::new();
+        inner.add_field_data("b", ColumnData::Integer(2));
+        inner.add_field_data("a", ColumnData::Integer(1));
+        let mut tuple = Tuple::new();
+        tuple.add_field_data("z", ColumnData::Record(inner));
+        tuple.add_field_data(
+            "y",
+            ColumnData::List(vec![ColumnData::Integer(1), ColumnData::Null]),
+        );
+        tuple.add_field_data("x", ColumnData::List(vec![]));
+        let record = SerializableTuple::new(&tuple, ValueEncoding::Text(BinaryEncoding::Hex));
+        assert_eq!(
+            serde_json::to_string(&record).unwrap(),
+            r#"{"z":{"b":2,"a":1},"y":[1,null],"x":[]}"#
+        );
+    }
+
+    #[test]
+    fn msgpack_timestamps_use_the_smallest_form() {
+        let ts = |seconds, nanos| msgpack_timestamp(&Utc.timestamp_opt(seconds, nanos).unwrap());
+        // 32 bit seconds
+        assert_eq!(ts(1, 0), [0, 0, 0, 1]);
+        // 30 bit nanoseconds and 34 bit seconds
+        assert_eq!(ts(1, 1), [0, 0, 0, 0b100, 0, 0, 0, 1]);
+        assert_eq!(ts(1 << 32, 0), [0, 0, 0, 1, 0, 0, 0, 0]);
+        // 32 bit nanoseconds and 64 bit seconds, for before 1970 or after 2514
+        assert_eq!(
+            ts(-1, 0),
+            [0, 0, 0, 0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]
+        );
+        assert_eq!(ts(1 << 34, 2), [0, 0, 0, 2, 0, 0, 0, 4, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn msgpack_values() {
+        let ts = Utc.timestamp_opt(1, 0).unwrap();
+        // fixext 4 with type -1
+        assert_eq!(msgpack(ColumnData::Timestamp(ts)), [0xd6, 0xff, 0, 0, 0, 1]);
+        // bin 8
+        let mut uuid_bytes = vec![0xc4, 16];
+        uuid_bytes.extend_from_slice(uuid().as_bytes());
+        assert_eq!(msgpack(ColumnData::Uuid(uuid())), uuid_bytes);
+        assert_eq!(msgpack(ColumnData::Bytes(vec![7])), [0xc4, 1, 7]);
+        assert_eq!(msgpack(ColumnData::Null), [0xc0]);
+    }
+
+    #[test]
+    fn cbor_values() {
+        // Tag 1 with an integer
+        let ts = Utc.timestamp_opt(1, 0).unwrap();
+        assert_eq!(cbor(ColumnData::Timestamp(ts)), [0xc1, 0x01]);
+        // Tag 0 with a text string of 30 bytes
+        let ts = Utc.timestamp_opt(0, 1).unwrap();
+        let mut text = vec![0xc0, 0x78, 30];
+        text.extend_from_slice(b"1970-01-01T00:00:00.000000001Z");
+        assert_eq!(cbor(ColumnData::Timestamp(ts)), text);
+        // Tag 37 with a byte string
+        let mut uuid_bytes = vec![0xd8, 37, 0x50];
+        uuid_bytes.extend_from_slice(uuid().as_bytes());
+        assert_eq!(cbor(ColumnData::Uuid(uuid())), uuid_bytes);
+        assert_eq!(cbor(ColumnData::Bytes(vec![7])), [0x41, 7]);
+        assert_eq!(cbor(ColumnData::Null), [0xf6]);
+
+        // Maps and arrays have definite lengths
+        let mut tuple = Tuple::new();
+        tuple.add_field_data("a", ColumnData::List(vec![ColumnData::Integer(1)]));
+        let mut output = Vec::new();
+        ciborium::ser::into_writer(
+            &SerializableTuple::new(&tuple, ValueEncoding::Cbor),
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(output, [0xa1, 0x61, b'a', 0x81, 0x01]);
+    }
+}
@@ -0,0 +1,392 @@
+use super::json::json_text;
+use super::*;
+use crate::data_repr::ColumnData;
+use crate::data_repr::*;
+use crate::definition::schema::{FieldType, RecordSchema};
+use std::io::Write;
+use std::rc::Rc;
+
+/**
+ * SqlDialect
+ *
+ * Controls identifier quoting, literals and column types of SQL output
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SqlDialect {
+    #[default]
+    Postgres,
+    MySql,
+    Sqlite,
+}
+
+impl SqlDialect {
+    pub fn quote_identifier(&self, name: &str) -> String {
+        match self {
+            SqlDialect::MySql => format!("`{}`", name.replace('`', "``")),
+            _ => format!("\"{}\"", name.replace('"', "\"\"")),
+        }
+    }
+
+    pub fn quote_string(&self, s: &str) -> String {
+        match self {
+            // MySQL treats backslashes in string literals as escapes by default
+            SqlDialect::MySql => format!(
+                "'{}'",
+                s.replace('\\', "\\\\")
+                    .replace('\'', "''")
+                    .replace('\0', "\\0")
+            ),
+            _ => format!("'{}'", s.replace('\'', "''")),
+        }
+    }
+
+    pub fn column_type(&self, field_type: &FieldType) -> &'static str {
+        match (self, field_type) {
+            (SqlDialect::Sqlite, FieldType::Integer(_)) => "INTEGER",
+            (_, FieldType::Integer(_)) => "BIGINT",
+            (SqlDialect::Postgres, FieldType::Float(_)) => "DOUBLE PRECISION",
+            (SqlDialect::MySql, FieldType::Float(_)) => "DOUBLE",
+            (SqlDialect::Sqlite, FieldType::Float(_)) => "REAL",
+            (SqlDialect::Sqlite, FieldType::Boolean(_)) => "INTEGER",
+            (_, FieldType::Boolean(_)) => "BOOLEAN",
+            (_, FieldType::String(_)) => "TEXT",
+            (SqlDialect::Postgres, FieldType::Uuid(_)) => "UUID",
+            (SqlDialect::MySql, FieldType::Uuid(_)) => "CHAR(36)",
+            (SqlDialect::Sqlite, FieldType::Uuid(_)) => "TEXT",
+            (SqlDialect::Postgres, FieldType::Bytes(_)) => "BYTEA",
+            (_, FieldType::Bytes(_)) => "BLOB",
+            (SqlDialect::Postgres, FieldType::Timestamp(_)) => "TIMESTAMPTZ",
+            (SqlDialect::MySql, FieldType::Timestamp(_)) => "DATETIME(6)",
+            (SqlDialect::Sqlite, FieldType::Timestamp(_)) => "TEXT",
+            // Nested values are stored as JSON documents
+            (SqlDialect::Postgres, FieldType::List(_) | FieldType::Record(_)) => "JSONB",
+            (SqlDialect::MySql, FieldType::List(_) | FieldType::Record(_)) => "JSON",
+            (SqlDialect::Sqlite, FieldType::List(_) | FieldType::Record(_)) => "TEXT",
+        }
+    }
+
+    pub fn literal(&self, data: &ColumnData, binary_encoding: BinaryEncoding) -> String {
+        match data {
+            ColumnData::Integer(v) => v.to_string(),
+            ColumnData::Float(v) if v.is_finite() => v.to_string(),
+            ColumnData::Float(_) => "NULL".to_string(),
+            ColumnData::Boolean(v) => match self {
+                SqlDialect::Sqlite => (*v as u8).to_string(),
+                _ => v.to_string().to_uppercase(),
+            },
+            ColumnData::String(v) => self.quote_string(v),
+            ColumnData::Uuid(v) => self.quote_string(&v.to_string()),
+            ColumnData::Bytes(v) => match self {
+                SqlDialect::Postgres => format!("'\\x{}'", hex::encode(v)),
+                _ => format!("X'{}'", hex::encode(v)),
+            },
+            ColumnData::Timestamp(v) => match self {
+                // DATETIME doesn't take a time zone, the value is in UTC
+                SqlDialect::MySql => format!("'{}'", v.format("%Y-%m-%d %H:%M:%S%.6f")),
+                _ => format!("'{}'", format_timestamp(v)),
+            },
+            ColumnData::Null => "NULL".to_string(),
+            ColumnData::Record(_) | ColumnData::List(_) => {
+                self.quote_string(&json_text(data, binary_encoding))
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for SqlDialect {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "postgres" => Ok(SqlDialect::Postgres),
+            "mysql" => Ok(SqlDialect::MySql),
+            "sqlite" => Ok(SqlDialect::Sqlite),
+            _ => Err(format!("Unknown SQL dialect: {}", s)),
+        }
+    }
+}
+
+/**
+ * TupleToSqlSerializer
+ *
+ * Writes INSERT statements of up to `batch_size` rows each
+ */
+pub struct TupleToSqlSerializer<T: Write> {
+    wrt: T,
+    table: String,
+    dialect: SqlDialect,
+    binary_encoding: BinaryEncoding,
+    batch_size: usize,
+    // Written ahead of the first row, then cleared
+    create_table: Option<Rc<RecordSchema>>,
+    rows_in_statement: usize,
+}
+
+impl<T: Write> TupleToSqlSerializer<T> {
+    pub fn new<S: Into<String>>(wrt: T, table: S, dialect: SqlDialect) -> Self {
+        TupleToSqlSerializer {
+            wrt,
+            table: table.into(),
+            dialect,
+            binary_encoding: Default::default(),
+            batch_size: 1,
+            create_table: None,
+            rows_in_statement: 0,
+        }
+    }
+
+    pub fn with_binary_encoding(mut self, binary_encoding: BinaryEncoding) -> Self {
+        self.binary_encoding = binary_encoding;
+        self
+    }
+
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Starts the output with a CREATE TABLE statement for `schema`
+    pub fn with_create_table(mut self, schema: Rc<RecordSchema>) -> Self {
+        self.create_table = Some(schema);
+        self
+    }
+
+    /// Only the columns in `columns` are created, as partitioned output leaves some out
+    fn write_create_table(&mut self, columns: Option<&Tuple>) -> std::io::Result<()> {
+        let schema = match self.create_table.take() {
+            Some(schema) => schema,
+            None => return Ok(()),
+        };
+        let definitions: Vec<String> = schema
+            .iter()
+            .filter(|f| columns.is_none_or(|t| t.get(f.get_name()).is_some()))
+            .map(|f| {
+                format!(
+                    "  {} {}",
+                    self.dialect.quote_identifier(f.get_name()),
+                    self.dialect.column_type(f.get_type())
+                )
+            })
+            .collect();
+        writeln!(
+            self.wrt,
+            "CREATE TABLE IF NOT EXISTS {} (\n{}\n);",
+            self.dialect.quote_identifier(&self.table),
+            definitions.join(",\n")
+        )
+    }
+}
+
+impl<T: Write> TupleWriter for TupleToSqlSerializer<T> {
+    fn supports_list(&self) -> bool {
+        true
+    }
+    fn supports_record(&self) -> bool {
+        true
+    }
+
+    fn write_tuple(&mut self, tuple: &Tuple) -> std::io::Result<()> {
+        self.write_create_table(Some(tuple))?;
+        if self.rows_in_statement == 0 {
+            let columns: Vec<String> = tuple
+                .into_iter()
+                .map(|(name, _)| self.dialect.quote_identifier(name))
+                .collect();
+            write!(
+                self.wrt,
+                "INSERT INTO {} ({}) VALUES\n(",
+                self.dialect.quote_identifier(&self.table),
+                columns.join(", ")
+            )?;
+        } else {
+            write!(self.wrt, ",\n(")?;
+        }
+        for (i, (_, data)) in tuple.into_iter().enumerate() {
+            if i > 0 {
+                write!(self.wrt, ", ")?;
+            }
+            write!(
+                self.wrt,
+                "{}",
+                self.dialect.literal(data, self.binary_encoding)
+            )?;
+        }
+        write!(self.wrt, ")")?;
+        self.rows_in_statement += 1;
+        if self.rows_in_statement == self.batch_size {
+            writeln!(self.wrt, ";")?;
+            self.rows_in_statement = 0;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.wrt.flush()
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        self.write_create_table(None)?;
+        if self.rows_in_statement > 0 {
+            writeln!(self.wrt, ";")?;
+            self.rows_in_statement = 0;
+        }
+        self.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::definition::schema::FieldSchema;
+    use chrono::{TimeZone, Utc};
+
+    /// Literals every dialect writes the same way
+    fn common_literals() -> Vec<(ColumnData, &'static str)> {
+        vec![
+            (ColumnData::Integer(-5), "-5"),
+            (ColumnData::Float(1.5), "1.5"),
+            (ColumnData::Float(f64::NAN), "NULL"),
+            (ColumnData::Float(f64::INFINITY), "NULL"),
+            (ColumnData::Float(f64::NEG_INFINITY), "NULL"),
+            (ColumnData::Null, "NULL"),
+            (
+                ColumnData::Uuid(uuid::Uuid::nil()),
+                "'00000000-0000-0000-0000-000000000000'",
+            ),
+            (
+                ColumnData::List(vec![ColumnData::Integer(1), ColumnData::Null]),
+                "'[1,null]'",
+            ),
+        ]
+    }
+
+    fn timestamp() -> ColumnData {
+        ColumnData::Timestamp(
+            Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap()
+                + chrono::Duration::nanoseconds(123_456_789),
+        )
+    }
+
+    fn string(s: &str) -> ColumnData {
+        ColumnData::String(s.to_string())
+    }
+
+    /// Checks literals and identifiers, then the statements for three rows in batches of two
+    fn check(
+        dialect: SqlDialect,
+        literals: &[(ColumnData, &str)],
+        identifiers: &[(&str, &str)],
+        statements: &str,
+    ) {
+        for (data, literal) in common_literals().iter().chain(literals) {
+            assert_eq!(
+                dialect.literal(data, BinaryEncoding::Base64),
+                *literal,
+                "{:?} {:?}",
+                dialect,
+                data
+            );
+        }
+        for (name, quoted) in identifiers {
+            assert_eq!(dialect.quote_identifier(name), *quoted);
+        }
+
+        let schema = RecordSchema::new()
+            .with_field(FieldSchema::new(
+                "id",
+                FieldType::Integer(Default::default()),
+            ))
+            .with_field(FieldSchema::new(
+                "at",
+                FieldType::Timestamp(Default::default()),
+            ))
+            .with_field(FieldSchema::new(
+                "raw",
+                FieldType::Bytes(Default::default()),
+            ));
+        let mut out = Vec::new();
+        let mut writer = TupleToSqlSerializer::new(&mut out, "t", dialect)
+            .with_batch_size(2)
+            .with_create_table(Rc::new(schema));
+        for id in 1..=3 {
+            let mut tuple = Tuple::new();
+            tuple.add_field_data("id", ColumnData::Integer(id));
+            tuple.add_field_data("at", ColumnData::Null);
+            tuple.add_field_data("raw", ColumnData::Bytes(vec![id as u8]));
+            writer.write_tuple(&tuple).unwrap();
+        }
+        writer.finish().unwrap();
+        drop(writer);
+        assert_eq!(String::from_utf8(out).unwrap(), statements);
+    }
+
+    #[test]
+    fn postgres() {
+        check(
+            SqlDialect::Postgres,
+            &[
+                (ColumnData::Boolean(true), "TRUE"),
+                (ColumnData::Boolean(false), "FALSE"),
+                (string("it's C:\\temp"), "'it''s C:\\temp'"),
+                (ColumnData::Bytes(vec![0xde, 0xad]), "'\\xdead'"),
+                (timestamp(), "'2024-01-02T03:04:05.123456789Z'"),
+            ],
+            &[("id", "\"id\""), ("we\"ird", "\"we\"\"ird\""), ("a`b", "\"a`b\"")],
+            "CREATE TABLE IF NOT EXISTS \"t\" (\n  \"id\" BIGINT,\n  \"at\" TIMESTAMPTZ,\n  \"raw\" BYTEA\n);\n\
+             INSERT INTO \"t\" (\"id\", \"at\", \"raw\") VALUES\n(1, NULL, '\\x01'),\n(2, NULL, '\\x02');\n\
+             INSERT INTO \"t\" (\"id\", \"at\", \"raw\") VALUES\n(3, NULL, '\\x03');\n",
+        );
+    }
+
+    #[test]
+    fn mysql() {
+        check(
+            SqlDialect::MySql,
+            &[
+                (ColumnData::Boolean(true), "TRUE"),
+                (string("it's C:\\temp"), "'it''s C:\\\\temp'"),
+                (string("nul\0"), "'nul\\0'"),
+                (ColumnData::Bytes(vec![0xde, 0xad]), "X'dead'"),
+                (timestamp(), "'2024-01-02 03:04:05.123456'"),
+            ],
+            &[("id", "`id`"), ("a`b", "`a``b`"), ("we\"ird", "`we\"ird`")],
+            "CREATE TABLE IF NOT EXISTS `t` (\n  `id` BIGINT,\n  `at` DATETIME(6),\n  `raw` BLOB\n);\n\
+             INSERT INTO `t` (`id`, `at`, `raw`) VALUES\n(1, NULL, X'01'),\n(2, NULL, X'02');\n\
+             INSERT INTO `t` (`id`, `at`, `raw`) VALUES\n(3, NULL, X'03');\n",
+        );
+    }
+
+    #[test]
+    fn sqlite() {
+        check(
+            SqlDialect::Sqlite,
+            &[
+                (ColumnData::Boolean(true), "1"),
+                (ColumnData::Boolean(false), "0"),
+                (string("it's C:\\temp"), "'it''s C:\\temp'"),
+                (ColumnData::Bytes(vec![0xde, 0xad]), "X'dead'"),
+                (timestamp(), "'2024-01-02T03:04:05.123456789Z'"),
+            ],
+            &[("id", "\"id\""), ("we\"ird", "\"we\"\"ird\"")],
+            "CREATE TABLE IF NOT EXISTS \"t\" (\n  \"id\" INTEGER,\n  \"at\" TEXT,\n  \"raw\" BLOB\n);\n\
+             INSERT INTO \"t\" (\"id\", \"at\", \"raw\") VALUES\n(1, NULL, X'01'),\n(2, NULL, X'02');\n\
+             INSERT INTO \"t\" (\"id\", \"at\", \"raw\") VALUES\n(3, NULL, X'03');\n",
+        );
+    }
+
+    #[test]
+    fn tables_are_created_without_rows() {
+        let schema = RecordSchema::new().with_field(FieldSchema::new(
+            "id",
+            FieldType::Integer(Default::default()),
+        ));
+        let mut out = Vec::new();
+        let mut writer = TupleToSqlSerializer::new(&mut out, "t", SqlDialect::Postgres)
+            .with_create_table(Rc::new(schema));
+        writer.finish().unwrap();
+        drop(writer);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "CREATE TABLE IF NOT EXISTS \"t\" (\n  \"id\" BIGINT\n);\n"
+        );
+    }
+}
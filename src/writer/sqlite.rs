@@ -0,0 +1,314 @@
+use super::json::json_text;
+use super::sql::SqlDialect;
+use super::*;
+use crate::data_repr::ColumnData;
+use crate::data_repr::*;
+use crate::definition::schema::RecordSchema;
+use crate::writer::counting::ByteCounter;
+use rusqlite::types::Value;
+use rusqlite::Connection;
+use std::io::Error;
+use std::path::Path;
+use std::rc::Rc;
+
+/**
+ * TupleToSqliteWriter
+ *
+ * Inserts tuples into a table of a SQLite database, creating the database and
+ * the table if they don't exist yet. Rows are committed `batch_size` at a time.
+ */
+pub struct TupleToSqliteWriter {
+    conn: Connection,
+    table: String,
+    schema: Rc<RecordSchema>,
+    binary_encoding: BinaryEncoding,
+    batch_size: usize,
+    // Set up from the first tuple
+    insert: Option<String>,
+    rows_in_transaction: usize,
+    counters: Vec<ByteCounter>,
+    database_size: u64,
+}
+
+fn sqlite_error(e: rusqlite::Error) -> Error {
+    Error::other(e)
+}
+
+impl TupleToSqliteWriter {
+    pub fn open<S: Into<String>>(
+        path: &Path,
+        table: S,
+        schema: Rc<RecordSchema>,
+    ) -> std::io::Result<Self> {
+        let conn = Connection::open(path)
+            .map_err(|e| Error::other(format!("{} - {}", path.display(), e)))?;
+        let mut writer = TupleToSqliteWriter {
+            conn,
+            table: table.into(),
+            schema,
+            binary_encoding: Default::default(),
+            batch_size: 1,
+            insert: None,
+            rows_in_transaction: 0,
+            counters: Vec::new(),
+            database_size: 0,
+        };
+        writer.database_size = writer.database_size()?;
+        Ok(writer)
+    }
+
+    pub fn with_binary_encoding(mut self, binary_encoding: BinaryEncoding) -> Self {
+        self.binary_encoding = binary_encoding;
+        self
+    }
+
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Counts the bytes the database grows by on each of `counters`
+    pub fn with_counters(mut self, counters: &[&ByteCounter]) -> Self {
+        self.counters = counters.iter().map(|c| (*c).clone()).collect();
+        self
+    }
+
+    fn database_size(&self) -> std::io::Result<u64> {
+        let pages: u64 = self
+            .conn
+            .query_row("PRAGMA page_count", [], |r| r.get(0))
+            .map_err(sqlite_error)?;
+        let page_size: u64 = self
+            .conn
+            .query_row("PRAGMA page_size", [], |r| r.get(0))
+            .map_err(sqlite_error)?;
+        Ok(pages * page_size)
+    }
+
+    /// Creates the table for the fields in `tuple`, as partitioned output leaves some out,
+    /// or for every field of the schema when nothing has been written
+    fn create_table(&mut self, tuple: Option<&Tuple>) -> std::io::Result<()> {
+        let dialect = SqlDialect::Sqlite;
+        let definitions: Vec<String> = self
+            .schema
+            .iter()
+            .filter(|f| tuple.is_none_or(|t| t.get(f.get_name()).is_some()))
+            .map(|f| {
+                format!(
+                    "{} {}",
+                    dialect.quote_identifier(f.get_name()),
+                    dialect.column_type(f.get_type())
+                )
+            })
+            .collect();
+        self.conn
+            .execute_batch(&format!(
+                "CREATE TABLE IF NOT EXISTS {} ({})",
+                dialect.quote_identifier(&self.table),
+                definitions.join(", ")
+            ))
+            .map_err(sqlite_error)
+    }
+
+    fn insert_statement(&self, tuple: &Tuple) -> String {
+        let dialect = SqlDialect::Sqlite;
+        let columns: Vec<String> = tuple
+            .into_iter()
+            .map(|(name, _)| dialect.quote_identifier(name))
+            .collect();
+        let placeholders = vec!["?"; columns.len()];
+        format!(
+            "INSERT INTO {} ({}) VALUES ({})",
+            dialect.quote_identifier(&self.table),
+            columns.join(", "),
+            placeholders.join(", ")
+        )
+    }
+
+    fn value(&self, data: &ColumnData) -> Value {
+        match data {
+            ColumnData::Integer(v) => Value::Integer(*v),
+            ColumnData::Float(v) => Value::Real(*v),
+            ColumnData::Boolean(v) => Value::Integer(*v as i64),
+            ColumnData::String(v) => Value::Text(v.to_string()),
+            ColumnData::Uuid(v) => Value::Text(v.to_string()),
+            ColumnData::Bytes(v) => Value::Blob(v.clone()),
+            ColumnData::Timestamp(v) => Value::Text(format_timestamp(v)),
+            ColumnData::Null => Value::Null,
+            // Nested values are stored as JSON text
+            ColumnData::Record(_) | ColumnData::List(_) => {
+                Value::Text(json_text(data, self.binary_encoding))
+            }
+        }
+    }
+
+    fn commit(&mut self) -> std::io::Result<()> {
+        if self.rows_in_transaction > 0 {
+            self.conn.execute_batch("COMMIT").map_err(sqlite_error)?;
+            self.rows_in_transaction = 0;
+            let size = self.database_size()?;
+            for counter in &self.counters {
+                counter.add(size.saturating_sub(self.database_size));
+            }
+            self.database_size = size;
+        }
+        Ok(())
+    }
+}
+
+impl TupleWriter for TupleToSqliteWriter {
+    fn supports_list(&self) -> bool {
+        true
+    }
+    fn supports_record(&self) -> bool {
+        true
+    }
+
+    fn write_tuple(&mut self, tuple: &Tuple) -> std::io::Result<()> {
+        let insert = match self.insert.take() {
+            Some(insert) => insert,
+            None => {
+                self.create_table(Some(tuple))?;
+                self.insert_statement(tuple)
+            }
+        };
+        if self.rows_in_transaction == 0 {
+            self.conn.execute_batch("BEGIN").map_err(sqlite_error)?;
+        }
+        let values: Vec<Value> = tuple.into_iter().map(|(_, d)| self.value(d)).collect();
+        self.conn
+            .prepare_cached(&insert)
+            .and_then(|mut stmt| stmt.execute(rusqlite::params_from_iter(values)))
+            .map_err(sqlite_error)?;
+        self.insert = Some(insert);
+        self.rows_in_transaction += 1;
+        if self.rows_in_transaction == self.batch_size {
+            self.commit()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.commit()
+    }
+
+    /// The table is there even when no rows were written
+    fn finish(&mut self) -> std::io::Result<()> {
+        if self.insert.is_none() {
+            self.create_table(None)?;
+        }
+        self.commit()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::definition::schema::{FieldSchema, FieldType};
+
+    fn schema() -> Rc<RecordSchema> {
+        Rc::new(
+            RecordSchema::new()
+                .with_field(FieldSchema::new(
+                    "id",
+                    FieldType::Integer(Default::default()),
+                ))
+                .with_field(FieldSchema::new(
+                    "score",
+                    FieldType::Float(Default::default()),
+                ))
+                .with_field(FieldSchema::new(
+                    "ok",
+                    FieldType::Boolean(Default::default()),
+                ))
+                .with_field(FieldSchema::new(
+                    "data",
+                    FieldType::Bytes(Default::default()),
+                ))
+                .with_field(FieldSchema::new(
+                    "tags",
+                    FieldType::List(Box::new(FieldType::String(Default::default()))),
+                )),
+        )
+    }
+
+    fn database(name: &str) -> std::path::PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("datablaster-{}-{}.db", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn columns(conn: &Connection) -> Vec<(String, String)> {
+        let mut stmt = conn.prepare("PRAGMA table_info(\"t\")").unwrap();
+        let rows = stmt.query_map([], |r| Ok((r.get(1)?, r.get(2)?))).unwrap();
+        rows.map(|r| r.unwrap()).collect()
+    }
+
+    #[test]
+    fn rows_are_committed_in_batches() {
+        let path = database("batches");
+        let mut writer = TupleToSqliteWriter::open(&path, "t", schema())
+            .unwrap()
+            .with_batch_size(2);
+        // Another connection only sees committed rows
+        let reader = Connection::open(&path).unwrap();
+        let committed = || -> i64 {
+            reader
+                .query_row("SELECT count(*) FROM t", [], |r| r.get(0))
+                .unwrap_or(-1)
+        };
+        for id in 0..5 {
+            let mut tuple = Tuple::new();
+            tuple.add_field_data("id", ColumnData::Integer(id));
+            tuple.add_field_data("score", ColumnData::Float(0.5));
+            tuple.add_field_data("ok", ColumnData::Boolean(id % 2 == 0));
+            tuple.add_field_data("data", ColumnData::Bytes(vec![0, 255]));
+            tuple.add_field_data(
+                "tags",
+                ColumnData::List(vec![ColumnData::String("a".to_string())]),
+            );
+            writer.write_tuple(&tuple).unwrap();
+            assert_eq!(committed(), [0, 2, 2, 4, 4][id as usize], "after {}", id);
+        }
+        writer.finish().unwrap();
+        assert_eq!(committed(), 5);
+
+        let expected = [
+            ("id", "INTEGER"),
+            ("score", "REAL"),
+            ("ok", "INTEGER"),
+            ("data", "BLOB"),
+            ("tags", "TEXT"),
+        ];
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|(n, t)| (n.to_string(), t.to_string()))
+            .collect();
+        assert_eq!(columns(&reader), expected);
+        let row: (i64, f64, i64, Vec<u8>, String) = reader
+            .query_row("SELECT * FROM t WHERE id = 4", [], |r| {
+                Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?))
+            })
+            .unwrap();
+        assert_eq!(row, (4, 0.5, 1, vec![0, 255], "[\"a\"]".to_string()));
+        drop(reader);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn tables_are_created_without_rows() {
+        let path = database("empty");
+        let mut writer = TupleToSqliteWriter::open(&path, "t", schema()).unwrap();
+        writer.finish().unwrap();
+        drop(writer);
+        let conn = Connection::open(&path).unwrap();
+        assert_eq!(columns(&conn).len(), 5);
+        let rows: i64 = conn
+            .query_row("SELECT count(*) FROM t", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(rows, 0);
+        drop(conn);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,149 @@
+use super::*;
+use crate::data_repr::*;
+use ::toml::map::Map;
+use ::toml::Value;
+use std::io::{Error, ErrorKind, Write};
+
+/**
+ * TupleToTomlSerializer
+ *
+ * Appends each tuple to an array of tables named after the table. Timestamps
+ * are offset date-times and, as TOML has no null, NULL fields are left out.
+ */
+pub struct TupleToTomlSerializer<T: Write> {
+    wrt: T,
+    table: String,
+    binary_encoding: BinaryEncoding,
+}
+
+impl<T: Write> TupleToTomlSerializer<T> {
+    pub fn new<S: Into<String>>(wrt: T, table: S) -> Self {
+        TupleToTomlSerializer {
+            wrt,
+            table: table.into(),
+            binary_encoding: Default::default(),
+        }
+    }
+
+    pub fn with_binary_encoding(mut self, binary_encoding: BinaryEncoding) -> Self {
+        self.binary_encoding = binary_encoding;
+        self
+    }
+}
+
+fn toml_error<E: std::error::Error + Send + Sync + 'static>(e: E) -> Error {
+    Error::new(ErrorKind::InvalidData, e)
+}
+
+/// The value of `data`, or None for NULL
+fn toml_value(data: &ColumnData, binary_encoding: BinaryEncoding) -> Option<Value> {
+    Some(match data {
+        ColumnData::Integer(v) => Value::Integer(*v),
+        ColumnData::Float(v) => Value::Float(*v),
+        ColumnData::Boolean(v) => Value::Boolean(*v),
+        ColumnData::String(v) => Value::String(v.clone()),
+        ColumnData::Uuid(v) => Value::String(v.to_string()),
+        ColumnData::Bytes(v) => Value::String(binary_encoding.encode(v)),
+        ColumnData::Timestamp(v) => Value::Datetime(
+            format_timestamp(v)
+                .parse()
+                .expect("RFC 3339 timestamps are TOML datetimes"),
+        ),
+        ColumnData::Null => return None,
+        ColumnData::Record(t) => Value::Table(toml_table(t, binary_encoding)),
+        ColumnData::List(v) => Value::Array(
+            v.iter()
+                .filter_map(|data| toml_value(data, binary_encoding))
+                .collect(),
+        ),
+    })
+}
+
+fn toml_table(tuple: &Tuple, binary_encoding: BinaryEncoding) -> Map<String, Value> {
+    tuple
+        .into_iter()
+        .filter_map(|(name, data)| Some((name.clone(), toml_value(data, binary_encoding)?)))
+        .collect()
+}
+
+impl<T: Write> TupleWriter for TupleToTomlSerializer<T> {
+    fn supports_list(&self) -> bool {
+        true
+    }
+    fn supports_record(&self) -> bool {
+        true
+    }
+
+    fn write_tuple(&mut self, tuple: &Tuple) -> std::io::Result<()> {
+        // Going through a toml::Value puts plain values ahead of sub tables,
+        // which TOML requires. It's built directly, as toml's serde view of
+        // values would turn datetimes into tables.
+        let record = toml_table(tuple, self.binary_encoding);
+        let mut document = Map::new();
+        document.insert(self.table.clone(), Value::Array(vec![Value::Table(record)]));
+        let toml = ::toml::to_string(&document).map_err(toml_error)?;
+        writeln!(self.wrt, "{}", toml)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.wrt.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn tuple(id: i64) -> Tuple {
+        let mut record = Tuple::new();
+        record.add_field_data("data", ColumnData::Bytes(vec![0xca, 0xfe]));
+        let mut tuple = Tuple::new();
+        tuple.add_field_data("record", ColumnData::Record(record));
+        tuple.add_field_data("id", ColumnData::Integer(id));
+        tuple.add_field_data("none", ColumnData::Null);
+        tuple.add_field_data(
+            "at",
+            ColumnData::Timestamp(Utc.timestamp_opt(1_700_000_000, 5_000_000).unwrap()),
+        );
+        tuple.add_field_data(
+            "list",
+            ColumnData::List(vec![ColumnData::Integer(1), ColumnData::Integer(2)]),
+        );
+        tuple
+    }
+
+    fn write(rows: i64) -> String {
+        let mut output = Vec::new();
+        let mut writer = TupleToTomlSerializer::new(&mut output, "t");
+        for id in 0..rows {
+            writer.write_tuple(&tuple(id)).unwrap();
+        }
+        writer.finish().unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn arrays_of_tables() {
+        let output = write(2);
+        let table = |id| {
+            format!(
+                "[[t]]\nid = {}\nat = 2023-11-14T22:13:20.005Z\nlist = [1, 2]\n\n[t.record]\ndata = \"cafe\"\n\n",
+                id
+            )
+        };
+        assert_eq!(output, format!("{}{}", table(0), table(1)));
+
+        let parsed: ::toml::Value = ::toml::from_str(&output).unwrap();
+        let tables = parsed["t"].as_array().unwrap();
+        assert_eq!(tables.len(), 2);
+        assert_eq!(tables[1]["id"].as_integer(), Some(1));
+        assert!(tables[1].get("none").is_none());
+        assert_eq!(
+            tables[1]["at"].as_datetime().unwrap().to_string(),
+            "2023-11-14T22:13:20.005Z"
+        );
+        assert_eq!(tables[1]["record"]["data"].as_str(), Some("cafe"));
+        assert_eq!(write(0), "");
+    }
+}
@@ -0,0 +1,185 @@
+use super::*;
+use crate::data_repr::ColumnData;
+use crate::data_repr::*;
+use std::io::Write;
+
+/**
+ * TupleToXmlSerializer
+ *
+ * Writes one `row` element per tuple inside a `root` element. Records become
+ * child elements and lists become a child element per item.
+ */
+pub struct TupleToXmlSerializer<T: Write> {
+    wrt: T,
+    root: String,
+    row: String,
+    item: String,
+    attributes: bool,
+    binary_encoding: BinaryEncoding,
+    wrote_root: bool,
+}
+
+/// Whether `name` can be used as an element or attribute name
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_' || c == '-' || c == '.')
+}
+
+/// Field names may start with a digit, which XML names can't
+fn element_name(name: &str) -> String {
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{}", name)
+    } else {
+        name.to_string()
+    }
+}
+
+fn escape(s: &str, attribute: bool) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' if attribute => escaped.push_str("&quot;"),
+            // Attribute values would have their whitespace normalized otherwise
+            '\t' | '\n' | '\r' if attribute => escaped.push_str(&format!("&#{};", c as u32)),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            // Other control characters can't appear in XML 1.0 at all
+            c if c.is_control() => escaped.push('\u{FFFD}'),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+impl<T: Write> TupleToXmlSerializer<T> {
+    pub fn new<S: Into<String>>(wrt: T, root: S, row: S) -> Self {
+        TupleToXmlSerializer {
+            wrt,
+            root: root.into(),
+            row: row.into(),
+            item: "item".to_string(),
+            attributes: false,
+            binary_encoding: Default::default(),
+            wrote_root: false,
+        }
+    }
+
+    pub fn with_item_name<S: Into<String>>(mut self, item: S) -> Self {
+        self.item = item.into();
+        self
+    }
+
+    /// Writes scalar fields as attributes rather than child elements
+    pub fn with_attributes(mut self, attributes: bool) -> Self {
+        self.attributes = attributes;
+        self
+    }
+
+    pub fn with_binary_encoding(mut self, binary_encoding: BinaryEncoding) -> Self {
+        self.binary_encoding = binary_encoding;
+        self
+    }
+
+    fn write_root(&mut self) -> std::io::Result<()> {
+        if !self.wrote_root {
+            writeln!(self.wrt, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+            writeln!(self.wrt, "<{}>", self.root)?;
+            self.wrote_root = true;
+        }
+        Ok(())
+    }
+
+    /// Text of a scalar value, None for NULL or nested data
+    fn text(&self, data: &ColumnData) -> Option<String> {
+        match data {
+            ColumnData::Integer(v) => Some(v.to_string()),
+            ColumnData::Float(v) if v.is_nan() => Some("NaN".to_string()),
+            ColumnData::Float(v) if v.is_infinite() && *v > 0.0 => Some("INF".to_string()),
+            ColumnData::Float(v) if v.is_infinite() => Some("-INF".to_string()),
+            ColumnData::Float(v) => Some(v.to_string()),
+            ColumnData::Boolean(v) => Some(v.to_string()),
+            ColumnData::String(v) => Some(v.to_string()),
+            ColumnData::Uuid(v) => Some(v.to_string()),
+            ColumnData::Bytes(v) => Some(self.binary_encoding.encode(v)),
+            ColumnData::Timestamp(v) => Some(format_timestamp(v)),
+            ColumnData::Null | ColumnData::Record(_) | ColumnData::List(_) => None,
+        }
+    }
+
+    /// Writes `tuple` as the element `name`. NULL fields are left out
+    fn write_element(&self, out: &mut String, name: &str, tuple: &Tuple) {
+        out.push('<');
+        out.push_str(name);
+        if self.attributes {
+            for (field, data) in tuple {
+                if let Some(text) = self.text(data) {
+                    out.push_str(&format!(
+                        " {}=\"{}\"",
+                        element_name(field),
+                        escape(&text, true)
+                    ));
+                }
+            }
+        }
+        out.push('>');
+        for (field, data) in tuple {
+            let is_scalar = !matches!(data, ColumnData::Record(_) | ColumnData::List(_));
+            if !(self.attributes && is_scalar) {
+                self.write_value(out, &element_name(field), data);
+            }
+        }
+        out.push_str(&format!("</{}>", name));
+    }
+
+    fn write_value(&self, out: &mut String, name: &str, data: &ColumnData) {
+        match data {
+            ColumnData::Null => (),
+            ColumnData::Record(t) => self.write_element(out, name, t),
+            ColumnData::List(v) => {
+                out.push_str(&format!("<{}>", name));
+                for item in v {
+                    match item {
+                        // Keeps the position of the other items
+                        ColumnData::Null => out.push_str(&format!("<{}/>", self.item)),
+                        item => self.write_value(out, &self.item, item),
+                    }
+                }
+                out.push_str(&format!("</{}>", name));
+            }
+            data => {
+                let text = self.text(data).unwrap_or_default();
+                out.push_str(&format!("<{}>{}</{}>", name, escape(&text, false), name));
+            }
+        }
+    }
+}
+
+impl<T: Write> TupleWriter for TupleToXmlSerializer<T> {
+    fn supports_list(&self) -> bool {
+        true
+    }
+    fn supports_record(&self) -> bool {
+        true
+    }
+
+    fn write_tuple(&mut self, tuple: &Tuple) -> std::io::Result<()> {
+        self.write_root()?;
+        let mut row = String::from("  ");
+        self.write_element(&mut row, &self.row, tuple);
+        row.push('\n');
+        self.wrt.write_all(row.as_bytes())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.wrt.flush()
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        self.write_root()?;
+        writeln!(self.wrt, "</{}>", self.root)?;
+        self.flush()
+    }
+}
@@ -0,0 +1,173 @@
+use super::serialize::{SerializableTuple, ValueEncoding};
+use super::*;
+use crate::data_repr::*;
+use std::io::{Error, ErrorKind, Write};
+
+/**
+ * YamlStyle
+ *
+ * A YAML document per tuple, or a single document holding a sequence of them
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum YamlStyle {
+    #[default]
+    Documents,
+    Sequence,
+}
+
+impl std::str::FromStr for YamlStyle {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "documents" => Ok(YamlStyle::Documents),
+            "sequence" => Ok(YamlStyle::Sequence),
+            _ => Err(format!("Unknown YAML style: {}", s)),
+        }
+    }
+}
+
+fn yaml_error(e: serde_norway::Error) -> Error {
+    Error::new(ErrorKind::InvalidData, e)
+}
+
+pub struct TupleToYamlSerializer<T: Write> {
+    wrt: T,
+    style: YamlStyle,
+    binary_encoding: BinaryEncoding,
+    wrote_first: bool,
+}
+
+impl<T: Write> TupleToYamlSerializer<T> {
+    pub fn new(wrt: T, style: YamlStyle) -> Self {
+        TupleToYamlSerializer {
+            wrt,
+            style,
+            binary_encoding: Default::default(),
+            wrote_first: false,
+        }
+    }
+
+    pub fn with_binary_encoding(mut self, binary_encoding: BinaryEncoding) -> Self {
+        self.binary_encoding = binary_encoding;
+        self
+    }
+}
+
+impl<T: Write> TupleWriter for TupleToYamlSerializer<T> {
+    fn supports_list(&self) -> bool {
+        true
+    }
+    fn supports_record(&self) -> bool {
+        true
+    }
+
+    fn write_tuple(&mut self, tuple: &Tuple) -> std::io::Result<()> {
+        let record = SerializableTuple::new(tuple, ValueEncoding::Text(self.binary_encoding));
+        match self.style {
+            YamlStyle::Documents => {
+                // Written separately, as serde_norway hides the kind of I/O errors
+                let yaml = serde_norway::to_string(&record).map_err(yaml_error)?;
+                self.wrt.write_all(b"---\n")?;
+                self.wrt.write_all(yaml.as_bytes())?
+            }
+            YamlStyle::Sequence => {
+                // Block scalars are indented relative to their key, so indenting
+                // every line keeps multi-line strings intact
+                let yaml = serde_norway::to_string(&record).map_err(yaml_error)?;
+                for (i, line) in yaml.lines().enumerate() {
+                    let prefix = if i == 0 { "- " } else { "  " };
+                    writeln!(self.wrt, "{}{}", prefix, line)?;
+                }
+            }
+        }
+        self.wrote_first = true;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.wrt.flush()
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        // An empty sequence still has to be a document
+        if self.style == YamlStyle::Sequence && !self.wrote_first {
+            writeln!(self.wrt, "[]")?;
+        }
+        self.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn tuple(id: i64) -> Tuple {
+        let mut record = Tuple::new();
+        record.add_field_data("data", ColumnData::Bytes(vec![0xca, 0xfe]));
+        let mut tuple = Tuple::new();
+        tuple.add_field_data("id", ColumnData::Integer(id));
+        tuple.add_field_data("text", ColumnData::String("two\nlines".to_string()));
+        tuple.add_field_data(
+            "at",
+            ColumnData::Timestamp(Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap()),
+        );
+        tuple.add_field_data("none", ColumnData::Null);
+        tuple.add_field_data(
+            "list",
+            ColumnData::List(vec![ColumnData::Record(record), ColumnData::Integer(1)]),
+        );
+        tuple
+    }
+
+    fn write(style: YamlStyle, rows: i64) -> String {
+        let mut output = Vec::new();
+        let mut writer = TupleToYamlSerializer::new(&mut output, style)
+            .with_binary_encoding(BinaryEncoding::Base64);
+        for id in 0..rows {
+            writer.write_tuple(&tuple(id)).unwrap();
+        }
+        writer.finish().unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn documents() {
+        let output = write(YamlStyle::Documents, 2);
+        let document = |id| {
+            format!(
+                "---\nid: {}\ntext: |-\n  two\n  lines\nat: 2024-01-02T03:04:05Z\nnone: null\nlist:\n- data: yv4=\n- 1\n",
+                id
+            )
+        };
+        assert_eq!(output, format!("{}{}", document(0), document(1)));
+        assert_eq!(write(YamlStyle::Documents, 0), "");
+    }
+
+    #[test]
+    fn sequences() {
+        let output = write(YamlStyle::Sequence, 2);
+        let item = |id| {
+            format!(
+                "- id: {}\n  text: |-\n    two\n    lines\n  at: 2024-01-02T03:04:05Z\n  none: null\n  list:\n  - data: yv4=\n  - 1\n",
+                id
+            )
+        };
+        assert_eq!(output, format!("{}{}", item(0), item(1)));
+        let parsed: serde_norway::Value = serde_norway::from_str(&output).unwrap();
+        assert_eq!(parsed[1]["id"], 1);
+        assert_eq!(parsed[1]["text"], "two\nlines");
+        assert_eq!(parsed[1]["list"][0]["data"], "yv4=");
+
+        let empty: serde_norway::Value =
+            serde_norway::from_str(&write(YamlStyle::Sequence, 0)).unwrap();
+        assert_eq!(empty, serde_norway::Value::Sequence(vec![]));
+    }
+
+    #[test]
+    fn styles() {
+        assert_eq!("documents".parse(), Ok(YamlStyle::Documents));
+        assert_eq!("sequence".parse(), Ok(YamlStyle::Sequence));
+        assert!("flow".parse::<YamlStyle>().is_err());
+    }
+}